@@ -0,0 +1,122 @@
+//! Benchmarks of the overhead of the middleware on the handling of a request.
+//!
+//! Along with the timings, the number of heap allocations made to handle each request is printed,
+//! as it does not vary between runs.
+
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::cell::Cell;
+
+use criterion::Criterion;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use fastrace::collector::Config;
+use fastrace::collector::Reporter;
+use fastrace::collector::SpanRecord;
+use fastrace_poem::FastraceMiddleware;
+use poem::Endpoint;
+use poem::EndpointExt;
+use poem::Request;
+use poem::Route;
+use poem::get;
+use poem::handler;
+use poem::http::Uri;
+use tokio::runtime::Runtime;
+
+const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Counts the allocations made on each thread, to leave out those of the collector thread.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Drops the recorded spans, to measure the middleware rather than a reporter.
+struct NoopReporter;
+
+impl Reporter for NoopReporter {
+    fn report(&mut self, _spans: Vec<SpanRecord>) {}
+}
+
+#[handler]
+fn get_user() -> &'static str {
+    "alice"
+}
+
+fn request(traceparent: bool) -> Request {
+    let builder = Request::builder()
+        .uri(Uri::from_static("http://localhost/users/42?fields=name"))
+        .header("user-agent", "bench/1.0");
+    let builder = if traceparent {
+        builder.header("traceparent", TRACEPARENT)
+    } else {
+        builder
+    };
+    builder.finish()
+}
+
+/// Benchmarks the handling of a request, after printing the number of allocations and
+/// reallocations made by the current thread to handle it.
+fn bench_request<E: Endpoint>(
+    c: &mut Criterion,
+    runtime: &Runtime,
+    name: &str,
+    endpoint: &E,
+    traceparent: bool,
+) {
+    // The first request warms up the lazily initialized state of poem and fastrace.
+    let _ = runtime.block_on(endpoint.call(request(traceparent)));
+    let req = request(traceparent);
+    let start = ALLOCATIONS.with(Cell::get);
+    let _ = runtime.block_on(endpoint.call(req));
+    let allocations = ALLOCATIONS.with(Cell::get) - start;
+    println!("request/{name}: {allocations} allocations");
+
+    c.bench_function(&format!("request/{name}"), |b| {
+        b.to_async(runtime)
+            .iter(|| endpoint.call(request(traceparent)))
+    });
+}
+
+fn bench_middleware(c: &mut Criterion) {
+    fastrace::set_reporter(NoopReporter, Config::default());
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let plain = Route::new().at("/users/:id", get(get_user));
+    let inside_route = Route::new().at("/users/:id", get(get_user).with(FastraceMiddleware::new()));
+    let around_route = Route::new()
+        .at("/users/:id", get(get_user))
+        .with(FastraceMiddleware::new());
+
+    bench_request(c, &runtime, "without middleware", &plain, true);
+    bench_request(c, &runtime, "not traced", &inside_route, false);
+    bench_request(c, &runtime, "traced inside route", &inside_route, true);
+    bench_request(c, &runtime, "traced around route", &around_route, true);
+}
+
+criterion_group!(benches, bench_middleware);
+criterion_main!(benches);
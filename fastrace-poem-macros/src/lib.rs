@@ -0,0 +1,57 @@
+//! Attribute macros for [fastrace-poem](https://crates.io/crates/fastrace-poem), re-exported by
+//! its `macros` feature.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::ItemFn;
+use syn::LitStr;
+use syn::parse_macro_input;
+
+/// Turns the function into a Poem handler whose calls are traced in a span named after the
+/// function, nested under the root span of `FastraceMiddleware`.
+///
+/// This combines `#[poem::handler]` with `#[fastrace::trace]`. The span can be renamed with
+/// `#[traced_handler(name = "...")]`. The expanded code refers to the `poem` and `fastrace`
+/// crates, which must be dependencies of the crate using the macro.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem_macros::traced_handler;
+/// use poem::web::Path;
+///
+/// #[traced_handler]
+/// async fn get_user(Path(id): Path<u64>) -> String {
+///     format!("user {id}")
+/// }
+///
+/// #[traced_handler(name = "health check")]
+/// fn health() -> &'static str {
+///     "ok"
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn traced_handler(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported traced_handler argument, expected `name`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+
+    let item = parse_macro_input!(item as ItemFn);
+    // Named explicitly, as the function is nested in the endpoint generated by `poem::handler`.
+    let name =
+        name.unwrap_or_else(|| LitStr::new(&item.sig.ident.to_string(), item.sig.ident.span()));
+
+    quote! {
+        #[::poem::handler]
+        #[::fastrace::trace(name = #name)]
+        #item
+    }
+    .into()
+}
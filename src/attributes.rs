@@ -0,0 +1,643 @@
+//! Semantic convention properties of the request span.
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::error::Error as StdError;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use opentelemetry_semantic_conventions::trace::CLIENT_ADDRESS;
+use opentelemetry_semantic_conventions::trace::EXCEPTION_MESSAGE;
+use opentelemetry_semantic_conventions::trace::EXCEPTION_TYPE;
+use opentelemetry_semantic_conventions::trace::HTTP_REQUEST_HEADER;
+use opentelemetry_semantic_conventions::trace::HTTP_REQUEST_METHOD;
+use opentelemetry_semantic_conventions::trace::HTTP_REQUEST_METHOD_ORIGINAL;
+use opentelemetry_semantic_conventions::trace::HTTP_RESPONSE_STATUS_CODE;
+use opentelemetry_semantic_conventions::trace::NETWORK_PROTOCOL_VERSION;
+use opentelemetry_semantic_conventions::trace::SERVER_ADDRESS;
+use opentelemetry_semantic_conventions::trace::SERVER_PORT;
+use opentelemetry_semantic_conventions::trace::URL_PATH;
+use opentelemetry_semantic_conventions::trace::URL_QUERY;
+use opentelemetry_semantic_conventions::trace::URL_SCHEME;
+use opentelemetry_semantic_conventions::trace::USER_AGENT_ORIGINAL;
+use percent_encoding::percent_decode_str;
+use poem::Error;
+use poem::Request;
+use poem::http::HeaderMap;
+use poem::http::Method;
+use poem::http::StatusCode;
+use poem::http::Version;
+use poem::http::header;
+use poem::http::uri::Authority;
+use poem::http::uri::Scheme;
+
+use crate::builder::Config;
+use crate::route::REDACTED;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_REAL_IP: &str = "x-real-ip";
+const X_REQUEST_START: &str = "x-request-start";
+const X_QUEUE_START: &str = "x-queue-start";
+const GRPC_TIMEOUT: &str = "grpc-timeout";
+const X_REQUEST_TIMEOUT: &str = "x-request-timeout";
+
+/// The time the request spent queued in front of the service, in milliseconds.
+pub(crate) const QUEUE_TIME_PROPERTY: &str = "http.request.queue_time_ms";
+
+/// The time the client gives the service to respond, in milliseconds.
+pub(crate) const REQUEST_TIMEOUT_PROPERTY: &str = "http.request.timeout_ms";
+
+/// The size of the request body, which is not exported by the semantic conventions crate without
+/// its experimental attributes.
+pub(crate) const HTTP_REQUEST_BODY_SIZE: &str = "http.request.body.size";
+
+/// The size of the response body, see [`HTTP_REQUEST_BODY_SIZE`].
+pub(crate) const HTTP_RESPONSE_BODY_SIZE: &str = "http.response.body.size";
+
+/// The default maximum length of the recorded property values, in bytes.
+pub(crate) const DEFAULT_MAX_ATTRIBUTE_LEN: usize = 1024;
+
+/// The maximum length of the property values configured on the middleware, stored in the request
+/// data for the extractors when it differs from [`DEFAULT_MAX_ATTRIBUTE_LEN`].
+#[derive(Clone, Copy)]
+pub(crate) struct MaxAttributeLen(pub(crate) usize);
+
+impl MaxAttributeLen {
+    pub(crate) fn of(req: &Request) -> usize {
+        req.data::<Self>()
+            .map_or(DEFAULT_MAX_ATTRIBUTE_LEN, |len| len.0)
+    }
+}
+
+/// The marker ending truncated property values.
+const TRUNCATION_MARKER: &str = "...";
+
+/// The `exception.type` of the errors returned by endpoints.
+const POEM_ERROR_TYPE: &str = "poem::Error";
+
+/// A span property. Values known ahead, such as the method or the scheme, are borrowed, leaving
+/// allocations to the values read from the request.
+pub(crate) type Property = (Cow<'static, str>, Cow<'static, str>);
+
+/// Which generation of the HTTP semantic conventions the span properties are named after.
+///
+/// The names were renamed when the HTTP conventions were stabilized, for example `http.method`
+/// became `http.request.method` and `http.status_code` became `http.response.status_code`.
+/// Properties that have no legacy name, such as `url.query`, use their current name in all modes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SemconvMode {
+    /// Use the current, stable names.
+    #[default]
+    New,
+    /// Use the legacy names, for backends that do not understand the stable ones yet.
+    Old,
+    /// Record both the stable and the legacy names, to ease migrating dashboards and alerts.
+    Dup,
+}
+
+/// The OpenTelemetry status of a span, see
+/// [`FastraceMiddlewareBuilder::with_status_classifier`](crate::FastraceMiddlewareBuilder::with_status_classifier).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpanStatus {
+    /// Leave the status unset, as for successful requests and client errors by default.
+    #[default]
+    Unset,
+    /// Mark the span as explicitly successful.
+    Ok,
+    /// Mark the span as failed, as for server errors by default.
+    Error,
+}
+
+impl SpanStatus {
+    /// Returns the default status of server spans: failed for server errors and unset otherwise,
+    /// since client errors are the client's fault.
+    pub(crate) fn from_status(status: StatusCode) -> Self {
+        if status.is_server_error() {
+            Self::Error
+        } else {
+            Self::Unset
+        }
+    }
+}
+
+impl SemconvMode {
+    /// Names the properties after the conventions of this mode.
+    pub(crate) fn rename<V: Clone>(
+        self,
+        properties: impl IntoIterator<Item = (Cow<'static, str>, V)>,
+    ) -> Vec<(Cow<'static, str>, V)> {
+        if self == Self::New {
+            // Collecting a `Vec` back into a `Vec` reuses its allocation.
+            return properties.into_iter().collect();
+        }
+        let mut renamed = Vec::new();
+        for (key, value) in properties {
+            match (self, legacy_name(&key)) {
+                (Self::New, _) | (_, None) => renamed.push((key, value)),
+                (Self::Old, Some(legacy)) => renamed.push((Cow::Borrowed(legacy), value)),
+                (Self::Dup, Some(legacy)) => {
+                    renamed.push((Cow::Borrowed(legacy), value.clone()));
+                    renamed.push((key, value));
+                }
+            }
+        }
+        renamed
+    }
+}
+
+/// Returns the name of the property before the HTTP semantic conventions were stabilized.
+fn legacy_name(key: &str) -> Option<&'static str> {
+    let legacy = match key {
+        HTTP_REQUEST_METHOD => "http.method",
+        HTTP_RESPONSE_STATUS_CODE => "http.status_code",
+        URL_PATH => "http.target",
+        URL_SCHEME => "http.scheme",
+        NETWORK_PROTOCOL_VERSION => "http.flavor",
+        SERVER_ADDRESS => "net.host.name",
+        SERVER_PORT => "net.host.port",
+        CLIENT_ADDRESS => "http.client_ip",
+        USER_AGENT_ORIGINAL => "http.user_agent",
+        HTTP_REQUEST_BODY_SIZE => "http.request_content_length",
+        HTTP_RESPONSE_BODY_SIZE => "http.response_content_length",
+        _ => return None,
+    };
+    Some(legacy)
+}
+
+/// Returns the properties describing the request.
+pub(crate) fn request_properties(config: &Config, req: &Request) -> Vec<Property> {
+    let mut properties = vec![
+        property(HTTP_REQUEST_METHOD, method_name(req.method())),
+        property(URL_PATH, req.uri().path().to_owned()),
+    ];
+
+    if !is_known_method(req.method()) {
+        properties.push(property(
+            HTTP_REQUEST_METHOD_ORIGINAL,
+            req.method().as_str().to_owned(),
+        ));
+    }
+
+    if config.query {
+        if let Some(query) = req.uri().query() {
+            let query = redact_query(query, &config.redacted_query_params);
+            properties.push(property(URL_QUERY, query.into_owned()));
+        }
+    }
+
+    properties.push(property(URL_SCHEME, scheme_name(req.scheme())));
+
+    if let Some(version) = protocol_version(req.version()) {
+        properties.push(property(NETWORK_PROTOCOL_VERSION, version));
+    }
+
+    let authority = server_authority(req);
+    if let Some(authority) = &authority {
+        properties.push(property(SERVER_ADDRESS, authority.host().to_owned()));
+    }
+    let port = authority
+        .as_ref()
+        .and_then(Authority::port_u16)
+        .or_else(|| req.local_addr().as_socket_addr().map(|addr| addr.port()));
+    if let Some(port) = port {
+        properties.push(property(SERVER_PORT, port.to_string()));
+    }
+
+    if !config.measure_request_body {
+        if let Some(size) = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+        {
+            properties.push(property(HTTP_REQUEST_BODY_SIZE, size.to_string()));
+        }
+    }
+
+    if let Some(user_agent) = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+    {
+        properties.push(property(USER_AGENT_ORIGINAL, user_agent.to_owned()));
+    }
+
+    if let Some(client_address) = client_address(config, req) {
+        properties.push(property(CLIENT_ADDRESS, client_address.to_string()));
+    }
+
+    properties.extend(header_properties(
+        HTTP_REQUEST_HEADER,
+        &config.capture_request_headers,
+        req.headers(),
+    ));
+
+    for (_, value) in &mut properties {
+        truncate(value, config.max_attribute_len);
+    }
+    config.semconv.rename(properties)
+}
+
+/// Returns the headers with the given names as `<prefix>.<name>` properties.
+pub(crate) fn header_properties<'a>(
+    prefix: &'a str,
+    names: &'a [String],
+    headers: &'a HeaderMap,
+) -> impl Iterator<Item = Property> + 'a {
+    names.iter().filter_map(move |name| {
+        let values = headers
+            .get_all(name.as_str())
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            return None;
+        }
+        Some((
+            Cow::Owned(format!("{prefix}.{name}")),
+            Cow::Owned(values.join(",")),
+        ))
+    })
+}
+
+/// Replaces the values of the query parameters with the given names by `REDACTED`.
+fn redact_query<'a>(query: &'a str, redacted: &[String]) -> Cow<'a, str> {
+    if redacted.is_empty() {
+        return Cow::Borrowed(query);
+    }
+    let pairs = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if is_redacted(name, redacted) => format!("{name}={REDACTED}"),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>();
+    Cow::Owned(pairs.join("&"))
+}
+
+fn is_redacted(name: &str, redacted: &[String]) -> bool {
+    let name = percent_decode_str(name).decode_utf8_lossy();
+    redacted.iter().any(|redacted| *redacted == name)
+}
+
+/// Returns the scheme as recorded in `url.scheme`, borrowed for `http` and `https`.
+fn scheme_name(scheme: &Scheme) -> Cow<'static, str> {
+    if *scheme == Scheme::HTTP {
+        Cow::Borrowed("http")
+    } else if *scheme == Scheme::HTTPS {
+        Cow::Borrowed("https")
+    } else {
+        Cow::Owned(scheme.as_str().to_owned())
+    }
+}
+
+fn protocol_version(version: Version) -> Option<&'static str> {
+    match version {
+        Version::HTTP_09 => Some("0.9"),
+        Version::HTTP_10 => Some("1.0"),
+        Version::HTTP_11 => Some("1.1"),
+        Version::HTTP_2 => Some("2"),
+        Version::HTTP_3 => Some("3"),
+        _ => None,
+    }
+}
+
+/// Returns the `exception.type` and `exception.message` properties of the exception event
+/// recorded for the error.
+///
+/// poem errors do not record the type of their source, so the type is always `poem::Error`, and
+/// the messages of the sources are appended to the message instead.
+pub(crate) fn exception_properties(err: &Error, max_len: usize) -> Vec<Property> {
+    let source = StdError::source(err);
+    let mut message = err.to_string();
+    let mut cause = source;
+    while let Some(error) = cause {
+        // The message of a poem error is usually the message of its source.
+        let error_message = error.to_string();
+        if !message.ends_with(&error_message) {
+            message.push_str(": ");
+            message.push_str(&error_message);
+        }
+        cause = error.source();
+    }
+    let mut message = Cow::Owned(message);
+    truncate(&mut message, max_len);
+
+    vec![
+        property(EXCEPTION_TYPE, POEM_ERROR_TYPE),
+        (Cow::Borrowed(EXCEPTION_MESSAGE), message),
+    ]
+}
+
+/// Returns the `exception.type` and `exception.message` properties of the exception event
+/// recorded for a panic, with the message the panic was started with.
+pub(crate) fn panic_properties(payload: &(dyn Any + Send), max_len: usize) -> Vec<Property> {
+    let mut message = match payload.downcast_ref::<&'static str>() {
+        Some(message) => Cow::Borrowed(*message),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => Cow::Owned(message.clone()),
+            None => Cow::Borrowed("Box<dyn Any>"),
+        },
+    };
+    truncate(&mut message, max_len);
+
+    vec![
+        property(EXCEPTION_TYPE, "panic"),
+        (Cow::Borrowed(EXCEPTION_MESSAGE), message),
+    ]
+}
+
+/// Returns the time since the proxy in front of the service received the request, from the
+/// `X-Request-Start` or `X-Queue-Start` header set by nginx, Heroku and similar proxies.
+///
+/// The header holds a Unix timestamp, optionally prefixed with `t=`, in seconds, milliseconds or
+/// microseconds, told apart by their magnitude.
+pub(crate) fn queue_time(req: &Request) -> Option<Duration> {
+    let value = req
+        .headers()
+        .get(X_REQUEST_START)
+        .or_else(|| req.headers().get(X_QUEUE_START))?
+        .to_str()
+        .ok()?
+        .trim();
+    let timestamp = value
+        .strip_prefix("t=")
+        .unwrap_or(value)
+        .parse::<f64>()
+        .ok()?;
+    let seconds = if timestamp > 1e15 {
+        timestamp / 1e6
+    } else if timestamp > 1e12 {
+        timestamp / 1e3
+    } else {
+        timestamp
+    };
+    let start = UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(seconds).ok()?)?;
+    // Clock skew between the proxy and the service can put the start in the future.
+    SystemTime::now().duration_since(start).ok()
+}
+
+/// Returns the time the client gives the service to respond, from the `grpc-timeout` header, such
+/// as `500m`, or the `X-Request-Timeout` header in seconds, optionally with an `s` or `ms` suffix.
+pub(crate) fn request_timeout(req: &Request) -> Option<Duration> {
+    if let Some(value) = req.headers().get(GRPC_TIMEOUT) {
+        let value = value.to_str().ok()?;
+        let (amount, unit) = value.split_at(value.len().checked_sub(1)?);
+        let amount = amount.parse::<u64>().ok()?;
+        return match unit {
+            "H" => amount.checked_mul(3600).map(Duration::from_secs),
+            "M" => amount.checked_mul(60).map(Duration::from_secs),
+            "S" => Some(Duration::from_secs(amount)),
+            "m" => Some(Duration::from_millis(amount)),
+            "u" => Some(Duration::from_micros(amount)),
+            "n" => Some(Duration::from_nanos(amount)),
+            _ => None,
+        };
+    }
+
+    let value = req.headers().get(X_REQUEST_TIMEOUT)?.to_str().ok()?.trim();
+    if let Some(millis) = value.strip_suffix("ms") {
+        return Duration::try_from_secs_f64(millis.trim().parse::<f64>().ok()? / 1e3).ok();
+    }
+    let seconds = value.strip_suffix('s').unwrap_or(value).trim();
+    Duration::try_from_secs_f64(seconds.parse().ok()?).ok()
+}
+
+/// Returns the authority the request was sent to, from the request target of HTTP/2 requests and
+/// from the `Host` header otherwise.
+fn server_authority(req: &Request) -> Option<Authority> {
+    if let Some(authority) = req.uri().authority() {
+        return Some(authority.clone());
+    }
+    req.headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|host| host.parse().ok())
+}
+
+/// Returns the address of the client: the peer address, or when the peer is a trusted proxy, the
+/// last untrusted address of `X-Forwarded-For`, falling back to `X-Real-IP`.
+fn client_address(config: &Config, req: &Request) -> Option<IpAddr> {
+    let peer = req.remote_addr().as_socket_addr()?.ip();
+    let is_trusted = |ip: &IpAddr| config.trusted_proxies.contains(ip);
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded = req
+        .headers()
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+    if let Some(first) = forwarded.first() {
+        let client = forwarded.iter().rev().find(|ip| !is_trusted(ip));
+        // When every hop is a trusted proxy, the first one is the client.
+        return Some(*client.unwrap_or(first));
+    }
+
+    req.headers()
+        .get(X_REAL_IP)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|ip| ip.trim().parse().ok())
+        .or(Some(peer))
+}
+
+pub(crate) fn is_known_method(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET
+            | Method::HEAD
+            | Method::POST
+            | Method::PUT
+            | Method::DELETE
+            | Method::CONNECT
+            | Method::OPTIONS
+            | Method::TRACE
+            | Method::PATCH
+    )
+}
+
+/// Returns the method as reported in `http.request.method`, where nonstandard methods are
+/// reported as `_OTHER`.
+pub(crate) fn method_name(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "_OTHER",
+    }
+}
+
+fn property(key: &'static str, value: impl Into<Cow<'static, str>>) -> Property {
+    (Cow::Borrowed(key), value.into())
+}
+
+/// Cuts the value to at most `max_len` bytes, on a character boundary, ending it with the
+/// truncation marker when there is room for it. Borrowed values are only copied when cut.
+pub(crate) fn truncate(value: &mut Cow<'static, str>, max_len: usize) {
+    if value.len() <= max_len {
+        return;
+    }
+    let value = value.to_mut();
+    let marker = if max_len >= TRUNCATION_MARKER.len() {
+        TRUNCATION_MARKER
+    } else {
+        ""
+    };
+    let mut end = max_len - marker.len();
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+    value.push_str(marker);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &'static str, value: &str) -> Request {
+        Request::builder().header(name, value).finish()
+    }
+
+    fn unix_time() -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+    }
+
+    #[test]
+    fn redact_query_replaces_selected_values() {
+        let redacted = ["token".to_string(), "api key".to_string()];
+        assert_eq!(
+            redact_query("token=abc&page=2&api%20key=x&flag", &redacted),
+            "token=REDACTED&page=2&api%20key=REDACTED&flag"
+        );
+        assert!(matches!(redact_query("token=abc", &[]), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn truncate_keeps_short_values() {
+        let mut value = Cow::Borrowed("short");
+        truncate(&mut value, 5);
+        assert!(matches!(value, Cow::Borrowed("short")));
+    }
+
+    #[test]
+    fn truncate_cuts_long_values_with_a_marker() {
+        let mut value = Cow::Borrowed("a long value");
+        truncate(&mut value, 8);
+        assert_eq!(value, "a lon...");
+    }
+
+    #[test]
+    fn truncate_cuts_on_a_character_boundary() {
+        let mut value = Cow::Borrowed("ééééé");
+        truncate(&mut value, 8);
+        assert_eq!(value, "éé...");
+        assert!(value.len() <= 8);
+    }
+
+    #[test]
+    fn truncate_leaves_out_the_marker_without_room_for_it() {
+        let mut value = Cow::Borrowed("abcdef");
+        truncate(&mut value, 2);
+        assert_eq!(value, "ab");
+        truncate(&mut value, 0);
+        assert_eq!(value, "");
+    }
+
+    #[test]
+    fn queue_time_reads_seconds_milliseconds_and_microseconds() {
+        let start = unix_time() - Duration::from_secs(2);
+        for value in [
+            format!("t={:.3}", start.as_secs_f64()),
+            start.as_millis().to_string(),
+            format!("t={}", start.as_micros()),
+        ] {
+            let queue_time = queue_time(&request(X_REQUEST_START, &value)).unwrap();
+            assert!(queue_time >= Duration::from_millis(1900), "{value}");
+            assert!(queue_time < Duration::from_secs(60), "{value}");
+        }
+    }
+
+    #[test]
+    fn queue_time_falls_back_to_x_queue_start() {
+        let start = unix_time() - Duration::from_secs(1);
+        let req = request(X_QUEUE_START, &start.as_millis().to_string());
+        assert!(queue_time(&req).is_some());
+    }
+
+    #[test]
+    fn queue_time_ignores_future_and_invalid_timestamps() {
+        let future = unix_time() + Duration::from_secs(60);
+        let cases = [
+            future.as_millis().to_string(),
+            "t=".to_string(),
+            "soon".to_string(),
+            "-5".to_string(),
+            "t=1e400".to_string(),
+        ];
+        for value in cases {
+            assert_eq!(
+                queue_time(&request(X_REQUEST_START, &value)),
+                None,
+                "{value}"
+            );
+        }
+        assert_eq!(queue_time(&Request::default()), None);
+    }
+
+    #[test]
+    fn request_timeout_reads_grpc_timeout_units() {
+        let timeout = |value: &str| request_timeout(&request(GRPC_TIMEOUT, value));
+        assert_eq!(timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(timeout("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(timeout("4S"), Some(Duration::from_secs(4)));
+        assert_eq!(timeout("500m"), Some(Duration::from_millis(500)));
+        assert_eq!(timeout("250u"), Some(Duration::from_micros(250)));
+        assert_eq!(timeout("100n"), Some(Duration::from_nanos(100)));
+    }
+
+    #[test]
+    fn request_timeout_rejects_invalid_grpc_timeouts() {
+        let timeout = |value: &str| request_timeout(&request(GRPC_TIMEOUT, value));
+        assert_eq!(timeout(""), None);
+        assert_eq!(timeout("m"), None);
+        assert_eq!(timeout("10"), None);
+        assert_eq!(timeout("10s"), None);
+        assert_eq!(timeout("-1S"), None);
+        assert_eq!(timeout(&format!("{}H", u64::MAX)), None);
+    }
+
+    #[test]
+    fn request_timeout_reads_x_request_timeout() {
+        let timeout = |value: &str| request_timeout(&request(X_REQUEST_TIMEOUT, value));
+        assert_eq!(timeout("5"), Some(Duration::from_secs(5)));
+        assert_eq!(timeout("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(timeout(" 250 ms "), Some(Duration::from_millis(250)));
+        assert_eq!(timeout("never"), None);
+        assert_eq!(timeout("-1"), None);
+        assert_eq!(request_timeout(&Request::default()), None);
+    }
+
+    #[test]
+    fn request_timeout_prefers_grpc_timeout() {
+        let req = Request::builder()
+            .header(GRPC_TIMEOUT, "100m")
+            .header(X_REQUEST_TIMEOUT, "5")
+            .finish();
+        assert_eq!(request_timeout(&req), Some(Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,99 @@
+//! Bodies observing the bytes they yield.
+
+use std::io;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+
+use bytes::Bytes;
+use http_body::Body as HttpBody;
+use http_body::Frame;
+use http_body::SizeHint;
+use poem::Body;
+
+type BoxBody = http_body_util::combinators::BoxBody<Bytes, io::Error>;
+
+/// What a body yielded, once it ended or was dropped.
+pub(crate) struct BodySummary {
+    /// The number of data bytes.
+    pub(crate) size: u64,
+    /// The first bytes of the data, up to the requested length.
+    pub(crate) head: Vec<u8>,
+    /// When the first data frame was yielded.
+    pub(crate) first_data: Option<Instant>,
+}
+
+/// Wraps the body to call `on_end` with what it yielded, keeping its first `head_len` bytes, once
+/// it ends or is dropped.
+pub(crate) fn observe_body<F>(body: Body, head_len: usize, on_end: F) -> Body
+where F: FnOnce(&BodySummary) + Send + Sync + Unpin + 'static {
+    Body::from(BoxBody::new(ObservedBody {
+        inner: body.into(),
+        summary: BodySummary {
+            size: 0,
+            head: Vec::new(),
+            first_data: None,
+        },
+        head_len,
+        on_end: Some(on_end),
+    }))
+}
+
+struct ObservedBody<F: FnOnce(&BodySummary)> {
+    inner: BoxBody,
+    summary: BodySummary,
+    head_len: usize,
+    on_end: Option<F>,
+}
+
+impl<F: FnOnce(&BodySummary)> ObservedBody<F> {
+    fn end(&mut self) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end(&self.summary);
+        }
+    }
+}
+
+impl<F: FnOnce(&BodySummary) + Unpin> HttpBody for ObservedBody<F> {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        let this = self.get_mut();
+        let frame = Pin::new(&mut this.inner).poll_frame(cx);
+        match &frame {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    let summary = &mut this.summary;
+                    summary.first_data.get_or_insert_with(Instant::now);
+                    summary.size += data.len() as u64;
+                    let missing = this.head_len.saturating_sub(summary.head.len());
+                    summary
+                        .head
+                        .extend_from_slice(&data[..missing.min(data.len())]);
+                }
+            }
+            Poll::Ready(_) => this.end(),
+            Poll::Pending => {}
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<F: FnOnce(&BodySummary)> Drop for ObservedBody<F> {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
@@ -0,0 +1,1477 @@
+//! Configuration of [`FastraceMiddleware`](struct@FastraceMiddleware).
+
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use fastrace::collector::SpanContext;
+use poem::Request;
+use poem::Response;
+use poem::http::Method;
+use poem::http::StatusCode;
+
+use crate::FastraceMiddleware;
+use crate::PathNormalization;
+use crate::SamplingDecision;
+use crate::SemconvMode;
+use crate::SpanCustomizer;
+use crate::SpanStatus;
+use crate::attributes::DEFAULT_MAX_ATTRIBUTE_LEN;
+use crate::customizer::RequestProperties;
+use crate::customizer::ResponseProperties;
+use crate::filter::PathGlob;
+use crate::filter::RequestFilter;
+use crate::filter::TracingSwitch;
+use crate::middleware::Configured;
+use crate::naming::NameLimiter;
+#[cfg(feature = "openapi")]
+use crate::openapi::OpenApiOperations;
+use crate::propagation::ContextExtractor;
+use crate::propagation::ContextInjector;
+use crate::propagation::ExtractorChain;
+use crate::propagation::W3CTraceContext;
+use crate::sampling::RateLimiter;
+
+/// How to handle requests whose parent context has the sampled flag unset, for example a
+/// `traceparent` with the `00` trace flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum UnsampledParent {
+    /// Create a root span that is not reported but keeps propagating the context, so that spans
+    /// created by downstream services are still linked to the trace.
+    #[default]
+    Propagate,
+    /// Skip the root span entirely, so that unsampled requests cost neither span creation nor
+    /// property collection. The context is not propagated to downstream services.
+    Noop,
+}
+
+type IdGenerator = dyn Fn() -> SpanContext + Send + Sync;
+type Sampler = dyn Fn(&Request) -> SamplingDecision + Send + Sync;
+type SpanNamer = dyn Fn(&Request) -> String + Send + Sync;
+type TailDecision = dyn Fn(StatusCode, Duration) -> bool + Send + Sync;
+type StatusClassifier = dyn Fn(&Request, StatusCode) -> SpanStatus + Send + Sync;
+
+/// The settings baked into every [`FastraceEndpoint`](crate::FastraceEndpoint).
+pub(crate) struct Config {
+    pub(crate) w3c: W3CTraceContext,
+    pub(crate) baggage_properties: Vec<String>,
+    pub(crate) attributes: Vec<(Cow<'static, str>, Cow<'static, str>)>,
+    pub(crate) trusted_proxies: Vec<IpAddr>,
+    pub(crate) service_name: Option<Cow<'static, str>>,
+    pub(crate) span_name: Option<Box<SpanNamer>>,
+    pub(crate) path_templating: bool,
+    pub(crate) path_normalization: PathNormalization,
+    pub(crate) name_limiter: Option<NameLimiter>,
+    pub(crate) route_params: bool,
+    pub(crate) redacted_route_params: Vec<String>,
+    pub(crate) query: bool,
+    pub(crate) redacted_query_params: Vec<String>,
+    pub(crate) measure_request_body: bool,
+    pub(crate) measure_response_body: bool,
+    pub(crate) error_body_snippet: usize,
+    pub(crate) capture_request_headers: Vec<String>,
+    pub(crate) capture_response_headers: Vec<String>,
+    pub(crate) max_attribute_len: usize,
+    pub(crate) semconv: SemconvMode,
+    pub(crate) request_id_header: Option<String>,
+    pub(crate) generate_request_id: bool,
+    pub(crate) queue_time: bool,
+    pub(crate) queue_time_event: bool,
+    pub(crate) deadline: bool,
+    pub(crate) deadline_event: bool,
+    pub(crate) handler_span: bool,
+    pub(crate) phase_spans: bool,
+    pub(crate) span_until_body_end: bool,
+    pub(crate) ttfb: bool,
+    pub(crate) heartbeat: Option<Duration>,
+    pub(crate) customizers: Vec<Box<dyn SpanCustomizer>>,
+    pub(crate) status_classifier: Option<Box<StatusClassifier>>,
+    #[cfg(feature = "openapi")]
+    pub(crate) openapi: OpenApiOperations,
+    #[cfg(feature = "graphql")]
+    pub(crate) graphql_path: Option<String>,
+    pub(crate) unsampled_parent: UnsampledParent,
+    pub(crate) force_trace_header: Option<String>,
+    pub(crate) record_conflicting_parents: bool,
+    pub(crate) traceresponse: bool,
+    pub(crate) trace_id_header: Option<String>,
+    pub(crate) response_traceparent: bool,
+    pub(crate) server_timing: bool,
+    pub(crate) forward_context: bool,
+    pub(crate) new_root_on_invalid_parent: bool,
+    pub(crate) trace_all_requests: bool,
+    pub(crate) resample_parents: bool,
+    pub(crate) id_generator: Option<Box<IdGenerator>>,
+    pub(crate) sampler: Option<Box<Sampler>>,
+    pub(crate) sample_ratio: f64,
+    pub(crate) rate_limiter: Option<RateLimiter>,
+    pub(crate) tail_decision: Option<Box<TailDecision>>,
+    pub(crate) slow_threshold: Option<Duration>,
+    pub(crate) keep_slow_requests: bool,
+    pub(crate) skip_paths: Vec<PathGlob>,
+    pub(crate) filters: Vec<Box<dyn RequestFilter>>,
+    pub(crate) skip_methods: Vec<Method>,
+    pub(crate) skip_preflight: bool,
+    pub(crate) switch: Option<TracingSwitch>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            w3c: W3CTraceContext::new(),
+            baggage_properties: Vec::new(),
+            attributes: Vec::new(),
+            trusted_proxies: Vec::new(),
+            service_name: None,
+            span_name: None,
+            path_templating: false,
+            path_normalization: PathNormalization::default(),
+            name_limiter: None,
+            route_params: false,
+            redacted_route_params: Vec::new(),
+            query: false,
+            redacted_query_params: Vec::new(),
+            measure_request_body: false,
+            measure_response_body: false,
+            error_body_snippet: 0,
+            capture_request_headers: Vec::new(),
+            capture_response_headers: Vec::new(),
+            max_attribute_len: DEFAULT_MAX_ATTRIBUTE_LEN,
+            semconv: SemconvMode::default(),
+            request_id_header: None,
+            generate_request_id: false,
+            queue_time: false,
+            queue_time_event: false,
+            deadline: false,
+            deadline_event: false,
+            handler_span: false,
+            phase_spans: false,
+            span_until_body_end: false,
+            ttfb: false,
+            heartbeat: None,
+            customizers: Vec::new(),
+            status_classifier: None,
+            #[cfg(feature = "openapi")]
+            openapi: OpenApiOperations::default(),
+            #[cfg(feature = "graphql")]
+            graphql_path: None,
+            unsampled_parent: UnsampledParent::default(),
+            force_trace_header: None,
+            record_conflicting_parents: false,
+            traceresponse: false,
+            trace_id_header: None,
+            response_traceparent: false,
+            server_timing: false,
+            forward_context: false,
+            new_root_on_invalid_parent: false,
+            trace_all_requests: false,
+            resample_parents: false,
+            id_generator: None,
+            sampler: None,
+            sample_ratio: 1.0,
+            rate_limiter: None,
+            tail_decision: None,
+            slow_threshold: None,
+            keep_slow_requests: false,
+            skip_paths: Vec::new(),
+            filters: Vec::new(),
+            skip_methods: Vec::new(),
+            skip_preflight: false,
+            switch: None,
+        }
+    }
+}
+
+/// A builder for [`FastraceMiddleware`](struct@FastraceMiddleware), created by
+/// [`FastraceMiddleware::builder`].
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::propagation::B3;
+///
+/// let middleware = FastraceMiddleware::builder()
+///     .with_extractor(B3)
+///     .with_traceresponse(true)
+///     .build();
+/// ```
+#[derive(Default)]
+pub struct FastraceMiddlewareBuilder {
+    extractor: Option<Arc<dyn ContextExtractor>>,
+    injector: Option<Arc<dyn ContextInjector>>,
+    config: Config,
+}
+
+impl FastraceMiddlewareBuilder {
+    /// Creates a builder configured from the standard OpenTelemetry environment variables, so that
+    /// the service follows the same deployment configuration as other instrumented services.
+    ///
+    /// - `OTEL_PROPAGATORS` selects the extractors, among `tracecontext`, `b3`, `b3multi`,
+    ///   `jaeger`, `datadog` and, with the `xray` feature, `xray`, and the injectors, among
+    ///   `tracecontext`, `b3`, `b3multi`, `jaeger` and `datadog`, where `b3` injects the single
+    ///   `b3` header and `b3multi` the `X-B3-*` headers. Other propagators are ignored.
+    /// - `OTEL_TRACES_SAMPLER` selects the sampling: `always_on` records every request;
+    ///   `always_off` records none, only propagating the parent context of requests with one;
+    ///   `traceidratio` records the ratio given by `OTEL_TRACES_SAMPLER_ARG` of the traces,
+    ///   ignoring the sampled flag of the parent context; `parentbased_always_on`,
+    ///   `parentbased_always_off` and `parentbased_traceidratio` follow the parent context, and
+    ///   respectively record all, none or the ratio of the requests without one.
+    ///
+    /// Variables that are not set leave the defaults untouched, and the builder can be configured
+    /// further.
+    pub fn from_env() -> Self {
+        crate::env::configure(Self::default())
+    }
+
+    /// Sets the [`ContextExtractor`] used to read the parent context from request headers.
+    ///
+    /// Defaults to [`ExtractorChain::builtin`], which accepts all built-in propagation formats.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use fastrace_poem::propagation::B3;
+    ///
+    /// let middleware = FastraceMiddleware::builder().with_extractor(B3).build();
+    /// ```
+    pub fn with_extractor(mut self, extractor: impl ContextExtractor) -> Self {
+        self.extractor = Some(Arc::new(extractor));
+        self
+    }
+
+    /// Sets the [`ContextInjector`] used by [`inject_context`](crate::inject_context) to write the
+    /// current context into the headers of outgoing requests.
+    ///
+    /// Defaults to the W3C `traceparent` header, renamed by
+    /// [`with_trace_header`](Self::with_trace_header).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use fastrace_poem::propagation::B3;
+    ///
+    /// let middleware = FastraceMiddleware::builder().with_injector(B3).build();
+    /// ```
+    pub fn with_injector(mut self, injector: impl ContextInjector) -> Self {
+        self.injector = Some(Arc::new(injector));
+        self
+    }
+
+    /// Replaces the context headers of the request with the context of the root span before the
+    /// request reaches the endpoint, using the injector set by
+    /// [`with_injector`](Self::with_injector).
+    ///
+    /// This is meant for services using poem as a reverse proxy and forwarding the request headers
+    /// as is, so that the downstream service continues the trace from the proxy rather than from
+    /// the original caller. Requests that are not traced keep their headers.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_forward_context(true)
+    ///     .build();
+    /// ```
+    pub fn with_forward_context(mut self, enable: bool) -> Self {
+        self.config.forward_context = enable;
+        self
+    }
+
+    /// Reads the W3C `traceparent` value from another header than
+    /// [`TRACEPARENT_HEADER`](crate::TRACEPARENT_HEADER), for gateways that rename the context
+    /// header. The `tracestate` header is only honored when this header carries a valid context.
+    ///
+    /// This applies to the built-in extractors. A custom extractor set with
+    /// [`with_extractor`](Self::with_extractor) should use [`W3CTraceContext::with_header`]
+    /// instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_trace_header("x-internal-traceparent")
+    ///     .build();
+    /// ```
+    pub fn with_trace_header(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.config.w3c = W3CTraceContext::with_header(name);
+        self
+    }
+
+    /// Records the entries of the `baggage` header with the given keys as properties on the root
+    /// span, for example tenant or user identifiers set by an upstream service.
+    ///
+    /// Other entries are not recorded, but remain available to handlers through
+    /// [`Baggage`](crate::propagation::Baggage).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_baggage_properties(["tenant.id"])
+    ///     .build();
+    /// ```
+    pub fn with_baggage_properties(
+        mut self,
+        keys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.config.baggage_properties = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the function naming the root span, replacing the default `{method} {route}` naming,
+    /// for example to add a gateway prefix or to look the operation up in a registry.
+    ///
+    /// The function only runs for requests that get a root span.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_span_name(|req| format!("gateway {} {}", req.method(), req.uri().path()))
+    ///     .build();
+    /// ```
+    pub fn with_span_name(
+        mut self,
+        span_name: impl Fn(&Request) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.config.span_name = Some(Box::new(span_name));
+        self
+    }
+
+    /// Names the root spans of requests handled by the operations of the given poem-openapi API
+    /// after their `operation_id`, or after their method and path template when they have none,
+    /// and records their tags as the `openapi.tag` property.
+    ///
+    /// Operations are matched against the path seen by the middleware, so it should wrap the
+    /// `OpenApiService` itself rather than a [`Route`](poem::Route) nesting it under a prefix.
+    /// This can be called several times to register several APIs. A [`SpanName`](crate::SpanName)
+    /// still takes precedence over the operation name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use poem::EndpointExt;
+    /// use poem::Route;
+    /// use poem_openapi::OpenApi;
+    /// use poem_openapi::OpenApiService;
+    /// use poem_openapi::param::Path;
+    /// use poem_openapi::payload::PlainText;
+    ///
+    /// struct Api;
+    ///
+    /// #[OpenApi]
+    /// impl Api {
+    ///     #[oai(
+    ///         path = "/users/:id",
+    ///         method = "get",
+    ///         operation_id = "getUser",
+    ///         tag = "ApiTags::User"
+    ///     )]
+    ///     async fn get_user(&self, id: Path<u64>) -> PlainText<String> {
+    ///         PlainText(id.0.to_string())
+    ///     }
+    /// }
+    ///
+    /// #[derive(poem_openapi::Tags)]
+    /// enum ApiTags {
+    ///     User,
+    /// }
+    ///
+    /// let service = OpenApiService::new(Api, "Users", "1.0");
+    /// let app = Route::new().nest(
+    ///     "/api",
+    ///     service.with(FastraceMiddleware::builder().with_openapi::<Api>().build()),
+    /// );
+    /// ```
+    #[cfg(feature = "openapi")]
+    pub fn with_openapi<T: poem_openapi::OpenApi>(mut self) -> Self {
+        self.config.openapi.register::<T>();
+        self
+    }
+
+    /// Inspects the GraphQL requests sent to the given path, such as the endpoint of
+    /// async-graphql-poem, to append the name of the operation to the name of the root span, as in
+    /// `POST /graphql GetUser`, and record the `graphql.operation.name` and
+    /// `graphql.operation.type` properties.
+    ///
+    /// The operation is read from the query of `GET` requests and from the `application/json` body
+    /// of other requests, which is buffered to be read before the endpoint is called and handed to
+    /// it unchanged. Bodies of other content types, such as multipart uploads, and bodies larger
+    /// than 64 KiB are not inspected. Batched requests are named after the path alone.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_graphql_path("/graphql")
+    ///     .build();
+    /// ```
+    #[cfg(feature = "graphql")]
+    pub fn with_graphql_path(mut self, path: impl Into<String>) -> Self {
+        self.config.graphql_path = Some(path.into());
+        self
+    }
+
+    /// Names the root span after a template of the path when the matched route is not known, that
+    /// is when the middleware wraps a [`Route`](poem::Route) rather than the endpoints inside it.
+    ///
+    /// Path segments that look like identifiers, such as numbers, UUIDs and hashes, are replaced
+    /// with `{id}`, so that `GET /users/123` is named `GET /users/{id}`. The template is also
+    /// recorded as `http.route` when the response does not carry the matched route.
+    pub fn with_path_templating(mut self, enable: bool) -> Self {
+        self.config.path_templating = enable;
+        self
+    }
+
+    /// Sets how the request path is normalized before a span name is derived from it, see
+    /// [`PathNormalization`].
+    ///
+    /// The normalization only applies to the span names derived from the path, that is with
+    /// [`with_path_templating`](Self::with_path_templating) and poem-openapi operation names. Span
+    /// names derived from the matched route or by [`with_span_name`](Self::with_span_name) are left
+    /// untouched, and `url.path` records the raw path.
+    pub fn with_path_normalization(mut self, normalization: PathNormalization) -> Self {
+        self.config.path_normalization = normalization;
+        self
+    }
+
+    /// Limits the number of distinct root span names to the given budget, protecting backends
+    /// keyed or billed by operation name from an explosion of paths.
+    ///
+    /// Once the budget is exhausted, spans that would get a new name are named `HTTP {method}`
+    /// instead. The names are tracked by the middleware for the lifetime of the process.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_path_templating(true)
+    ///     .with_max_span_names(1000)
+    ///     .build();
+    /// ```
+    pub fn with_max_span_names(mut self, max_names: usize) -> Self {
+        self.config.name_limiter = Some(NameLimiter::new(max_names));
+        self
+    }
+
+    /// Records the path parameters of the matched route as `http.route.params.<name>` properties,
+    /// for example `http.route.params.id` for the `/users/:id` route, making it possible to find
+    /// all the traces of a resource without recording high-cardinality span names.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_route_params(true)
+    ///     .with_redacted_route_params(["token"])
+    ///     .build();
+    /// ```
+    pub fn with_route_params(mut self, enable: bool) -> Self {
+        self.config.route_params = enable;
+        self
+    }
+
+    /// Records the path parameters with the given names as `REDACTED` instead of their value, see
+    /// [`with_route_params`](Self::with_route_params).
+    pub fn with_redacted_route_params(
+        mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.config.redacted_route_params = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Records the query string of the request as `url.query`.
+    ///
+    /// Query strings often carry credentials, so list the sensitive parameters with
+    /// [`with_redacted_query_params`](Self::with_redacted_query_params).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_query(true)
+    ///     .with_redacted_query_params(["token", "api_key"])
+    ///     .build();
+    /// ```
+    pub fn with_query(mut self, enable: bool) -> Self {
+        self.config.query = enable;
+        self
+    }
+
+    /// Records the query parameters with the given names as `REDACTED` instead of their value, see
+    /// [`with_query`](Self::with_query).
+    pub fn with_redacted_query_params(
+        mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.config.redacted_query_params = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Records in `http.request.body.size` the number of bytes of the request body read by the
+    /// endpoint, instead of the size declared by the `Content-Length` header.
+    ///
+    /// This also covers chunked uploads, which do not declare their size, at the cost of wrapping
+    /// the request body.
+    pub fn with_measure_request_body(mut self, enable: bool) -> Self {
+        self.config.measure_request_body = enable;
+        self
+    }
+
+    /// Records in `http.response.body.size` the number of bytes of the response body sent to the
+    /// client, including streaming responses without a `Content-Length` header.
+    ///
+    /// The root span then ends when the response body has been sent, instead of when the endpoint
+    /// returns the response.
+    pub fn with_measure_response_body(mut self, enable: bool) -> Self {
+        self.config.measure_response_body = enable;
+        self
+    }
+
+    /// Records up to the given number of bytes from the start of the body of client and server
+    /// error responses as the `http.response.body.snippet` property, showing what exactly was
+    /// returned to the client. Disabled when zero, which is the default.
+    ///
+    /// The body is observed as it is sent, so the root span of error responses ends when their
+    /// body has been sent.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_error_body_snippet(256)
+    ///     .build();
+    /// ```
+    pub fn with_error_body_snippet(mut self, max_len: usize) -> Self {
+        self.config.error_body_snippet = max_len;
+        self
+    }
+
+    /// Records the request headers with the given names as `http.request.header.<name>`
+    /// properties, with the lowercase header name and the values of repeated headers joined by
+    /// commas.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_capture_request_headers(["Content-Type", "X-Tenant-Id"])
+    ///     .with_capture_response_headers(["Content-Type"])
+    ///     .build();
+    /// ```
+    pub fn with_capture_request_headers(
+        mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.config.capture_request_headers = header_names(names);
+        self
+    }
+
+    /// Records the response headers with the given names as `http.response.header.<name>`
+    /// properties, see [`with_capture_request_headers`](Self::with_capture_request_headers).
+    pub fn with_capture_response_headers(
+        mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.config.capture_response_headers = header_names(names);
+        self
+    }
+
+    /// Sets the maximum length in bytes of the property values recorded from the request and the
+    /// response, such as the path, the query, the user agent and the captured headers. Longer
+    /// values are cut and end with `...`. Defaults to 1024 bytes. This also applies to the error
+    /// messages recorded by [`Traced`](crate::Traced) extractors.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_max_attribute_len(256)
+    ///     .build();
+    /// ```
+    pub fn with_max_attribute_len(mut self, max_len: usize) -> Self {
+        self.config.max_attribute_len = max_len;
+        self
+    }
+
+    /// Sets which generation of the HTTP semantic conventions the span properties are named
+    /// after. Defaults to [`SemconvMode::New`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use fastrace_poem::SemconvMode;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_semconv_mode(SemconvMode::Dup)
+    ///     .build();
+    /// ```
+    pub fn with_semconv_mode(mut self, mode: SemconvMode) -> Self {
+        self.config.semconv = mode;
+        self
+    }
+
+    /// Records the request id sent in the given header, such as `x-request-id`, under the
+    /// `http.request.id` property, so that the request ids quoted in logs and support tickets can
+    /// be joined to traces.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_request_id_header("x-request-id")
+    ///     .with_generate_request_id(true)
+    ///     .build();
+    /// ```
+    pub fn with_request_id_header(mut self, name: impl Into<String>) -> Self {
+        self.config.request_id_header = Some(name.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Generates a request id for requests without one, setting it on the request header given to
+    /// [`with_request_id_header`](Self::with_request_id_header), and returns the request id in the
+    /// same header of the response. The ids are random 32-digit hexadecimal strings.
+    pub fn with_generate_request_id(mut self, enable: bool) -> Self {
+        self.config.generate_request_id = enable;
+        self
+    }
+
+    /// Records the time the request spent queued in front of the service, between the proxy
+    /// receiving it and the middleware, as the `http.request.queue_time_ms` property.
+    ///
+    /// The queue start is read from the `X-Request-Start` or `X-Queue-Start` header, such as the
+    /// `t=${msec}` value set by nginx or the milliseconds set by Heroku. This makes load balancer
+    /// backlogs visible in traces, provided the clocks of the proxy and the service are in sync.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_queue_time(true)
+    ///     .with_queue_time_event(true)
+    ///     .build();
+    /// ```
+    pub fn with_queue_time(mut self, enable: bool) -> Self {
+        self.config.queue_time = enable;
+        self
+    }
+
+    /// Also adds a `request queued` event with the queue time to the root span, see
+    /// [`with_queue_time`](Self::with_queue_time).
+    pub fn with_queue_time_event(mut self, enable: bool) -> Self {
+        self.config.queue_time_event = enable;
+        self
+    }
+
+    /// Records the time the client gives the service to respond, in milliseconds, as the
+    /// `http.request.timeout_ms` property. It is read from the `grpc-timeout` header, such as
+    /// `500m`, or the `X-Request-Timeout` header in seconds, optionally with an `s` or `ms` suffix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_deadline(true)
+    ///     .with_deadline_event(true)
+    ///     .build();
+    /// ```
+    pub fn with_deadline(mut self, enable: bool) -> Self {
+        self.config.deadline = enable;
+        self
+    }
+
+    /// Also adds a `deadline exceeded` event to the root span when the response is returned after
+    /// the client's deadline, see [`with_deadline`](Self::with_deadline), with how late it was in
+    /// milliseconds as the `exceeded_by_ms` property. The client has most likely given up on the
+    /// request by then.
+    pub fn with_deadline_event(mut self, enable: bool) -> Self {
+        self.config.deadline_event = enable;
+        self
+    }
+
+    /// Wraps the call of the inner endpoint in a `handler` child span of the root span, with the
+    /// spans of the handler as its children.
+    ///
+    /// The root span covers the work of the middleware itself, such as extracting the context and
+    /// recording the properties, and of the response body when it is measured. The `handler` span
+    /// only covers the endpoints and middlewares the middleware is applied to, which is the
+    /// handler alone when it wraps the endpoints inside a [`Route`](poem::Route).
+    pub fn with_handler_span(mut self, enable: bool) -> Self {
+        self.config.handler_span = enable;
+        self
+    }
+
+    /// Adds child spans of the root span for the phases of the request, to tell the overhead of
+    /// the middleware, the business logic and slow clients apart:
+    ///
+    /// - `setup`, recording the properties of the request and preparing the call;
+    /// - `handler`, the call of the inner endpoint, as with
+    ///   [`with_handler_span`](Self::with_handler_span);
+    /// - `response.write`, from the return of the endpoint until the response body is fully sent or
+    ///   dropped.
+    ///
+    /// The response body is wrapped to find out when it is sent. The context is extracted before
+    /// the root span starts, so it is not part of any phase.
+    pub fn with_phase_spans(mut self, enable: bool) -> Self {
+        self.config.phase_spans = enable;
+        self
+    }
+
+    /// Keeps the root span open until the response body is fully sent, fails or is dropped on
+    /// disconnect, instead of ending it when the endpoint returns, so that the duration of
+    /// streamed and chunked responses includes sending them. The response body is wrapped to find
+    /// out when it ends.
+    ///
+    /// Measuring the response body with
+    /// [`with_measure_response_body`](Self::with_measure_response_body) and phase spans keep the
+    /// root span open as well.
+    pub fn with_span_until_body_end(mut self, enable: bool) -> Self {
+        self.config.span_until_body_end = enable;
+        self
+    }
+
+    /// Records the time from the return of the endpoint to the first chunk of the response body,
+    /// in milliseconds, as the `http.response.ttfb_ms` property, for example the time until the
+    /// first event of a server-sent event stream or the first block of a download.
+    ///
+    /// The response body is wrapped to find out when its first chunk is sent, keeping the root
+    /// span open until it ends. Nothing is recorded for responses without a body.
+    pub fn with_ttfb(mut self, enable: bool) -> Self {
+        self.config.ttfb = enable;
+        self
+    }
+
+    /// Adds a `still running` event to the root span every `interval` while the inner endpoint
+    /// runs, with the time it has been running in milliseconds as the `elapsed_ms` property, so
+    /// hung requests can be told apart in backends receiving span events before the span ends.
+    ///
+    /// At most one event is added every time the endpoint is polled, so intervals shorter than the
+    /// time between polls record fewer events. The timer relies on the Tokio runtime Poem servers
+    /// run on. Disabled when zero, which is the default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_heartbeat(Duration::from_secs(10))
+    ///     .build();
+    /// ```
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.config.heartbeat = (!interval.is_zero()).then_some(interval);
+        self
+    }
+
+    /// Records the properties returned by the given function on the root span, for example a
+    /// tenant read from a header or the bucket of an A/B test.
+    ///
+    /// The function only runs for requests that get a root span. This can be called several times,
+    /// in which case the properties of every function are recorded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    ///
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_request_properties(|req| {
+    ///         req.headers()
+    ///             .get("x-tenant-id")
+    ///             .and_then(|value| value.to_str().ok())
+    ///             .map(|tenant| (Cow::Borrowed("tenant.id"), tenant.to_string()))
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn with_request_properties<I>(
+        self,
+        properties: impl Fn(&Request) -> I + Send + Sync + 'static,
+    ) -> Self
+    where
+        I: IntoIterator<Item = (Cow<'static, str>, String)>,
+    {
+        self.with_customizer(RequestProperties(move |req: &Request| {
+            properties(req).into_iter().collect()
+        }))
+    }
+
+    /// Records the properties returned by the given function on the root span once the endpoint
+    /// returned its response, for example values that handlers or authentication middleware put
+    /// into the response extensions, such as the user id or a cache hit flag.
+    ///
+    /// The function only runs for requests that get a root span and whose endpoint returned a
+    /// response rather than an error. This can be called several times, in which case the
+    /// properties of every function are recorded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    ///
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// #[derive(Clone)]
+    /// struct UserId(u64);
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_response_properties(|resp| {
+    ///         resp.extensions()
+    ///             .get::<UserId>()
+    ///             .map(|user| (Cow::Borrowed("enduser.id"), user.0.to_string()))
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn with_response_properties<I>(
+        self,
+        properties: impl Fn(&Response) -> I + Send + Sync + 'static,
+    ) -> Self
+    where
+        I: IntoIterator<Item = (Cow<'static, str>, String)>,
+    {
+        self.with_customizer(ResponseProperties(move |resp: &Response| {
+            properties(resp).into_iter().collect()
+        }))
+    }
+
+    /// Sets the function deciding the [`SpanStatus`] of the root span from the request and the
+    /// response status, for example to treat `404 Not Found` or `429 Too Many Requests` as
+    /// expected, or to only mark the failures of some routes or methods.
+    ///
+    /// The classifier receives the method, URI, version and headers of the request, without its
+    /// body or extensions. Defaults to marking server errors, and gRPC server errors reported in
+    /// the `grpc-status` header, as failed and leaving the status unset otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use fastrace_poem::SpanStatus;
+    /// use poem::http::StatusCode;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_status_classifier(|req, status| match status {
+    ///         StatusCode::SERVICE_UNAVAILABLE => SpanStatus::Unset,
+    ///         StatusCode::NOT_FOUND if req.uri().path().starts_with("/api/") => SpanStatus::Error,
+    ///         status if status.is_server_error() => SpanStatus::Error,
+    ///         _ => SpanStatus::Unset,
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn with_status_classifier(
+        mut self,
+        classifier: impl Fn(&Request, StatusCode) -> SpanStatus + Send + Sync + 'static,
+    ) -> Self {
+        self.config.status_classifier = Some(Box::new(classifier));
+        self
+    }
+
+    /// Adds a [`SpanCustomizer`] enriching the root span when it is created and once the endpoint
+    /// returned.
+    ///
+    /// This can be called several times, in which case the customizers run in the order they were
+    /// added.
+    pub fn with_customizer(mut self, customizer: impl SpanCustomizer) -> Self {
+        self.config.customizers.push(Box::new(customizer));
+        self
+    }
+
+    /// Records the given properties on every root span, for example the deployment environment or
+    /// the region of the service.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_attributes([("deployment.environment", "prod"), ("region", "eu-west-1")])
+    ///     .build();
+    /// ```
+    pub fn with_attributes<K, V>(mut self, attributes: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<Cow<'static, str>>,
+        V: Into<Cow<'static, str>>,
+    {
+        self.config.attributes = attributes
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self
+    }
+
+    /// Records the given `service.name` property on every root span.
+    ///
+    /// This distinguishes the spans of several logical services hosted by one binary, each mounted
+    /// with its own middleware on a different prefix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use poem::EndpointExt;
+    /// use poem::Route;
+    /// use poem::get;
+    /// use poem::handler;
+    ///
+    /// #[handler]
+    /// fn list() -> &'static str {
+    ///     "[]"
+    /// }
+    ///
+    /// let app = Route::new()
+    ///     .nest(
+    ///         "/billing",
+    ///         Route::new().at("/invoices", get(list)).with(
+    ///             FastraceMiddleware::builder()
+    ///                 .with_service_name("billing")
+    ///                 .build(),
+    ///         ),
+    ///     )
+    ///     .nest(
+    ///         "/catalog",
+    ///         Route::new().at("/products", get(list)).with(
+    ///             FastraceMiddleware::builder()
+    ///                 .with_service_name("catalog")
+    ///                 .build(),
+    ///         ),
+    ///     );
+    /// ```
+    pub fn with_service_name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.config.service_name = Some(name.into());
+        self
+    }
+
+    /// Trusts the `X-Forwarded-For` and `X-Real-IP` headers of requests received from the given
+    /// proxies to find the client address recorded as `client.address`.
+    ///
+    /// By default, the peer address of the connection is recorded. When the peer is one of the
+    /// trusted proxies, the last address of `X-Forwarded-For` that is not a trusted proxy is
+    /// recorded instead, falling back to `X-Real-IP`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::net::IpAddr;
+    /// use std::net::Ipv4Addr;
+    ///
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_trusted_proxies([IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))])
+    ///     .build();
+    /// ```
+    pub fn with_trusted_proxies(mut self, proxies: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.config.trusted_proxies = proxies.into_iter().collect();
+        self
+    }
+
+    /// Sets how to handle requests whose parent context is not sampled.
+    ///
+    /// Defaults to [`UnsampledParent::Propagate`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use fastrace_poem::UnsampledParent;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_unsampled_parent(UnsampledParent::Noop)
+    ///     .build();
+    /// ```
+    pub fn with_unsampled_parent(mut self, unsampled_parent: UnsampledParent) -> Self {
+        self.config.unsampled_parent = unsampled_parent;
+        self
+    }
+
+    /// Sets a header that forces the request to be traced when its value is `1` or `true`.
+    ///
+    /// A forced request always gets a sampled root span, continuing the parent context if there is
+    /// one and starting a new trace otherwise, regardless of the incoming sampled flag. Forced
+    /// requests are traced even when their path, method or a filter would skip them, but not while
+    /// tracing is turned off with [`with_switch`](Self::with_switch). This makes it possible to
+    /// trace a single request in production while debugging.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_force_trace_header("x-fastrace-force-trace")
+    ///     .build();
+    /// ```
+    pub fn with_force_trace_header(mut self, name: impl Into<String>) -> Self {
+        self.config.force_trace_header = Some(name.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Records parent contexts that conflict with the one used for the root span.
+    ///
+    /// When enabled, every extractor is run, and contexts referring to another trace or span than
+    /// the chosen parent are recorded on the root span, encoded as `traceparent` values, under the
+    /// `trace.conflicting_parents` property. This helps diagnosing callers that send different
+    /// contexts in different header formats. Disabled by default, as it requires running all
+    /// extractors for every request.
+    pub fn with_record_conflicting_parents(mut self, enable: bool) -> Self {
+        self.config.record_conflicting_parents = enable;
+        self
+    }
+
+    /// Writes the [`TRACERESPONSE_HEADER`](crate::TRACERESPONSE_HEADER) on responses, carrying
+    /// the trace id and span id of the server's root span, so that callers and browsers can
+    /// correlate their spans with the server trace. Nothing is written for requests that are not
+    /// traced.
+    pub fn with_traceresponse(mut self, enable: bool) -> Self {
+        self.config.traceresponse = enable;
+        self
+    }
+
+    /// Writes the [`TRACEPARENT_HEADER`](crate::TRACEPARENT_HEADER) on responses, carrying the
+    /// trace id and span id of the server's root span, for browser RUM SDKs and fetch
+    /// instrumentations that read the server context from it rather than from the `traceresponse`
+    /// header of [`with_traceresponse`](Self::with_traceresponse). Nothing is written for requests
+    /// that are not traced.
+    ///
+    /// Browsers only expose the header to cross-origin scripts listed in
+    /// `Access-Control-Expose-Headers`.
+    pub fn with_response_traceparent(mut self, enable: bool) -> Self {
+        self.config.response_traceparent = enable;
+        self
+    }
+
+    /// Appends a `Server-Timing` header to responses, with the time spent in the middleware as
+    /// the `total` metric and, for traced requests, the context of the root span as the
+    /// `traceparent` entry, such as:
+    ///
+    /// ```text
+    /// total;dur=12.5, traceparent;desc="00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+    /// ```
+    ///
+    /// Browser performance tooling reads `Server-Timing` natively, linking front-end measurements
+    /// to backend traces. Cross-origin responses also need a `Timing-Allow-Origin` header for
+    /// the entries to be exposed.
+    pub fn with_server_timing(mut self, enable: bool) -> Self {
+        self.config.server_timing = enable;
+        self
+    }
+
+    /// Writes the trace id of the root span on responses in the given header, usually
+    /// [`TRACE_ID_HEADER`](crate::TRACE_ID_HEADER), so that clients and support tooling can
+    /// reference the trace of a response. Nothing is written for requests that are not traced.
+    ///
+    /// When it writes response headers, such as this one, the
+    /// [`traceresponse`](Self::with_traceresponse) or the
+    /// [`Server-Timing`](Self::with_server_timing) header, the middleware converts the errors
+    /// returned by the endpoint into their responses, with the status of the error, so that their
+    /// responses get the headers too, and returns them as errors made from these responses with
+    /// [`Error::from_response`](poem::Error::from_response). Outer error handlers still receive an
+    /// error, but can no longer downcast it to the type of its source.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use fastrace_poem::TRACE_ID_HEADER;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_trace_id_header(TRACE_ID_HEADER)
+    ///     .build();
+    /// ```
+    pub fn with_trace_id_header(mut self, name: impl Into<String>) -> Self {
+        self.config.trace_id_header = Some(name.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Starts a new trace for requests carrying a malformed `traceparent` header, instead of not
+    /// tracing them.
+    ///
+    /// The root span gets an `invalid traceparent` event recording the received header value, so
+    /// that broken propagation in upstream services becomes visible.
+    pub fn with_new_root_on_invalid_parent(mut self, enable: bool) -> Self {
+        self.config.new_root_on_invalid_parent = enable;
+        self
+    }
+
+    /// Starts a new trace for requests without a parent context, instead of not tracing them.
+    ///
+    /// Enable this for services at the edge, which are the first to see a request and would
+    /// otherwise produce no traces at all.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_trace_all_requests(true)
+    ///     .build();
+    /// ```
+    pub fn with_trace_all_requests(mut self, enable: bool) -> Self {
+        self.config.trace_all_requests = enable;
+        self
+    }
+
+    /// Sets the generator of the context of new traces, used instead of
+    /// [`SpanContext::random`] when the middleware starts a trace, for example so that snapshot
+    /// tests of the recorded spans are stable across runs. Generated request ids stay random, so
+    /// that enabling them does not shift the generated contexts.
+    ///
+    /// The trace id and the parent span id of new root spans are taken from the generated context.
+    /// The ids of the spans themselves are assigned by fastrace.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::AtomicU64;
+    /// use std::sync::atomic::Ordering;
+    ///
+    /// use fastrace::collector::SpanContext;
+    /// use fastrace::collector::SpanId;
+    /// use fastrace::collector::TraceId;
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let next = AtomicU64::new(1);
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_trace_all_requests(true)
+    ///     .with_id_generator(move || {
+    ///         let id = next.fetch_add(1, Ordering::Relaxed);
+    ///         SpanContext::new(TraceId(id.into()), SpanId(id))
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn with_id_generator(
+        mut self,
+        generator: impl Fn() -> SpanContext + Send + Sync + 'static,
+    ) -> Self {
+        self.config.id_generator = Some(Box::new(generator));
+        self
+    }
+
+    /// Sets a sampler deciding for every request whether to record it, only propagate its context,
+    /// or not trace it at all, for example based on the path, the method or a customer header.
+    ///
+    /// The decision of the sampler replaces the sampled flag of the parent context and the
+    /// [`with_unsampled_parent`](Self::with_unsampled_parent) and
+    /// [`with_trace_all_requests`](Self::with_trace_all_requests) settings. Requests forced with
+    /// [`with_force_trace_header`](Self::with_force_trace_header) are always recorded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use fastrace_poem::SamplingDecision;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_sampler(|req| match req.uri().path() {
+    ///         "/health" => SamplingDecision::Drop,
+    ///         _ => SamplingDecision::Record,
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn with_sampler(
+        mut self,
+        sampler: impl Fn(&Request) -> SamplingDecision + Send + Sync + 'static,
+    ) -> Self {
+        self.config.sampler = Some(Box::new(sampler));
+        self
+    }
+
+    /// Records only the given ratio of the traces started by this service, between `0.0` and
+    /// `1.0`. The other new traces are only propagated.
+    ///
+    /// This applies when the service is the trace root, that is when the request has no parent
+    /// context. Requests with a parent context follow its sampled flag, and requests forced with
+    /// [`with_force_trace_header`](Self::with_force_trace_header) are always recorded. Defaults
+    /// to `1.0`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_trace_all_requests(true)
+    ///     .with_sample_ratio(0.05)
+    ///     .build();
+    /// ```
+    pub fn with_sample_ratio(mut self, ratio: f64) -> Self {
+        self.config.sample_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Ignores the sampled flag of parent contexts, so that the requests with a parent are sampled
+    /// like new traces, with [`with_sample_ratio`](Self::with_sample_ratio) and
+    /// [`with_max_roots_per_second`](Self::with_max_roots_per_second), as the OpenTelemetry
+    /// `traceidratio` sampler does. The trace of the parent is continued either way.
+    ///
+    /// As the ratio depends on the trace id only, the services resampling with the same ratio
+    /// record the same traces. Defaults to `false`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_trace_all_requests(true)
+    ///     .with_resample_parents(true)
+    ///     .with_sample_ratio(0.05)
+    ///     .build();
+    /// ```
+    pub fn with_resample_parents(mut self, enable: bool) -> Self {
+        self.config.resample_parents = enable;
+        self
+    }
+
+    /// Records at most the given number of traces started by this service per second. The other
+    /// new traces are only propagated, which protects the reporter pipeline during traffic spikes.
+    ///
+    /// Like [`with_sample_ratio`](Self::with_sample_ratio), this applies when the request has no
+    /// parent context and is not forced. The limit is shared by all endpoints wrapped by the same
+    /// middleware.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_trace_all_requests(true)
+    ///     .with_max_roots_per_second(100)
+    ///     .build();
+    /// ```
+    pub fn with_max_roots_per_second(mut self, limit: u32) -> Self {
+        self.config.rate_limiter = Some(RateLimiter::new(limit));
+        self
+    }
+
+    /// Reports only the requests that failed, that is whose response status is at least the given
+    /// status, for services where traces of successful requests are noise.
+    ///
+    /// The root span is still created for every request, so that the context is propagated and
+    /// child spans are collected, but it is cancelled once a response or an error below the status
+    /// is returned. For example, the `404 Not Found` errors returned by `Route` for unknown paths
+    /// are not reported with `with_errors_only(StatusCode::INTERNAL_SERVER_ERROR)`.
+    ///
+    /// This is a shorthand for [`with_tail_decision`](Self::with_tail_decision) and replaces the
+    /// decision set with it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use poem::http::StatusCode;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_errors_only(StatusCode::INTERNAL_SERVER_ERROR)
+    ///     .build();
+    /// ```
+    pub fn with_errors_only(self, min_status: StatusCode) -> Self {
+        self.with_tail_decision(move |status, _| status >= min_status)
+    }
+
+    /// Sets a decision taken once the response is returned, receiving its status and the time
+    /// spent handling the request, that determines whether the root span is reported (`true`) or
+    /// cancelled (`false`). This makes it possible to keep only slow or failed requests without a
+    /// tail-sampling collector.
+    ///
+    /// As with [`with_errors_only`](Self::with_errors_only), the root span is created for every
+    /// request. When the endpoint returns an error, the decision receives the status of the error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_tail_decision(|status, elapsed| {
+    ///         status.is_server_error() || elapsed > Duration::from_millis(500)
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn with_tail_decision(
+        mut self,
+        decision: impl Fn(StatusCode, Duration) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.tail_decision = Some(Box::new(decision));
+        self
+    }
+
+    /// Marks the root span of requests taking longer than `threshold` to handle with the
+    /// `slow_request=true` property, to search slow traces directly in backends.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use poem::http::StatusCode;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_slow_threshold(Duration::from_secs(1))
+    ///     .with_keep_slow_requests(true)
+    ///     .with_errors_only(StatusCode::INTERNAL_SERVER_ERROR)
+    ///     .build();
+    /// ```
+    pub fn with_slow_threshold(mut self, threshold: Duration) -> Self {
+        self.config.slow_threshold = Some(threshold);
+        self
+    }
+
+    /// Reports the requests marked as slow by [`with_slow_threshold`](Self::with_slow_threshold)
+    /// regardless of the [tail decision](Self::with_tail_decision).
+    pub fn with_keep_slow_requests(mut self, enable: bool) -> Self {
+        self.config.keep_slow_requests = enable;
+        self
+    }
+
+    /// Does not trace requests whose path matches one of the given patterns, where `*` matches any
+    /// sequence of characters, for example infrastructure endpoints such as health checks.
+    ///
+    /// Skipped requests are passed to the endpoint before any header is parsed, so they cost
+    /// nothing but the path matching. Requests forced with
+    /// [`with_force_trace_header`](Self::with_force_trace_header) are traced nonetheless, as with
+    /// the other filters.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_skip_paths(["/healthz", "/metrics", "/static/*"])
+    ///     .build();
+    /// ```
+    pub fn with_skip_paths(
+        mut self,
+        patterns: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.config.skip_paths = patterns.into_iter().map(PathGlob::new).collect();
+        self
+    }
+
+    /// Adds a [`RequestFilter`] deciding whether a request is traced, for example based on a
+    /// header, the method or the client address.
+    ///
+    /// This can be called several times, in which case a request is traced only if every filter
+    /// accepts it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use poem::Request;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_filter(|req: &Request| !req.headers().contains_key("x-internal-probe"))
+    ///     .build();
+    /// ```
+    pub fn with_filter(mut self, filter: impl RequestFilter) -> Self {
+        self.config.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Does not trace requests with the given methods, for example `OPTIONS` and `HEAD`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::FastraceMiddleware;
+    /// use poem::http::Method;
+    ///
+    /// let middleware = FastraceMiddleware::builder()
+    ///     .with_skip_methods([Method::OPTIONS, Method::HEAD])
+    ///     .build();
+    /// ```
+    pub fn with_skip_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.config.skip_methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Does not trace CORS preflight requests, that is `OPTIONS` requests with an
+    /// `Access-Control-Request-Method` header, which browser-heavy APIs receive in large numbers.
+    pub fn with_skip_preflight(mut self, enable: bool) -> Self {
+        self.config.skip_preflight = enable;
+        self
+    }
+
+    /// Sets a [`TracingSwitch`] to enable or disable tracing at runtime.
+    pub fn with_switch(mut self, switch: TracingSwitch) -> Self {
+        self.config.switch = Some(switch);
+        self
+    }
+
+    /// Builds the middleware.
+    pub fn build(self) -> FastraceMiddleware {
+        FastraceMiddleware {
+            configured: Some(self.configure()),
+        }
+    }
+
+    pub(crate) fn configure(self) -> Configured {
+        let extractor = self
+            .extractor
+            .unwrap_or_else(|| Arc::new(ExtractorChain::builtin_with(self.config.w3c.clone())));
+        let injector = self
+            .injector
+            .unwrap_or_else(|| Arc::new(self.config.w3c.clone()));
+
+        Configured {
+            extractor,
+            injector,
+            config: Arc::new(self.config),
+        }
+    }
+}
+
+fn header_names(names: impl IntoIterator<Item = impl Into<String>>) -> Vec<String> {
+    names
+        .into_iter()
+        .map(|name| name.into().to_ascii_lowercase())
+        .collect()
+}
@@ -0,0 +1,79 @@
+//! Propagation of the request context to outgoing reqwest calls.
+
+use fastrace::collector::SpanContext;
+use poem::http::HeaderMap;
+use poem::http::HeaderValue;
+use reqwest::RequestBuilder;
+
+use crate::BAGGAGE_HEADER;
+use crate::TRACEPARENT_HEADER;
+use crate::TRACESTATE_HEADER;
+use crate::inject::with_propagation;
+use crate::inject_context;
+
+/// An extension trait for [`reqwest::RequestBuilder`] continuing the trace of the request handled
+/// by [`FastraceMiddleware`](struct@crate::FastraceMiddleware) in the called service.
+pub trait RequestBuilderExt {
+    /// Adds the current local parent context to the request, written like
+    /// [`inject_context`](crate::inject_context), along with the `tracestate` and `baggage`
+    /// headers received by the handled request when the context is written in the `traceparent`
+    /// header, as they belong to the W3C trace. Nothing is added without a local parent.
+    ///
+    /// Call it while the handler is polled, before the request is sent, so that the context of
+    /// the span around the call is written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::RequestBuilderExt;
+    /// use poem::Result;
+    /// use poem::error::BadGateway;
+    /// use poem::handler;
+    ///
+    /// #[handler]
+    /// async fn proxy() -> Result<String> {
+    ///     reqwest::Client::new()
+    ///         .get("http://inventory/items")
+    ///         .with_trace_context()
+    ///         .send()
+    ///         .await
+    ///         .map_err(BadGateway)?
+    ///         .text()
+    ///         .await
+    ///         .map_err(BadGateway)
+    /// }
+    /// ```
+    fn with_trace_context(self) -> Self;
+}
+
+impl RequestBuilderExt for RequestBuilder {
+    fn with_trace_context(self) -> Self {
+        if SpanContext::current_local_parent().is_none() {
+            return self;
+        }
+        let mut headers = HeaderMap::new();
+        inject_context(&mut headers);
+        if !headers.contains_key(TRACEPARENT_HEADER) {
+            return self.headers(headers);
+        }
+        with_propagation(|propagation| {
+            let Some(propagation) = propagation else {
+                return;
+            };
+            let trace_state = propagation
+                .trace_state
+                .as_ref()
+                .map(|trace_state| (TRACESTATE_HEADER, trace_state.as_str()));
+            let baggage = propagation
+                .baggage
+                .as_ref()
+                .map(|baggage| (BAGGAGE_HEADER, baggage.as_str()));
+            for (name, value) in trace_state.into_iter().chain(baggage) {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    headers.insert(name, value);
+                }
+            }
+        });
+        self.headers(headers)
+    }
+}
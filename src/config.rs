@@ -0,0 +1,274 @@
+//! Deserializable configuration of [`FastraceMiddleware`](struct@crate::FastraceMiddleware).
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use poem::http::Method;
+use poem::http::StatusCode;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::de::Error;
+
+use crate::FastraceMiddlewareBuilder;
+use crate::PathNormalization;
+use crate::SemconvMode;
+use crate::UnsampledParent;
+use crate::filter::TraceFilter;
+use crate::propagation::W3CTraceContext;
+
+/// The configuration of [`FastraceMiddleware`](struct@crate::FastraceMiddleware), loaded from the
+/// service's configuration files with serde.
+///
+/// Every field is optional and defaults to the default of the matching
+/// [`FastraceMiddlewareBuilder`] method. The middleware is built by converting the configuration
+/// into a builder, which can be configured further.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceConfig;
+/// use fastrace_poem::FastraceMiddlewareBuilder;
+///
+/// let config: FastraceConfig = serde_json::from_str(
+///     r#"{
+///         "propagators": ["tracecontext", "b3"],
+///         "trace_all_requests": true,
+///         "sample_ratio": 0.05,
+///         "skip_paths": ["/metrics", "/static/*"],
+///         "ignore_probes": true
+///     }"#,
+/// )
+/// .unwrap();
+///
+/// let middleware = FastraceMiddlewareBuilder::from(config).build();
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct FastraceConfig {
+    /// The OpenTelemetry names of the propagators to extract the parent context and inject the
+    /// current one with, see [`FastraceMiddlewareBuilder::from_env`]. Defaults to extracting all
+    /// built-in propagation formats and injecting W3C Trace Context.
+    pub propagators: Option<Vec<String>>,
+    /// See [`FastraceMiddlewareBuilder::with_trace_header`].
+    pub trace_header: Option<String>,
+    /// See [`FastraceMiddlewareBuilder::with_baggage_properties`].
+    pub baggage_properties: Vec<String>,
+    /// See [`FastraceMiddlewareBuilder::with_service_name`].
+    pub service_name: Option<String>,
+    /// See [`FastraceMiddlewareBuilder::with_path_templating`].
+    pub path_templating: bool,
+    /// See [`FastraceMiddlewareBuilder::with_path_normalization`], with the `trim_trailing_slash`,
+    /// `collapse_slashes` and `lowercase` keys.
+    pub path_normalization: PathNormalization,
+    /// See [`FastraceMiddlewareBuilder::with_graphql_path`].
+    #[cfg(feature = "graphql")]
+    pub graphql_path: Option<String>,
+    /// See [`FastraceMiddlewareBuilder::with_max_span_names`].
+    pub max_span_names: Option<usize>,
+    /// See [`FastraceMiddlewareBuilder::with_route_params`].
+    pub route_params: bool,
+    /// See [`FastraceMiddlewareBuilder::with_redacted_route_params`].
+    pub redacted_route_params: Vec<String>,
+    /// See [`FastraceMiddlewareBuilder::with_query`].
+    pub query: bool,
+    /// See [`FastraceMiddlewareBuilder::with_redacted_query_params`].
+    pub redacted_query_params: Vec<String>,
+    /// See [`FastraceMiddlewareBuilder::with_measure_request_body`].
+    pub measure_request_body: bool,
+    /// See [`FastraceMiddlewareBuilder::with_measure_response_body`].
+    pub measure_response_body: bool,
+    /// See [`FastraceMiddlewareBuilder::with_error_body_snippet`].
+    pub error_body_snippet: usize,
+    /// See [`FastraceMiddlewareBuilder::with_capture_request_headers`].
+    pub capture_request_headers: Vec<String>,
+    /// See [`FastraceMiddlewareBuilder::with_capture_response_headers`].
+    pub capture_response_headers: Vec<String>,
+    /// See [`FastraceMiddlewareBuilder::with_max_attribute_len`].
+    pub max_attribute_len: Option<usize>,
+    /// See [`FastraceMiddlewareBuilder::with_semconv_mode`], as `new`, `old` or `dup`.
+    pub semconv_mode: SemconvMode,
+    /// See [`FastraceMiddlewareBuilder::with_request_id_header`].
+    pub request_id_header: Option<String>,
+    /// See [`FastraceMiddlewareBuilder::with_generate_request_id`].
+    pub generate_request_id: bool,
+    /// See [`FastraceMiddlewareBuilder::with_queue_time`].
+    pub queue_time: bool,
+    /// See [`FastraceMiddlewareBuilder::with_queue_time_event`].
+    pub queue_time_event: bool,
+    /// See [`FastraceMiddlewareBuilder::with_deadline`].
+    pub deadline: bool,
+    /// See [`FastraceMiddlewareBuilder::with_deadline_event`].
+    pub deadline_event: bool,
+    /// See [`FastraceMiddlewareBuilder::with_handler_span`].
+    pub handler_span: bool,
+    /// See [`FastraceMiddlewareBuilder::with_phase_spans`].
+    pub phase_spans: bool,
+    /// See [`FastraceMiddlewareBuilder::with_span_until_body_end`].
+    pub span_until_body_end: bool,
+    /// See [`FastraceMiddlewareBuilder::with_ttfb`].
+    pub ttfb: bool,
+    /// The interval of [`FastraceMiddlewareBuilder::with_heartbeat`], in milliseconds. Disabled
+    /// when zero.
+    pub heartbeat_ms: Option<u64>,
+    /// See [`FastraceMiddlewareBuilder::with_attributes`].
+    pub attributes: BTreeMap<String, String>,
+    /// See [`FastraceMiddlewareBuilder::with_unsampled_parent`].
+    pub unsampled_parent: UnsampledParent,
+    /// See [`FastraceMiddlewareBuilder::with_trusted_proxies`].
+    pub trusted_proxies: Vec<IpAddr>,
+    /// See [`FastraceMiddlewareBuilder::with_force_trace_header`].
+    pub force_trace_header: Option<String>,
+    /// See [`FastraceMiddlewareBuilder::with_record_conflicting_parents`].
+    pub record_conflicting_parents: bool,
+    /// See [`FastraceMiddlewareBuilder::with_traceresponse`].
+    pub traceresponse: bool,
+    /// See [`FastraceMiddlewareBuilder::with_trace_id_header`].
+    pub trace_id_header: Option<String>,
+    /// See [`FastraceMiddlewareBuilder::with_response_traceparent`].
+    pub response_traceparent: bool,
+    /// See [`FastraceMiddlewareBuilder::with_server_timing`].
+    pub server_timing: bool,
+    /// See [`FastraceMiddlewareBuilder::with_forward_context`].
+    pub forward_context: bool,
+    /// See [`FastraceMiddlewareBuilder::with_new_root_on_invalid_parent`].
+    pub new_root_on_invalid_parent: bool,
+    /// See [`FastraceMiddlewareBuilder::with_trace_all_requests`].
+    pub trace_all_requests: bool,
+    /// See [`FastraceMiddlewareBuilder::with_resample_parents`].
+    pub resample_parents: bool,
+    /// See [`FastraceMiddlewareBuilder::with_sample_ratio`].
+    pub sample_ratio: Option<f64>,
+    /// See [`FastraceMiddlewareBuilder::with_max_roots_per_second`].
+    pub max_roots_per_second: Option<u32>,
+    /// The minimum status of the reported requests, see
+    /// [`FastraceMiddlewareBuilder::with_errors_only`].
+    #[serde(deserialize_with = "deserialize_status")]
+    pub errors_only: Option<StatusCode>,
+    /// The threshold of [`FastraceMiddlewareBuilder::with_slow_threshold`], in milliseconds.
+    pub slow_threshold_ms: Option<u64>,
+    /// See [`FastraceMiddlewareBuilder::with_keep_slow_requests`].
+    pub keep_slow_requests: bool,
+    /// See [`FastraceMiddlewareBuilder::with_skip_paths`].
+    pub skip_paths: Vec<String>,
+    /// See [`FastraceMiddlewareBuilder::with_skip_methods`].
+    #[serde(deserialize_with = "deserialize_methods")]
+    pub skip_methods: Vec<Method>,
+    /// See [`FastraceMiddlewareBuilder::with_skip_preflight`].
+    pub skip_preflight: bool,
+    /// Skips health checks and probes, see [`TraceFilter::ignore_probes`].
+    pub ignore_probes: bool,
+}
+
+impl From<FastraceConfig> for FastraceMiddlewareBuilder {
+    fn from(config: FastraceConfig) -> Self {
+        let mut builder = FastraceMiddlewareBuilder::default();
+
+        if let Some(trace_header) = config.trace_header.clone() {
+            builder = builder.with_trace_header(trace_header);
+        }
+        if let Some(propagators) = &config.propagators {
+            let w3c = match config.trace_header {
+                Some(trace_header) => W3CTraceContext::with_header(trace_header),
+                None => W3CTraceContext::new(),
+            };
+            let names = || propagators.iter().map(String::as_str);
+            let injector = crate::env::injector_chain(names(), w3c.clone());
+            let chain = crate::env::extractor_chain(names(), w3c);
+            builder = builder.with_extractor(chain).with_injector(injector);
+        }
+        if let Some(service_name) = config.service_name {
+            builder = builder.with_service_name(service_name);
+        }
+        if let Some(force_trace_header) = config.force_trace_header {
+            builder = builder.with_force_trace_header(force_trace_header);
+        }
+        if let Some(ratio) = config.sample_ratio {
+            builder = builder.with_sample_ratio(ratio);
+        }
+        if let Some(limit) = config.max_roots_per_second {
+            builder = builder.with_max_roots_per_second(limit);
+        }
+        if let Some(min_status) = config.errors_only {
+            builder = builder.with_errors_only(min_status);
+        }
+        if let Some(trace_id_header) = config.trace_id_header {
+            builder = builder.with_trace_id_header(trace_id_header);
+        }
+        if let Some(request_id_header) = config.request_id_header {
+            builder = builder.with_request_id_header(request_id_header);
+        }
+        if let Some(max_len) = config.max_attribute_len {
+            builder = builder.with_max_attribute_len(max_len);
+        }
+        if let Some(threshold) = config.slow_threshold_ms {
+            builder = builder.with_slow_threshold(Duration::from_millis(threshold));
+        }
+        if let Some(interval) = config.heartbeat_ms {
+            builder = builder.with_heartbeat(Duration::from_millis(interval));
+        }
+        #[cfg(feature = "graphql")]
+        if let Some(path) = config.graphql_path {
+            builder = builder.with_graphql_path(path);
+        }
+        if let Some(max_names) = config.max_span_names {
+            builder = builder.with_max_span_names(max_names);
+        }
+        if config.ignore_probes {
+            builder = builder.with_filter(TraceFilter::ignore_probes());
+        }
+
+        builder
+            .with_baggage_properties(config.baggage_properties)
+            .with_attributes(config.attributes)
+            .with_trusted_proxies(config.trusted_proxies)
+            .with_path_templating(config.path_templating)
+            .with_path_normalization(config.path_normalization)
+            .with_route_params(config.route_params)
+            .with_redacted_route_params(config.redacted_route_params)
+            .with_query(config.query)
+            .with_redacted_query_params(config.redacted_query_params)
+            .with_measure_request_body(config.measure_request_body)
+            .with_measure_response_body(config.measure_response_body)
+            .with_error_body_snippet(config.error_body_snippet)
+            .with_capture_request_headers(config.capture_request_headers)
+            .with_capture_response_headers(config.capture_response_headers)
+            .with_semconv_mode(config.semconv_mode)
+            .with_generate_request_id(config.generate_request_id)
+            .with_queue_time(config.queue_time)
+            .with_queue_time_event(config.queue_time_event)
+            .with_deadline(config.deadline)
+            .with_deadline_event(config.deadline_event)
+            .with_handler_span(config.handler_span)
+            .with_phase_spans(config.phase_spans)
+            .with_span_until_body_end(config.span_until_body_end)
+            .with_ttfb(config.ttfb)
+            .with_unsampled_parent(config.unsampled_parent)
+            .with_record_conflicting_parents(config.record_conflicting_parents)
+            .with_traceresponse(config.traceresponse)
+            .with_response_traceparent(config.response_traceparent)
+            .with_server_timing(config.server_timing)
+            .with_forward_context(config.forward_context)
+            .with_new_root_on_invalid_parent(config.new_root_on_invalid_parent)
+            .with_trace_all_requests(config.trace_all_requests)
+            .with_resample_parents(config.resample_parents)
+            .with_keep_slow_requests(config.keep_slow_requests)
+            .with_skip_paths(config.skip_paths)
+            .with_skip_methods(config.skip_methods)
+            .with_skip_preflight(config.skip_preflight)
+    }
+}
+
+fn deserialize_status<'de, D: Deserializer<'de>>(d: D) -> Result<Option<StatusCode>, D::Error> {
+    Option::<u16>::deserialize(d)?
+        .map(|code| StatusCode::from_u16(code).map_err(D::Error::custom))
+        .transpose()
+}
+
+fn deserialize_methods<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Method>, D::Error> {
+    Vec::<String>::deserialize(d)?
+        .iter()
+        .map(|method| Method::from_bytes(method.as_bytes()).map_err(D::Error::custom))
+        .collect()
+}
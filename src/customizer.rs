@@ -0,0 +1,92 @@
+//! Hooks enriching the root span over the lifetime of a request.
+
+use std::borrow::Cow;
+
+use fastrace::Span;
+use poem::Error;
+use poem::Request;
+use poem::Response;
+
+/// Enriches the root span of a request at each stage of its lifetime, so that span enrichment
+/// policies can be shared as a single value between services.
+///
+/// Customizers are added with
+/// [`FastraceMiddlewareBuilder::with_customizer`](crate::FastraceMiddlewareBuilder::with_customizer)
+/// and only run for requests that get a root span. Every method does nothing by default.
+///
+/// # Example
+///
+/// ```
+/// use fastrace::Span;
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::SpanCustomizer;
+/// use poem::Error;
+/// use poem::Request;
+///
+/// struct Tenant;
+///
+/// impl SpanCustomizer for Tenant {
+///     fn on_request(&self, span: &Span, req: &Request) {
+///         if let Some(tenant) = req.header("x-tenant-id") {
+///             span.add_property(|| ("tenant.id", tenant.to_string()));
+///         }
+///     }
+///
+///     fn on_error(&self, span: &Span, err: &Error) {
+///         span.add_property(|| {
+///             (
+///                 "error.kind",
+///                 if err.is::<std::io::Error>() {
+///                     "io"
+///                 } else {
+///                     "other"
+///                 },
+///             )
+///         });
+///     }
+/// }
+///
+/// let middleware = FastraceMiddleware::builder()
+///     .with_customizer(Tenant)
+///     .build();
+/// ```
+pub trait SpanCustomizer: Send + Sync + 'static {
+    /// Called when the root span is created, before the request is passed to the endpoint.
+    fn on_request(&self, span: &Span, req: &Request) {
+        let _ = (span, req);
+    }
+
+    /// Called when the endpoint returned a response.
+    fn on_response(&self, span: &Span, resp: &Response) {
+        let _ = (span, resp);
+    }
+
+    /// Called when the endpoint returned an error.
+    fn on_error(&self, span: &Span, err: &Error) {
+        let _ = (span, err);
+    }
+}
+
+type Properties = Vec<(Cow<'static, str>, String)>;
+
+/// Records the properties returned by a function of the request.
+pub(crate) struct RequestProperties<F>(pub(crate) F);
+
+impl<F> SpanCustomizer for RequestProperties<F>
+where F: Fn(&Request) -> Properties + Send + Sync + 'static
+{
+    fn on_request(&self, span: &Span, req: &Request) {
+        span.add_properties(|| (self.0)(req));
+    }
+}
+
+/// Records the properties returned by a function of the response.
+pub(crate) struct ResponseProperties<F>(pub(crate) F);
+
+impl<F> SpanCustomizer for ResponseProperties<F>
+where F: Fn(&Response) -> Properties + Send + Sync + 'static
+{
+    fn on_response(&self, span: &Span, resp: &Response) {
+        span.add_properties(|| (self.0)(resp));
+    }
+}
@@ -0,0 +1,148 @@
+//! Configuration from the standard OpenTelemetry environment variables.
+
+use std::env;
+
+use crate::FastraceMiddlewareBuilder;
+use crate::SamplingDecision;
+use crate::propagation::B3;
+use crate::propagation::B3Multi;
+use crate::propagation::Datadog;
+use crate::propagation::ExtractorChain;
+use crate::propagation::InjectorChain;
+use crate::propagation::Jaeger;
+use crate::propagation::W3CTraceContext;
+#[cfg(feature = "xray")]
+use crate::propagation::XRay;
+
+const OTEL_PROPAGATORS: &str = "OTEL_PROPAGATORS";
+const OTEL_TRACES_SAMPLER: &str = "OTEL_TRACES_SAMPLER";
+const OTEL_TRACES_SAMPLER_ARG: &str = "OTEL_TRACES_SAMPLER_ARG";
+
+pub(crate) fn configure(mut builder: FastraceMiddlewareBuilder) -> FastraceMiddlewareBuilder {
+    if let Ok(propagators) = env::var(OTEL_PROPAGATORS) {
+        let chain = extractor_chain(propagators.split(','), W3CTraceContext::new());
+        let injector = injector_chain(propagators.split(','), W3CTraceContext::new());
+        builder = builder.with_extractor(chain).with_injector(injector);
+    }
+
+    let ratio = env::var(OTEL_TRACES_SAMPLER_ARG)
+        .ok()
+        .and_then(|arg| arg.trim().parse::<f64>().ok())
+        .unwrap_or(1.0);
+    match env::var(OTEL_TRACES_SAMPLER).as_deref().map(str::trim) {
+        Ok("always_on") => builder.with_sampler(|_| SamplingDecision::Record),
+        // Parents are still propagated, unsampled, but no trace is started.
+        Ok("always_off") => builder
+            .with_trace_all_requests(false)
+            .with_resample_parents(true)
+            .with_sample_ratio(0.0),
+        Ok("traceidratio") => builder
+            .with_trace_all_requests(true)
+            .with_resample_parents(true)
+            .with_sample_ratio(ratio),
+        Ok("parentbased_always_on") => builder.with_trace_all_requests(true),
+        Ok("parentbased_always_off") => builder.with_trace_all_requests(false),
+        Ok("parentbased_traceidratio") => builder
+            .with_trace_all_requests(true)
+            .with_sample_ratio(ratio),
+        _ => builder,
+    }
+}
+
+/// Builds the chain of extractors for the given OpenTelemetry propagator names, ignoring unknown
+/// names.
+pub(crate) fn extractor_chain<'a>(
+    propagators: impl IntoIterator<Item = &'a str>,
+    w3c: W3CTraceContext,
+) -> ExtractorChain {
+    let mut chain = ExtractorChain::new();
+    let mut has_b3 = false;
+    for propagator in propagators.into_iter().map(str::trim) {
+        chain = match propagator {
+            "tracecontext" => chain.with(w3c.clone()),
+            // The B3 extractor accepts both the single and the multi-header formats.
+            "b3" | "b3multi" if !has_b3 => {
+                has_b3 = true;
+                chain.with(B3)
+            }
+            "jaeger" => chain.with(Jaeger),
+            "datadog" => chain.with(Datadog),
+            #[cfg(feature = "xray")]
+            "xray" => chain.with(XRay),
+            _ => chain,
+        };
+    }
+    chain
+}
+
+/// Builds the chain of injectors for the given OpenTelemetry propagator names, ignoring unknown
+/// names and formats without an injector.
+pub(crate) fn injector_chain<'a>(
+    propagators: impl IntoIterator<Item = &'a str>,
+    w3c: W3CTraceContext,
+) -> InjectorChain {
+    let mut chain = InjectorChain::new();
+    for propagator in propagators.into_iter().map(str::trim) {
+        chain = match propagator {
+            "tracecontext" => chain.with(w3c.clone()),
+            "b3" => chain.with(B3),
+            "b3multi" => chain.with(B3Multi),
+            "jaeger" => chain.with(Jaeger),
+            "datadog" => chain.with(Datadog),
+            _ => chain,
+        };
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use fastrace::prelude::*;
+    use poem::http::HeaderMap;
+    use poem::http::HeaderValue;
+
+    use super::*;
+    use crate::propagation::ContextExtractor;
+    use crate::propagation::ContextInjector;
+
+    #[test]
+    fn extractor_chain_follows_the_propagator_names() {
+        let chain = extractor_chain("b3, jaeger, unknown".split(','), W3CTraceContext::new());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            HeaderValue::from_static("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+        );
+        assert_eq!(chain.extract(&headers), None);
+
+        headers.insert(
+            "uber-trace-id",
+            HeaderValue::from_static("0af7651916cd43dd8448eb211c80319c:b7ad6b7169203331:0:1"),
+        );
+        assert_eq!(
+            chain.extract(&headers).unwrap().span_id,
+            SpanId(0xb7ad6b7169203331)
+        );
+    }
+
+    #[test]
+    fn injector_chain_follows_the_propagator_names() {
+        let chain = injector_chain(
+            "tracecontext,b3multi,xray".split(','),
+            W3CTraceContext::new(),
+        );
+        let mut headers = HeaderMap::new();
+        chain.inject(
+            &SpanContext::new(TraceId(1), SpanId(2)).sampled(true),
+            &mut headers,
+        );
+
+        assert_eq!(
+            headers["traceparent"],
+            "00-00000000000000000000000000000001-0000000000000002-01"
+        );
+        assert_eq!(headers["x-b3-traceid"], "00000000000000000000000000000001");
+        assert_eq!(headers["x-b3-spanid"], "0000000000000002");
+        assert!(!headers.contains_key("b3"));
+    }
+}
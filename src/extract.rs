@@ -0,0 +1,231 @@
+//! Handler extractors for the trace of the request.
+
+use std::any::type_name;
+use std::fmt;
+use std::future::Future;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use fastrace::Event;
+use fastrace::Span;
+use fastrace::collector::SpanContext;
+use poem::FromRequest;
+use poem::Request;
+use poem::RequestBody;
+use poem::Result;
+
+use crate::attributes::MaxAttributeLen;
+use crate::attributes::exception_properties;
+
+/// An extractor for the trace id of the request, to include it in error payloads or audit logs.
+///
+/// The id is read from the [`SpanContext`] stored in the request extensions by
+/// [`FastraceMiddleware`](struct@crate::FastraceMiddleware). Extracting it never fails: it is
+/// `None` when the request is not traced, the middleware is not applied or no reporter is set.
+///
+/// The id is formatted as the 32 lowercase hex digits used in `traceparent` headers, and as an
+/// empty string when it is missing.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::TraceId;
+/// use poem::handler;
+///
+/// #[handler]
+/// fn checkout(trace_id: TraceId) -> String {
+///     format!("order failed, reference: {trace_id}")
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TraceId(pub Option<fastrace::collector::TraceId>);
+
+impl TraceId {
+    /// Returns the trace id, if the request is traced.
+    pub fn get(&self) -> Option<fastrace::collector::TraceId> {
+        self.0
+    }
+
+    /// Returns the trace id of the request handled on the current thread, read from the local
+    /// parent span set by [`FastraceMiddleware`](struct@crate::FastraceMiddleware) while it polls
+    /// the inner endpoint. This is meant for log formatters, which have no access to the
+    /// request.
+    pub fn current() -> Self {
+        TraceId(SpanContext::current_local_parent().map(|context| context.trace_id))
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(trace_id) => write!(f, "{trace_id}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> FromRequest<'a> for TraceId {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> Result<Self> {
+        let context = req.extensions().get::<SpanContext>();
+        Ok(TraceId(context.map(|context| context.trace_id)))
+    }
+}
+
+/// An extractor for the root span of the request, to create child spans or add properties in code
+/// paths that are not instrumented with [`LocalSpan`](fastrace::local::LocalSpan).
+///
+/// The handle is cheap to clone and dereferences to the [`Span`] created by
+/// [`FastraceMiddleware`](struct@crate::FastraceMiddleware). It is a noop span when the request is
+/// not traced or the middleware is not applied. The root span is only reported once every handle is
+/// dropped, so handles moved into background tasks keep it open.
+///
+/// # Example
+///
+/// ```
+/// use fastrace::prelude::*;
+/// use fastrace_poem::CurrentSpan;
+/// use poem::handler;
+///
+/// #[handler]
+/// async fn upload(span: CurrentSpan) -> &'static str {
+///     span.add_property(|| ("upload.kind", "avatar"));
+///
+///     let child = Span::enter_with_parent("resize", &span);
+///     std::thread::spawn(move || {
+///         let _child = child;
+///         // Resize the image on another thread.
+///     });
+///
+///     "ok"
+/// }
+/// ```
+#[derive(Clone)]
+pub struct CurrentSpan(pub(crate) Arc<Span>);
+
+impl Deref for CurrentSpan {
+    type Target = Span;
+
+    fn deref(&self) -> &Span {
+        &self.0
+    }
+}
+
+impl fmt::Debug for CurrentSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CurrentSpan")
+            .field(&SpanContext::from_span(&self.0))
+            .finish()
+    }
+}
+
+impl<'a> FromRequest<'a> for CurrentSpan {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> Result<Self> {
+        Ok(req
+            .extensions()
+            .get::<CurrentSpan>()
+            .cloned()
+            .unwrap_or_else(|| CurrentSpan(Arc::new(Span::noop()))))
+    }
+}
+
+/// An extractor wrapper timing the inner extractor in a child span named after its type, such as
+/// `Json<Order>`, to show hidden costs like reading and deserializing the body in traces.
+///
+/// Errors of the inner extractor are recorded as exception events on the span, truncated to the
+/// [maximum length](crate::FastraceMiddlewareBuilder::with_max_attribute_len) configured on the
+/// middleware.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::Traced;
+/// use poem::handler;
+/// use poem::web::Json;
+/// use serde_json::Value;
+///
+/// #[handler]
+/// fn create(Traced(Json(order)): Traced<Json<Value>>) -> String {
+///     order["id"].to_string()
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Traced<T>(pub T);
+
+impl<T> Traced<T> {
+    /// Returns the inner extractor.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Traced<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Traced<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<'a, T: FromRequest<'a>> FromRequest<'a> for Traced<T> {
+    async fn from_request(req: &'a Request, body: &mut RequestBody) -> Result<Self> {
+        let span = Span::enter_with_local_parent(short_type_name(type_name::<T>()));
+        // Boxed to erase the future, see https://github.com/rust-lang/rust/issues/100013.
+        let value: Pin<Box<dyn Future<Output = Result<T>> + Send + '_>> =
+            Box::pin(T::from_request(req, body));
+        let value = value.await;
+        if let Err(err) = &value {
+            span.add_event(
+                Event::new("exception")
+                    .with_properties(|| exception_properties(err, MaxAttributeLen::of(req))),
+            );
+        }
+        value.map(Traced)
+    }
+}
+
+/// Strips the module paths from a type name, turning `poem::web::Json<app::Order>` into
+/// `Json<Order>`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut segment_start = 0;
+    let mut rest = name;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("::") {
+            short.truncate(segment_start);
+            rest = &rest[2..];
+            continue;
+        }
+        short.push(c);
+        if matches!(c, '<' | '>' | ',' | ' ' | '(' | ')' | '[' | ']' | '&' | ';') {
+            segment_start = short.len();
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_type_name_strips_module_paths() {
+        assert_eq!(
+            short_type_name("poem::web::Json<app::Order>"),
+            "Json<Order>"
+        );
+        assert_eq!(
+            short_type_name("core::option::Option<(alloc::string::String, &[u8; 4])>"),
+            "Option<(String, &[u8; 4])>"
+        );
+        assert_eq!(short_type_name("u64"), "u64");
+    }
+}
@@ -0,0 +1,276 @@
+//! Filters deciding which requests are traced.
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use poem::Request;
+use poem::http::header;
+
+/// Decides whether a request is traced.
+///
+/// Requests rejected by a filter are passed to the endpoint untouched: no span is created and the
+/// parent context is not extracted. Filters are set with
+/// [`FastraceMiddlewareBuilder::with_filter`](crate::FastraceMiddlewareBuilder::with_filter), and
+/// closures taking a `&Request` and returning a `bool` implement this trait.
+pub trait RequestFilter: Send + Sync + 'static {
+    /// Returns whether the request is traced.
+    fn should_trace(&self, req: &Request) -> bool;
+}
+
+impl<F> RequestFilter for F
+where F: Fn(&Request) -> bool + Send + Sync + 'static
+{
+    fn should_trace(&self, req: &Request) -> bool {
+        self(req)
+    }
+}
+
+/// A path pattern where `*` matches any sequence of characters, including `/`.
+pub(crate) struct PathGlob(String);
+
+impl PathGlob {
+    pub(crate) fn new(pattern: impl Into<String>) -> Self {
+        Self(pattern.into())
+    }
+
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        glob_match(self.0.as_bytes(), path.as_bytes())
+    }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The position after the last `*` and the text position it currently matches up to.
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, t));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// A [`RequestFilter`] skipping requests by path and `User-Agent`.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::filter::TraceFilter;
+///
+/// let middleware = FastraceMiddleware::builder()
+///     .with_filter(TraceFilter::ignore_probes())
+///     .build();
+/// ```
+#[derive(Default)]
+pub struct TraceFilter {
+    paths: Vec<PathGlob>,
+    user_agents: Vec<String>,
+}
+
+impl TraceFilter {
+    /// Creates a filter accepting every request.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a filter skipping health checks and probes: requests from the Kubernetes, AWS
+    /// ELB and Google Cloud load balancer health checkers, and requests to common health paths
+    /// such as `/healthz`, `/livez` and `/readyz`.
+    pub fn ignore_probes() -> Self {
+        Self::new()
+            .with_skip_paths([
+                "/health",
+                "/health/*",
+                "/healthz",
+                "/livez",
+                "/readyz",
+                "/ready",
+                "/live",
+            ])
+            .with_skip_user_agents(["kube-probe/", "ELB-HealthChecker/", "GoogleHC/"])
+    }
+
+    /// Skips requests whose path matches one of the given patterns, where `*` matches any
+    /// sequence of characters.
+    pub fn with_skip_paths(
+        mut self,
+        patterns: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.paths.extend(patterns.into_iter().map(PathGlob::new));
+        self
+    }
+
+    /// Skips requests whose `User-Agent` header starts with one of the given prefixes.
+    pub fn with_skip_user_agents(
+        mut self,
+        prefixes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.user_agents
+            .extend(prefixes.into_iter().map(Into::into));
+        self
+    }
+}
+
+impl RequestFilter for TraceFilter {
+    fn should_trace(&self, req: &Request) -> bool {
+        let path = req.uri().path();
+        if self.paths.iter().any(|glob| glob.matches(path)) {
+            return false;
+        }
+
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok());
+        match user_agent {
+            Some(user_agent) => !self
+                .user_agents
+                .iter()
+                .any(|prefix| user_agent.starts_with(prefix)),
+            None => true,
+        }
+    }
+}
+
+/// A handle enabling or disabling tracing at runtime, for example from an admin endpoint when the
+/// tracing backend is degraded.
+///
+/// The handle is cheap to clone, and every clone controls the middlewares it was given to with
+/// [`FastraceMiddlewareBuilder::with_switch`](crate::FastraceMiddlewareBuilder::with_switch).
+/// While disabled, requests are passed to the endpoint untouched, including requests forced with
+/// the force-trace header.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::filter::TracingSwitch;
+///
+/// let switch = TracingSwitch::new();
+/// let middleware = FastraceMiddleware::builder()
+///     .with_switch(switch.clone())
+///     .build();
+///
+/// switch.disable();
+/// ```
+#[derive(Clone, Debug)]
+pub struct TracingSwitch(Arc<AtomicBool>);
+
+impl Default for TracingSwitch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TracingSwitch {
+    /// Creates an enabled switch.
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicBool::new(true)))
+    }
+
+    /// Enables tracing.
+    pub fn enable(&self) {
+        self.set(true);
+    }
+
+    /// Disables tracing.
+    pub fn disable(&self) {
+        self.set(false);
+    }
+
+    /// Enables or disables tracing.
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns whether tracing is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        glob_match(pattern.as_bytes(), path.as_bytes())
+    }
+
+    #[test]
+    fn glob_matches_literal_paths() {
+        assert!(matches("/health", "/health"));
+        assert!(!matches("/health", "/healthz"));
+        assert!(!matches("/health", "/heal"));
+        assert!(matches("", ""));
+        assert!(!matches("", "/"));
+    }
+
+    #[test]
+    fn glob_star_matches_any_sequence_including_slashes() {
+        assert!(matches("/static/*", "/static/"));
+        assert!(matches("/static/*", "/static/css/site.css"));
+        assert!(matches("*", ""));
+        assert!(matches("*.js", "/assets/app.js"));
+        assert!(!matches("*.js", "/assets/app.json"));
+        assert!(!matches("/static/*", "/static"));
+    }
+
+    #[test]
+    fn glob_backtracks_over_several_stars() {
+        assert!(matches("/api/*/v*/health", "/api/users/v1/v2/health"));
+        assert!(matches("**", "/anything"));
+        assert!(matches("/a*b*c", "/aXbYbZc"));
+        assert!(!matches("/a*b*c", "/aXbYbZ"));
+        assert!(matches("/*/*", "/a/b/c"));
+    }
+
+    #[test]
+    fn trace_filter_skips_paths_and_user_agents() {
+        let filter = TraceFilter::new()
+            .with_skip_paths(["/internal/*"])
+            .with_skip_user_agents(["kube-probe/"]);
+        let request = |path: &str, user_agent: Option<&str>| {
+            let builder = Request::builder().uri(path.parse().unwrap());
+            match user_agent {
+                Some(user_agent) => builder.header(header::USER_AGENT, user_agent).finish(),
+                None => builder.finish(),
+            }
+        };
+
+        assert!(filter.should_trace(&request("/users", None)));
+        assert!(filter.should_trace(&request("/users", Some("curl/8.0"))));
+        assert!(!filter.should_trace(&request("/internal/metrics", None)));
+        assert!(!filter.should_trace(&request("/users", Some("kube-probe/1.29"))));
+    }
+
+    #[test]
+    fn tracing_switch_is_shared_by_clones() {
+        let switch = TracingSwitch::new();
+        let clone = switch.clone();
+        assert!(clone.is_enabled());
+        switch.disable();
+        assert!(!clone.is_enabled());
+        clone.set(true);
+        assert!(switch.is_enabled());
+    }
+}
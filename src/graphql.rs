@@ -0,0 +1,401 @@
+//! Operation-aware naming of GraphQL requests.
+
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+
+use bytes::Bytes;
+use bytes::BytesMut;
+use http_body::Body as HttpBody;
+use http_body::Frame;
+use http_body::SizeHint;
+use http_body_util::BodyExt;
+use percent_encoding::percent_decode_str;
+use poem::Body;
+use poem::Request;
+use poem::http::Method;
+use poem::http::header;
+use serde::Deserialize;
+
+type BoxBody = http_body_util::combinators::BoxBody<Bytes, io::Error>;
+
+/// The property recording the name of the GraphQL operation.
+const GRAPHQL_OPERATION_NAME: &str = "graphql.operation.name";
+
+/// The property recording the type of the GraphQL operation: `query`, `mutation` or
+/// `subscription`.
+const GRAPHQL_OPERATION_TYPE: &str = "graphql.operation.type";
+
+/// The largest body read to find the operation. Larger bodies are passed on without being
+/// inspected.
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+/// The GraphQL operation executed by a request.
+pub(crate) struct GraphqlOperation {
+    pub(crate) name: Option<String>,
+    pub(crate) kind: Option<&'static str>,
+}
+
+impl GraphqlOperation {
+    pub(crate) fn properties(&self) -> Vec<(&'static str, String)> {
+        let name = self.name.clone().map(|name| (GRAPHQL_OPERATION_NAME, name));
+        let kind = self
+            .kind
+            .map(|kind| (GRAPHQL_OPERATION_TYPE, kind.to_string()));
+        name.into_iter().chain(kind).collect()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlRequest {
+    query: Option<String>,
+    operation_name: Option<String>,
+}
+
+/// Returns the operation of a GraphQL request, sent in the query of `GET` requests or as a JSON
+/// body otherwise. JSON bodies of up to [`MAX_BODY_LEN`] bytes are read and always put back into
+/// the request, other bodies are not inspected. Batched requests are not inspected either.
+pub(crate) async fn graphql_operation(req: &mut Request) -> Option<GraphqlOperation> {
+    let request = if req.method() == Method::GET {
+        let mut request = GraphqlRequest {
+            query: None,
+            operation_name: None,
+        };
+        for pair in req.uri().query()?.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = value.replace('+', " ");
+            let value = percent_decode_str(&value).decode_utf8_lossy().into_owned();
+            match key {
+                "query" => request.query = Some(value),
+                "operationName" => request.operation_name = Some(value),
+                _ => {}
+            }
+        }
+        request
+    } else {
+        if !is_json(req) || content_length(req).is_some_and(|len| len > MAX_BODY_LEN as u64) {
+            return None;
+        }
+        let (data, body) = buffer_body(req.take_body(), MAX_BODY_LEN).await;
+        req.set_body(body);
+        serde_json::from_slice(&data?).ok()?
+    };
+
+    let name = request.operation_name.filter(|name| !name.is_empty());
+    let (kind, document_name) = match &request.query {
+        Some(query) => operation_definition(query, name.as_deref())?,
+        None => (None, None),
+    };
+    Some(GraphqlOperation {
+        name: name.or(document_name),
+        kind,
+    })
+}
+
+fn is_json(req: &Request) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+fn content_length(req: &Request) -> Option<u64> {
+    req.headers()
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Reads the body until it ends or yields more than `max_len` bytes of data. Returns the data of
+/// the body when it ended within the limit, and a body yielding the same frames as the original
+/// one, including any error, to put back into the request in every case.
+async fn buffer_body(body: Body, max_len: usize) -> (Option<Bytes>, Body) {
+    let mut inner = BoxBody::from(body);
+    let mut frames = VecDeque::new();
+    let mut len = 0;
+    let (complete, error, rest) = loop {
+        match inner.frame().await {
+            Some(Ok(frame)) => {
+                len += frame.data_ref().map_or(0, Bytes::len);
+                frames.push_back(frame);
+                if len > max_len {
+                    break (false, None, Some(inner));
+                }
+            }
+            Some(Err(err)) => break (false, Some(err), None),
+            None => break (true, None, None),
+        }
+    };
+
+    let data = complete.then(|| {
+        let mut data = BytesMut::with_capacity(len);
+        for frame in &frames {
+            if let Some(chunk) = frame.data_ref() {
+                data.extend_from_slice(chunk);
+            }
+        }
+        data.freeze()
+    });
+    let body = BufferedBody {
+        frames,
+        error,
+        rest,
+    };
+    (data, Body::from(BoxBody::new(body)))
+}
+
+/// A body replaying the frames read by [`buffer_body`], followed by the rest of the original body.
+struct BufferedBody {
+    frames: VecDeque<Frame<Bytes>>,
+    error: Option<io::Error>,
+    rest: Option<BoxBody>,
+}
+
+impl HttpBody for BufferedBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        let this = self.get_mut();
+        if let Some(frame) = this.frames.pop_front() {
+            return Poll::Ready(Some(Ok(frame)));
+        }
+        if let Some(err) = this.error.take() {
+            return Poll::Ready(Some(Err(err)));
+        }
+        match &mut this.rest {
+            Some(rest) => Pin::new(rest).poll_frame(cx),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.frames.is_empty()
+            && self.error.is_none()
+            && self.rest.as_ref().is_none_or(HttpBody::is_end_stream)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self
+            .frames
+            .iter()
+            .filter_map(Frame::data_ref)
+            .map(|data| data.len() as u64)
+            .sum::<u64>();
+        let mut hint = match &self.rest {
+            Some(rest) => rest.size_hint(),
+            None if self.error.is_none() => SizeHint::with_exact(0),
+            None => SizeHint::default(),
+        };
+        if let Some(upper) = hint.upper() {
+            hint.set_upper(upper + buffered);
+        }
+        hint.set_lower(hint.lower() + buffered);
+        hint
+    }
+}
+
+/// Finds the type and name of the operation in the document, the one with the given name or the
+/// first one. Anonymous `{ ... }` operations are queries.
+fn operation_definition(
+    document: &str,
+    name: Option<&str>,
+) -> Option<(Option<&'static str>, Option<String>)> {
+    let mut tokens = Tokens {
+        rest: document,
+        depth: 0,
+    };
+    let mut first = None;
+    // Whether the next selection set belongs to a named definition.
+    let mut in_definition = false;
+    while let Some(token) = tokens.next_top_level() {
+        let (kind, operation_name) = match token {
+            "{" if in_definition => {
+                in_definition = false;
+                continue;
+            }
+            "{" => (Some("query"), None),
+            "fragment" => {
+                in_definition = true;
+                continue;
+            }
+            "query" | "mutation" | "subscription" => {
+                in_definition = true;
+                let kind = match token {
+                    "query" => "query",
+                    "mutation" => "mutation",
+                    _ => "subscription",
+                };
+                (Some(kind), tokens.peek_name().map(str::to_string))
+            }
+            _ => continue,
+        };
+        match name {
+            Some(name) if operation_name.as_deref() == Some(name) => {
+                return Some((kind, operation_name));
+            }
+            Some(_) => {}
+            None => return Some((kind, operation_name)),
+        }
+        first.get_or_insert((kind, operation_name));
+    }
+    // The named operation was not found, the server rejects the request.
+    name.map(|_| (None, None)).or(first)
+}
+
+/// A minimal GraphQL lexer, yielding the names and opening braces outside of selection sets and
+/// argument lists.
+struct Tokens<'a> {
+    rest: &'a str,
+    depth: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn next_top_level(&mut self) -> Option<&'a str> {
+        loop {
+            self.skip_ignored();
+            let c = self.rest.chars().next()?;
+            match c {
+                '{' => {
+                    let token = &self.rest[..1];
+                    self.rest = &self.rest[1..];
+                    self.depth += 1;
+                    if self.depth == 1 {
+                        return Some(token);
+                    }
+                }
+                '(' => {
+                    self.rest = &self.rest[1..];
+                    self.depth += 1;
+                }
+                '}' | ')' => {
+                    self.rest = &self.rest[1..];
+                    self.depth = self.depth.saturating_sub(1);
+                }
+                '"' => self.skip_string(),
+                c if c == '_' || c.is_ascii_alphabetic() => {
+                    let name = self.take_name();
+                    if self.depth == 0 {
+                        return Some(name);
+                    }
+                }
+                c => self.rest = &self.rest[c.len_utf8()..],
+            }
+        }
+    }
+
+    fn peek_name(&mut self) -> Option<&'a str> {
+        self.skip_ignored();
+        let c = self.rest.chars().next()?;
+        (c == '_' || c.is_ascii_alphabetic()).then(|| self.take_name())
+    }
+
+    fn take_name(&mut self) -> &'a str {
+        let end = self
+            .rest
+            .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(self.rest.len());
+        let (name, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        name
+    }
+
+    /// Skips white space, commas and comments.
+    fn skip_ignored(&mut self) {
+        loop {
+            self.rest = self
+                .rest
+                .trim_start_matches(|c: char| c.is_whitespace() || c == ',' || c == '\u{feff}');
+            match self.rest.strip_prefix('#') {
+                Some(comment) => self.rest = comment.find('\n').map_or("", |end| &comment[end..]),
+                None => return,
+            }
+        }
+    }
+
+    fn skip_string(&mut self) {
+        if let Some(block) = self.rest.strip_prefix("\"\"\"") {
+            self.rest = block.find("\"\"\"").map_or("", |end| &block[end + 3..]);
+            return;
+        }
+        let mut chars = self.rest[1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '"' | '\n' => {
+                    self.rest = &self.rest[1 + i + 1..];
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.rest = "";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operation_definition_finds_the_named_operation() {
+        let document = r#"
+            # query Ignored { a }
+            fragment Fields on User { id name(format: "{ query Nope }") }
+            query GetUser($id: ID!) { user(id: $id) { ...Fields } }
+            mutation DeleteUser { deleteUser(id: 1) }
+        "#;
+        assert_eq!(
+            operation_definition(document, None),
+            Some((Some("query"), Some("GetUser".to_string())))
+        );
+        assert_eq!(
+            operation_definition(document, Some("DeleteUser")),
+            Some((Some("mutation"), Some("DeleteUser".to_string())))
+        );
+        assert_eq!(
+            operation_definition(document, Some("Missing")),
+            Some((None, None))
+        );
+    }
+
+    #[test]
+    fn operation_definition_of_anonymous_operations() {
+        assert_eq!(
+            operation_definition("{ user { id } }", None),
+            Some((Some("query"), None))
+        );
+        assert_eq!(
+            operation_definition("subscription { events }", None),
+            Some((Some("subscription"), None))
+        );
+        assert_eq!(operation_definition("", None), None);
+    }
+
+    #[tokio::test]
+    async fn buffer_body_puts_the_body_back() {
+        let (data, body) = buffer_body(
+            Body::from_bytes(Bytes::from_static(b"{\"query\":\"{ a }\"}")),
+            64,
+        )
+        .await;
+        assert_eq!(data.as_deref(), Some(&b"{\"query\":\"{ a }\"}"[..]));
+        assert_eq!(body.into_string().await.unwrap(), "{\"query\":\"{ a }\"}");
+
+        let (data, body) =
+            buffer_body(Body::from_bytes(Bytes::from_static(b"0123456789")), 4).await;
+        assert_eq!(data, None);
+        assert_eq!(body.into_string().await.unwrap(), "0123456789");
+    }
+}
@@ -0,0 +1,88 @@
+//! Propagation of the current context to outgoing requests.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+#[cfg(feature = "reqwest")]
+use crate::propagation::Baggage;
+use crate::propagation::ContextInjector;
+#[cfg(feature = "reqwest")]
+use crate::propagation::TraceState;
+use crate::propagation::W3CTraceContext;
+
+/// The propagation state of the request polled by the middleware.
+pub(crate) struct Propagation {
+    pub(crate) injector: Arc<dyn ContextInjector>,
+    #[cfg(feature = "reqwest")]
+    pub(crate) trace_state: Option<TraceState>,
+    #[cfg(feature = "reqwest")]
+    pub(crate) baggage: Option<Baggage>,
+}
+
+thread_local! {
+    /// The propagation state of the request polled on the current thread.
+    static PROPAGATION: RefCell<Option<Arc<Propagation>>> = const { RefCell::new(None) };
+}
+
+/// Sets the propagation state used by [`inject_context`] until the guard is dropped.
+pub(crate) fn set_propagation(propagation: &Arc<Propagation>) -> PropagationGuard {
+    let previous = PROPAGATION.with(|current| current.replace(Some(propagation.clone())));
+    PropagationGuard { previous }
+}
+
+pub(crate) struct PropagationGuard {
+    previous: Option<Arc<Propagation>>,
+}
+
+impl Drop for PropagationGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        PROPAGATION.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Writes the current local parent context into the headers of an outgoing request, so that the
+/// downstream service continues the trace.
+///
+/// The context is written with the injector configured by
+/// [`FastraceMiddlewareBuilder::with_injector`](crate::FastraceMiddlewareBuilder::with_injector)
+/// when called while the middleware polls the request, and in the W3C `traceparent` header
+/// otherwise, for example from a spawned task. Nothing is written without a local parent.
+///
+/// # Example
+///
+/// ```
+/// use poem::handler;
+/// use poem::http::HeaderMap;
+///
+/// #[handler]
+/// fn proxy() {
+///     let mut headers = HeaderMap::new();
+///     fastrace_poem::inject_context(&mut headers);
+///     // Send the downstream request with the headers...
+/// }
+/// ```
+pub fn inject_context(headers: &mut HeaderMap) {
+    let Some(context) = SpanContext::current_local_parent() else {
+        return;
+    };
+    PROPAGATION.with(|propagation| match &*propagation.borrow() {
+        Some(propagation) => propagation.injector.inject(&context, headers),
+        None => W3CTraceContext::new().inject(&context, headers),
+    });
+}
+
+/// Calls the closure with the propagation state of the request polled on the current thread.
+#[cfg(feature = "reqwest")]
+pub(crate) fn with_propagation<R>(f: impl FnOnce(Option<&Propagation>) -> R) -> R {
+    PROPAGATION.with(|propagation| f(propagation.borrow().as_deref()))
+}
+
+/// Returns the current local parent context encoded as a W3C `traceparent` value, for example to
+/// pass it to a client that does not take headers.
+pub fn current_traceparent() -> Option<String> {
+    SpanContext::current_local_parent().map(|context| context.encode_w3c_traceparent())
+}
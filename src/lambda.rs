@@ -0,0 +1,50 @@
+//! Tracing of requests served on AWS Lambda with poem-lambda.
+
+use fastrace::collector::SpanContext;
+use poem::Request;
+use poem_lambda::Context;
+
+use crate::propagation::xray::amzn_trace_id_defers_sampling;
+use crate::propagation::xray::decode_amzn_trace_id;
+
+/// The cloud provider, which is not exported by the semantic conventions crate without its
+/// experimental attributes.
+const CLOUD_PROVIDER: &str = "cloud.provider";
+
+/// The ARN of the invoked function.
+const CLOUD_RESOURCE_ID: &str = "cloud.resource_id";
+
+/// The id of the invocation, also known as the AWS request id.
+const FAAS_INVOCATION_ID: &str = "faas.invocation_id";
+
+/// The type of the trigger of the invocation.
+const FAAS_TRIGGER: &str = "faas.trigger";
+
+/// Returns the parent context passed to the function by the Lambda runtime, for invocations
+/// traced by AWS X-Ray without an `X-Amzn-Trace-Id` header on the request.
+pub(crate) fn lambda_parent(req: &Request) -> Option<SpanContext> {
+    let context = req.extensions().get::<Context>()?;
+    decode_amzn_trace_id(context.xray_trace_id.as_deref()?)
+}
+
+/// Returns whether the parent context passed by the Lambda runtime leaves the sampling decision to
+/// the function.
+pub(crate) fn lambda_defers_sampling(req: &Request, parent: &SpanContext) -> bool {
+    req.extensions()
+        .get::<Context>()
+        .and_then(|context| context.xray_trace_id.as_deref())
+        .is_some_and(|value| amzn_trace_id_defers_sampling(value, parent))
+}
+
+/// Returns the properties of the invocation, for requests served by poem-lambda.
+pub(crate) fn lambda_properties(req: &Request) -> Vec<(&'static str, String)> {
+    let Some(context) = req.extensions().get::<Context>() else {
+        return Vec::new();
+    };
+    vec![
+        (CLOUD_PROVIDER, "aws".to_string()),
+        (FAAS_TRIGGER, "http".to_string()),
+        (FAAS_INVOCATION_ID, context.request_id.clone()),
+        (CLOUD_RESOURCE_ID, context.invoked_function_arn.clone()),
+    ]
+}
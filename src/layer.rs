@@ -0,0 +1,220 @@
+//! A Tower layer variant of the middleware, for stacks applied with `TowerLayerCompatExt`.
+
+use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::future::Future;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::task::Context;
+use std::task::Poll;
+
+use poem::Endpoint;
+use poem::Error;
+use poem::IntoResponse;
+use poem::Middleware;
+use poem::Request;
+use poem::Response;
+use poem::Result;
+use poem::http::StatusCode;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::FastraceEndpoint;
+use crate::FastraceMiddleware;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// A Tower [`Layer`] tracing requests like [`FastraceMiddleware`](struct@FastraceMiddleware), to
+/// keep its position inside a stack of Tower layers applied to a Poem endpoint with
+/// [`TowerLayerCompatExt`](poem::middleware::TowerLayerCompatExt).
+///
+/// The wrapped services handle [`poem::Request`]s, as the services created by
+/// `TowerLayerCompatExt` do. Errors of the inner service are converted into responses once
+/// recorded, so that their status is kept by the compat adapter; errors other than
+/// [`poem::Error`] are recorded as `500 Internal Server Error`. The errors returned by Poem
+/// endpoints are wrapped by the adapter below the stack, so convert them into responses first,
+/// for example with [`EndpointExt::to_response`](poem::EndpointExt::to_response), to
+/// record their status.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceLayer;
+/// use fastrace_poem::FastraceMiddleware;
+/// use poem::EndpointExt;
+/// use poem::Route;
+/// use poem::get;
+/// use poem::handler;
+/// use poem::middleware::TowerLayerCompatExt;
+///
+/// #[handler]
+/// fn ping() -> &'static str {
+///     "pong"
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let layer = FastraceLayer::new(FastraceMiddleware::new());
+/// let app = Route::new()
+///     .at("/ping", get(ping))
+///     .to_response()
+///     .with(layer.compat());
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct FastraceLayer {
+    middleware: FastraceMiddleware,
+}
+
+impl FastraceLayer {
+    /// Creates a layer tracing requests with the given middleware configuration.
+    pub fn new(middleware: FastraceMiddleware) -> Self {
+        FastraceLayer { middleware }
+    }
+}
+
+impl From<FastraceMiddleware> for FastraceLayer {
+    fn from(middleware: FastraceMiddleware) -> Self {
+        FastraceLayer::new(middleware)
+    }
+}
+
+impl<S> Layer<S> for FastraceLayer
+where
+    S: Service<Request> + Send,
+    S::Response: IntoResponse,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    type Service = FastraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FastraceService {
+            endpoint: Arc::new(
+                self.middleware
+                    .transform(ServiceEndpoint(Mutex::new(inner))),
+            ),
+        }
+    }
+}
+
+/// A Tower [`Service`] created by [`FastraceLayer`].
+pub struct FastraceService<S> {
+    endpoint: Arc<FastraceEndpoint<ServiceEndpoint<S>>>,
+}
+
+impl<S> Clone for FastraceService<S> {
+    fn clone(&self) -> Self {
+        FastraceService {
+            endpoint: self.endpoint.clone(),
+        }
+    }
+}
+
+impl<S> Service<Request> for FastraceService<S>
+where
+    S: Service<Request> + Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        // The inner service is driven to readiness once the request is traced.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let endpoint = self.endpoint.clone();
+        Box::pin(async move { Ok(endpoint.get_response(req).await) })
+    }
+}
+
+/// An endpoint calling the inner service of a [`FastraceService`]. The service is only locked
+/// while it is polled for readiness and called, not while its response is awaited.
+struct ServiceEndpoint<S>(Mutex<S>);
+
+impl<S> Endpoint for ServiceEndpoint<S>
+where
+    S: Service<Request> + Send,
+    S::Response: IntoResponse,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Response> {
+        let mut req = Some(req);
+        let response = poll_fn(|cx| {
+            let mut service = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            match service.poll_ready(cx) {
+                Poll::Ready(Ok(())) => Poll::Ready(Ok(service.call(req.take().unwrap()))),
+                Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await
+        .map_err(into_poem_error)?;
+        response
+            .await
+            .map(IntoResponse::into_response)
+            .map_err(into_poem_error)
+    }
+}
+
+fn into_poem_error(err: impl Into<BoxError>) -> Error {
+    match err.into().downcast::<Error>() {
+        Ok(err) => *err,
+        Err(err) => Error::from_string(err.to_string(), StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Ready;
+    use std::future::ready;
+    use std::io;
+
+    use super::*;
+
+    /// A service failing every request with the error it is given.
+    struct Failing(fn() -> BoxError);
+
+    impl Service<Request> for Failing {
+        type Response = Response;
+        type Error = BoxError;
+        type Future = Ready<Result<Response, BoxError>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Request) -> Self::Future {
+            ready(Err((self.0)()))
+        }
+    }
+
+    async fn status_of(error: fn() -> BoxError) -> StatusCode {
+        let mut service = FastraceLayer::default().layer(Failing(error));
+        let resp = service.call(Request::default()).await.unwrap();
+        resp.status()
+    }
+
+    #[tokio::test]
+    async fn service_errors_become_responses() {
+        assert_eq!(
+            status_of(|| Error::from_status(StatusCode::BAD_GATEWAY).into()).await,
+            StatusCode::BAD_GATEWAY
+        );
+        assert_eq!(
+            status_of(|| io::Error::other("connection reset").into()).await,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
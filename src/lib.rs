@@ -1,16 +1,97 @@
 #![doc = include_str!("../README.md")]
+#![cfg_attr(docsrs, feature(doc_auto_cfg))]
+// The tracing path of the middleware is compiled out with the `disabled` feature, leaving the code
+// only it uses unused.
+#![cfg_attr(feature = "disabled", allow(dead_code, unused_imports))]
 
-use fastrace::prelude::*;
-use opentelemetry_semantic_conventions::trace::HTTP_REQUEST_METHOD;
-use opentelemetry_semantic_conventions::trace::HTTP_RESPONSE_STATUS_CODE;
-use opentelemetry_semantic_conventions::trace::HTTP_ROUTE;
-use opentelemetry_semantic_conventions::trace::URL_PATH;
-use poem::Endpoint;
-use poem::IntoResponse;
-use poem::Middleware;
-use poem::Request;
-use poem::Response;
-use poem::Result;
+mod attributes;
+mod body;
+mod builder;
+#[cfg(feature = "reqwest")]
+mod client;
+#[cfg(feature = "serde")]
+mod config;
+mod customizer;
+mod env;
+mod extract;
+pub mod filter;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod inject;
+#[cfg(feature = "lambda")]
+mod lambda;
+#[cfg(feature = "tower")]
+mod layer;
+#[cfg(feature = "log")]
+mod logger;
+mod middleware;
+#[cfg(feature = "multipart")]
+mod multipart;
+mod naming;
+#[cfg(feature = "openapi")]
+mod openapi;
+#[cfg(feature = "opentelemetry")]
+mod otel;
+pub mod propagation;
+mod response;
+mod route;
+#[cfg(feature = "grpc")]
+mod rpc;
+mod sampling;
+#[cfg(feature = "sse")]
+mod sse;
+mod static_files;
+#[cfg(feature = "test")]
+pub mod test;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+#[cfg(feature = "macros")]
+pub use fastrace_poem_macros::traced_handler;
+
+pub use crate::attributes::SemconvMode;
+pub use crate::attributes::SpanStatus;
+pub use crate::builder::FastraceMiddlewareBuilder;
+pub use crate::builder::UnsampledParent;
+#[cfg(feature = "reqwest")]
+pub use crate::client::RequestBuilderExt;
+#[cfg(feature = "serde")]
+pub use crate::config::FastraceConfig;
+pub use crate::customizer::SpanCustomizer;
+pub use crate::extract::CurrentSpan;
+pub use crate::extract::TraceId;
+pub use crate::extract::Traced;
+pub use crate::inject::current_traceparent;
+pub use crate::inject::inject_context;
+#[cfg(feature = "tower")]
+pub use crate::layer::FastraceLayer;
+#[cfg(feature = "tower")]
+pub use crate::layer::FastraceService;
+#[cfg(feature = "log")]
+pub use crate::logger::TraceIdLogger;
+pub use crate::middleware::FastraceEndpoint;
+pub use crate::middleware::FastraceMiddleware;
+#[cfg(feature = "multipart")]
+pub use crate::multipart::TracedField;
+#[cfg(feature = "multipart")]
+pub use crate::multipart::TracedMultipart;
+pub use crate::naming::PathNormalization;
+pub use crate::naming::SpanName;
+pub use crate::naming::SpanNameEndpoint;
+#[cfg(feature = "opentelemetry")]
+pub use crate::otel::OtelContext;
+#[cfg(feature = "opentelemetry")]
+pub use crate::otel::from_otel_span_context;
+#[cfg(feature = "opentelemetry")]
+pub use crate::otel::to_otel_span_context;
+pub use crate::response::ResponseExt;
+pub use crate::sampling::SamplingDecision;
+#[cfg(feature = "sse")]
+pub use crate::sse::TracedEvents;
+pub use crate::static_files::TracedStaticFiles;
+pub use crate::static_files::TracedStaticFilesEndpoint;
+#[cfg(feature = "websocket")]
+pub use crate::websocket::TracedWebSocket;
 
 /// The standard [W3C Trace Context](https://www.w3.org/TR/trace-context/) header name for passing trace information.
 ///
@@ -18,81 +99,18 @@ use poem::Result;
 /// the W3C Trace Context specification.
 pub const TRACEPARENT_HEADER: &str = "traceparent";
 
-/// Middleware for integrating fastrace distributed tracing with Poem web framework.
-///
-/// This middleware extracts trace context from incoming HTTP requests and creates
-/// a new root span for each request, properly linking it to any parent context
-/// that might exist from upstream services.
-///
-/// # Example
-///
-/// ```
-/// use fastrace_poem::FastraceMiddleware;
-/// use poem::Route;
-/// use poem::get;
-/// use poem::handler;
-///
-/// let app = Route::new().at("/ping", get(ping)).with(FastraceMiddleware);
-/// ```
-#[derive(Default)]
-pub struct FastraceMiddleware;
-
-impl<E: Endpoint> Middleware<E> for FastraceMiddleware {
-    type Output = FastraceEndpoint<E>;
-
-    fn transform(&self, ep: E) -> Self::Output {
-        FastraceEndpoint { inner: ep }
-    }
-}
-
-/// An endpoint wrapper created by [`FastraceMiddleware`].
-///
-/// This type is created by the `FastraceMiddleware` and handles the extraction
-/// of trace context from requests and the creation of spans around request handlers.
-pub struct FastraceEndpoint<E> {
-    inner: E,
-}
-
-impl<E: Endpoint> Endpoint for FastraceEndpoint<E> {
-    type Output = Response;
-
-    async fn call(&self, req: Request) -> Result<Self::Output> {
-        let headers = req.headers();
-        let parent = headers.get(TRACEPARENT_HEADER).and_then(|traceparent| {
-            SpanContext::decode_w3c_traceparent(traceparent.to_str().ok()?)
-        });
-
-        let span = if let Some(parent) = parent {
-            // TODO: use low cardinality route once poem supports it.
-            let name = format!("{} {}", req.method().as_str(), req.uri().path());
-
-            let root = Span::root(name, parent);
+/// The standard [W3C Trace Context](https://www.w3.org/TR/trace-context/) header name for passing
+/// vendor-specific trace information along with [`TRACEPARENT_HEADER`].
+pub const TRACESTATE_HEADER: &str = "tracestate";
 
-            root.add_properties(|| {
-                [
-                    (HTTP_REQUEST_METHOD, req.method().to_string()),
-                    (URL_PATH, req.uri().path().to_string()),
-                    // TODO: use low cardinality route once poem supports it.
-                    (HTTP_ROUTE, req.uri().path().to_string()),
-                ]
-            });
+/// The standard [W3C Baggage](https://www.w3.org/TR/baggage/) header name for passing
+/// application-defined key-value pairs.
+pub const BAGGAGE_HEADER: &str = "baggage";
 
-            root
-        } else {
-            Span::noop()
-        };
+/// The [W3C Trace Context Level 2](https://www.w3.org/TR/trace-context-2/#traceresponse-header)
+/// response header name for returning the server's trace context to the caller.
+pub const TRACERESPONSE_HEADER: &str = "traceresponse";
 
-        async {
-            let resp = self.inner.call(req).await?.into_response();
-            LocalSpan::add_property(|| {
-                (
-                    HTTP_RESPONSE_STATUS_CODE,
-                    resp.status().as_u16().to_string(),
-                )
-            });
-            Ok(resp)
-        }
-        .in_span(span)
-        .await
-    }
-}
+/// The conventional response header name for returning the trace id of the request, see
+/// [`FastraceMiddlewareBuilder::with_trace_id_header`].
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
@@ -0,0 +1,109 @@
+//! Correlation of `log` records with the trace of the request.
+
+use fastrace::collector::SpanContext;
+use log::Log;
+use log::Metadata;
+use log::Record;
+use log::kv::Error;
+use log::kv::Key;
+use log::kv::Source;
+use log::kv::Value;
+use log::kv::VisitSource;
+
+/// The key of the trace id added to log records.
+const TRACE_ID_KEY: &str = "trace_id";
+
+/// The key of the span id added to log records.
+const SPAN_ID_KEY: &str = "span_id";
+
+/// A [`Log`] wrapper adding the `trace_id` and `span_id` key-values to the records logged while
+/// a traced request is handled.
+///
+/// The ids are those of the local parent span set by
+/// [`FastraceMiddleware`](struct@crate::FastraceMiddleware) while it polls the inner endpoint, so
+/// records logged by handlers, including after an `.await`, carry the ids of their request. Records
+/// logged outside of a traced request are passed on unchanged. The wrapped logger must print the
+/// key-values of records, or read the id with [`TraceId::current`](crate::TraceId::current) in its
+/// format.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::TraceIdLogger;
+/// use log::LevelFilter;
+/// use log::Log;
+/// use log::Metadata;
+/// use log::Record;
+///
+/// struct StderrLogger;
+///
+/// impl Log for StderrLogger {
+///     fn enabled(&self, _metadata: &Metadata) -> bool {
+///         true
+///     }
+///
+///     fn log(&self, record: &Record) {
+///         let trace_id = record.key_values().get("trace_id".into());
+///         eprintln!("{} {:?} {}", record.level(), trace_id, record.args());
+///     }
+///
+///     fn flush(&self) {}
+/// }
+///
+/// static LOGGER: TraceIdLogger<StderrLogger> = TraceIdLogger::new(StderrLogger);
+///
+/// log::set_logger(&LOGGER).unwrap();
+/// log::set_max_level(LevelFilter::Info);
+/// ```
+pub struct TraceIdLogger<L> {
+    inner: L,
+}
+
+impl<L: Log> TraceIdLogger<L> {
+    /// Wraps the logger to add the trace and span ids to its records.
+    pub const fn new(inner: L) -> Self {
+        TraceIdLogger { inner }
+    }
+}
+
+impl<L: Log> Log for TraceIdLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let Some(context) = SpanContext::current_local_parent() else {
+            return self.inner.log(record);
+        };
+        let source = ContextSource {
+            inner: record.key_values(),
+            context,
+        };
+        self.inner
+            .log(&record.to_builder().key_values(&source).build());
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// The key-values of a record followed by the ids of the span context.
+struct ContextSource<'a> {
+    inner: &'a dyn Source,
+    context: SpanContext,
+}
+
+impl Source for ContextSource<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        self.inner.visit(visitor)?;
+        visitor.visit_pair(
+            Key::from_str(TRACE_ID_KEY),
+            Value::from_display(&self.context.trace_id),
+        )?;
+        visitor.visit_pair(
+            Key::from_str(SPAN_ID_KEY),
+            Value::from_display(&self.context.span_id),
+        )
+    }
+}
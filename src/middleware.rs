@@ -0,0 +1,1100 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::future::poll_fn;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::task::Poll;
+use std::time::Instant;
+
+use fastrace::prelude::*;
+use opentelemetry_semantic_conventions::resource::SERVICE_NAME;
+use opentelemetry_semantic_conventions::trace::HTTP_RESPONSE_HEADER;
+use opentelemetry_semantic_conventions::trace::HTTP_RESPONSE_STATUS_CODE;
+use opentelemetry_semantic_conventions::trace::HTTP_ROUTE;
+use poem::Endpoint;
+use poem::Error;
+use poem::IntoResponse;
+use poem::Middleware;
+use poem::PathPattern;
+use poem::Request;
+use poem::Response;
+use poem::Result;
+use poem::http::HeaderMap;
+use poem::http::HeaderName;
+use poem::http::HeaderValue;
+use poem::http::Method;
+use poem::http::StatusCode;
+use poem::http::header;
+
+use crate::SamplingDecision;
+use crate::SpanStatus;
+use crate::TRACEPARENT_HEADER;
+use crate::TRACERESPONSE_HEADER;
+use crate::attributes::DEFAULT_MAX_ATTRIBUTE_LEN;
+use crate::attributes::HTTP_REQUEST_BODY_SIZE;
+use crate::attributes::HTTP_RESPONSE_BODY_SIZE;
+use crate::attributes::MaxAttributeLen;
+use crate::attributes::QUEUE_TIME_PROPERTY;
+use crate::attributes::REQUEST_TIMEOUT_PROPERTY;
+use crate::attributes::exception_properties;
+use crate::attributes::header_properties;
+use crate::attributes::is_known_method;
+use crate::attributes::method_name;
+use crate::attributes::panic_properties;
+use crate::attributes::queue_time;
+use crate::attributes::request_properties;
+use crate::attributes::request_timeout;
+use crate::attributes::truncate;
+use crate::body::observe_body;
+use crate::builder::Config;
+use crate::builder::FastraceMiddlewareBuilder;
+use crate::builder::UnsampledParent;
+use crate::extract::CurrentSpan;
+use crate::filter::TracingSwitch;
+#[cfg(feature = "graphql")]
+use crate::graphql::graphql_operation;
+use crate::inject::Propagation;
+use crate::inject::set_propagation;
+#[cfg(feature = "lambda")]
+use crate::lambda::lambda_defers_sampling;
+#[cfg(feature = "lambda")]
+use crate::lambda::lambda_parent;
+#[cfg(feature = "lambda")]
+use crate::lambda::lambda_properties;
+use crate::naming::RouteNames;
+use crate::naming::SpanName;
+use crate::naming::template_path;
+use crate::propagation::Baggage;
+use crate::propagation::ContextExtractor;
+use crate::propagation::ContextInjector;
+use crate::propagation::TraceState;
+use crate::response::PendingProperties;
+use crate::route::REDACTED;
+use crate::route::ROUTE_PARAMS_PREFIX;
+use crate::route::route_params;
+#[cfg(feature = "grpc")]
+use crate::rpc::GrpcMethod;
+#[cfg(feature = "grpc")]
+use crate::rpc::grpc_status;
+#[cfg(feature = "grpc")]
+use crate::rpc::grpc_status_property;
+#[cfg(feature = "grpc")]
+use crate::rpc::is_grpc_server_error;
+use crate::sampling::RateLimiter;
+use crate::sampling::sample_ratio;
+
+/// The span property recording the incoming `tracestate` header.
+const TRACESTATE_PROPERTY: &str = "w3c.tracestate";
+
+/// The span property listing parent contexts that were received but not used.
+const CONFLICTING_PARENTS_PROPERTY: &str = "trace.conflicting_parents";
+
+/// The span property marking requests traced because of the force-trace header.
+const FORCED_PROPERTY: &str = "trace.forced";
+
+/// The span property marking failed requests, understood by most tracing backends.
+const ERROR_PROPERTY: &str = "error";
+
+/// The span property recording the OpenTelemetry status of the span.
+const OTEL_STATUS_CODE_PROPERTY: &str = "otel.status_code";
+
+/// The span property recording the start of the body of error responses.
+const RESPONSE_BODY_SNIPPET_PROPERTY: &str = "http.response.body.snippet";
+
+/// The span property recording the time from the return of the endpoint to the first chunk of
+/// the response body.
+const RESPONSE_TTFB_PROPERTY: &str = "http.response.ttfb_ms";
+
+/// The response header exposing server metrics to browser performance tooling.
+const SERVER_TIMING_HEADER: &str = "server-timing";
+
+/// The span property marking requests exceeding the slow threshold.
+const SLOW_REQUEST_PROPERTY: &str = "slow_request";
+
+/// The event property recording how long after the deadline of the client the response was
+/// returned.
+const DEADLINE_EXCEEDED_BY_PROPERTY: &str = "exceeded_by_ms";
+
+/// The span property recording the request id.
+const REQUEST_ID_PROPERTY: &str = "http.request.id";
+
+/// The name of the event periodically added to the root span of long-running requests.
+const HEARTBEAT_EVENT_NAME: &str = "still running";
+
+/// The property recording how long the inner endpoint has been running.
+const HEARTBEAT_ELAPSED_PROPERTY: &str = "elapsed_ms";
+
+/// The name of the child span around the call of the inner endpoint.
+const HANDLER_SPAN_NAME: &str = "handler";
+
+/// The name of the phase span recording the request before the call of the inner endpoint.
+const SETUP_SPAN_NAME: &str = "setup";
+
+/// The name of the phase span sending the response body.
+const RESPONSE_WRITE_SPAN_NAME: &str = "response.write";
+
+/// Middleware for integrating fastrace distributed tracing with Poem web framework.
+///
+/// This middleware extracts trace context from incoming HTTP requests and creates
+/// a new root span for each request, properly linking it to any parent context
+/// that might exist from upstream services.
+///
+/// The parent context is read from the W3C `traceparent` header, falling back to the
+/// B3 single `b3` header, the B3 multi-header format (`X-B3-TraceId`, `X-B3-SpanId`,
+/// `X-B3-Sampled`, ...), Jaeger's `uber-trace-id` header, the Datadog `x-datadog-*` headers and
+/// Google Cloud's `X-Cloud-Trace-Context` header. With the `xray`, `skywalking` and `grpc` features
+/// enabled, the AWS X-Ray `X-Amzn-Trace-Id` header, the SkyWalking `sw8` header and the gRPC
+/// `grpc-trace-bin` metadata are accepted as well.
+///
+/// The matched route pattern, such as `/users/:id`, is recorded as the `http.route` property, and
+/// the raw path as `url.path`. The span is named `{method} {route}` when the middleware wraps the
+/// endpoints inside a [`Route`](poem::Route), for example with `get(handler).with(..)`. When it
+/// wraps the `Route` itself, the route is only known once the response is returned, so the span is
+/// named after the method alone.
+///
+/// With the `grpc` feature, the spans of gRPC requests are named `{package.Service}/{Method}`
+/// instead, with the `rpc.system`, `rpc.service` and `rpc.method` properties. The
+/// `rpc.grpc.status_code` property is recorded when the status is sent in the response headers,
+/// as for calls failing before sending any message, and marks the span as failed for server
+/// errors.
+///
+/// With the `lambda` feature, requests served by poem-lambda continue the X-Ray trace passed by
+/// the Lambda runtime when no header carries a parent context, and record the `faas.invocation_id`
+/// and `cloud.resource_id` properties from the invocation.
+///
+/// With the `tracing` feature, a `tracing` span named `request` with the `trace_id` and `span_id`
+/// of the root span is entered while the request is handled, so the events logged with `tracing`
+/// by the handler carry the ids of the trace.
+///
+/// The files served by Poem's static file endpoints are recorded when they are wrapped with
+/// [`TracedStaticFiles`](crate::TracedStaticFiles).
+///
+/// The [`SpanContext`] of the root span is stored in the request extensions, so handlers and
+/// inner middlewares can read the trace and span id with `Option<Data<&SpanContext>>`, for example
+/// for logging or audit records, or with the [`TraceId`](crate::TraceId) extractor. It is not set
+/// when the request is not traced.
+///
+/// Use [`FastraceMiddleware::builder`] to change how the context is read and how requests are
+/// traced.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use poem::EndpointExt;
+/// use poem::Route;
+/// use poem::get;
+/// use poem::handler;
+///
+/// #[handler]
+/// fn ping() -> &'static str {
+///     "pong"
+/// }
+///
+/// let app = Route::new()
+///     .at("/ping", get(ping))
+///     .with(FastraceMiddleware::new());
+/// ```
+#[derive(Clone)]
+pub struct FastraceMiddleware {
+    // `None` for the `FastraceMiddleware` constant, which is configured when it is applied.
+    pub(crate) configured: Option<Configured>,
+}
+
+/// The middleware with the default configuration, like [`FastraceMiddleware::new`], so that
+/// `.with(FastraceMiddleware)` keeps working from when the middleware was a unit struct.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use poem::EndpointExt;
+/// use poem::Route;
+/// use poem::get;
+/// use poem::handler;
+///
+/// #[handler]
+/// fn ping() -> &'static str {
+///     "pong"
+/// }
+///
+/// let app = Route::new().at("/ping", get(ping)).with(FastraceMiddleware);
+/// ```
+#[allow(non_upper_case_globals)]
+pub const FastraceMiddleware: FastraceMiddleware = FastraceMiddleware { configured: None };
+
+#[derive(Clone)]
+pub(crate) struct Configured {
+    pub(crate) extractor: Arc<dyn ContextExtractor>,
+    pub(crate) injector: Arc<dyn ContextInjector>,
+    pub(crate) config: Arc<Config>,
+}
+
+impl Default for FastraceMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FastraceMiddleware {
+    /// Creates a middleware with the default configuration.
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    /// Creates a [`FastraceMiddlewareBuilder`] to configure the middleware.
+    pub fn builder() -> FastraceMiddlewareBuilder {
+        FastraceMiddlewareBuilder::default()
+    }
+
+    /// Creates a middleware configured from the standard OpenTelemetry environment variables, see
+    /// [`FastraceMiddlewareBuilder::from_env`].
+    pub fn from_env() -> Self {
+        FastraceMiddlewareBuilder::from_env().build()
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for FastraceMiddleware {
+    type Output = FastraceEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        let Configured {
+            extractor,
+            injector,
+            config,
+        } = match &self.configured {
+            Some(configured) => configured.clone(),
+            None => FastraceMiddlewareBuilder::default().configure(),
+        };
+        FastraceEndpoint {
+            inner: ep,
+            extractor,
+            injector,
+            config,
+            route_names: RouteNames::default(),
+        }
+    }
+}
+
+/// An endpoint wrapper created by [`FastraceMiddleware`](struct@FastraceMiddleware).
+///
+/// This type is created by the `FastraceMiddleware` and handles the extraction
+/// of trace context from requests and the creation of spans around request handlers.
+pub struct FastraceEndpoint<E> {
+    inner: E,
+    extractor: Arc<dyn ContextExtractor>,
+    injector: Arc<dyn ContextInjector>,
+    config: Arc<Config>,
+    route_names: RouteNames,
+}
+
+impl<E: Endpoint> FastraceEndpoint<E> {
+    fn is_forced(&self, req: &Request) -> bool {
+        let Some(name) = &self.config.force_trace_header else {
+            return false;
+        };
+        req.headers()
+            .get(name)
+            .is_some_and(|value| value == "1" || value.as_bytes().eq_ignore_ascii_case(b"true"))
+    }
+
+    /// Returns whether the parent context is the one of the request's `traceparent` header.
+    fn is_w3c_parent(&self, req: &Request, parent: &SpanContext) -> bool {
+        self.config
+            .w3c
+            .extract(req.headers())
+            .is_some_and(|w3c| w3c.trace_id == parent.trace_id && w3c.span_id == parent.span_id)
+    }
+
+    fn span_name(&self, req: &Request, route: Option<&PathPattern>) -> Cow<'static, str> {
+        let name = self.unlimited_span_name(req, route);
+        match &self.config.name_limiter {
+            Some(limiter) if !limiter.admit(&name) => {
+                Cow::Owned(format!("HTTP {}", method_name(req.method())))
+            }
+            _ => name,
+        }
+    }
+
+    fn unlimited_span_name(&self, req: &Request, route: Option<&PathPattern>) -> Cow<'static, str> {
+        if let Some(name) = req.data::<SpanName>() {
+            return name.0.clone();
+        }
+        if let Some(name) = self.route_names.find(req.method(), req.uri().path()) {
+            return name;
+        }
+
+        #[cfg(feature = "openapi")]
+        if let Some(operation) = self
+            .config
+            .openapi
+            .find(req.method(), &self.naming_path(req))
+        {
+            return Cow::Owned(operation.name().to_string());
+        }
+
+        if let Some(span_name) = &self.config.span_name {
+            return Cow::Owned(span_name(req));
+        }
+
+        #[cfg(feature = "grpc")]
+        if let Some(method) = GrpcMethod::from_request(req, original_path(req)) {
+            return Cow::Owned(method.span_name());
+        }
+
+        // Spans of requests with a nonstandard method are named `HTTP`, as recommended by the
+        // semantic conventions, to bound the cardinality of the name.
+        let method = if is_known_method(req.method()) {
+            method_name(req.method())
+        } else {
+            "HTTP"
+        };
+        match route {
+            Some(route) => Cow::Owned(format!("{method} {}", route.0)),
+            None => Cow::Borrowed(method),
+        }
+    }
+
+    fn record_route(&self, span: &Span, route: Option<&PathPattern>, path: Option<&str>) {
+        let Some(route) = route else {
+            return;
+        };
+        span.add_property(|| (HTTP_ROUTE, route.0.to_string()));
+
+        if let Some(path) = path {
+            span.add_properties(|| {
+                route_params(&route.0, path)
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let redacted = self.config.redacted_route_params.iter().any(|n| n == name);
+                        let mut value = if redacted {
+                            Cow::Borrowed(REDACTED)
+                        } else {
+                            Cow::Owned(value)
+                        };
+                        truncate(&mut value, self.config.max_attribute_len);
+                        (format!("{ROUTE_PARAMS_PREFIX}{name}"), value)
+                    })
+            });
+        }
+    }
+
+    /// The path span names are derived from.
+    fn naming_path<'a>(&self, req: &'a Request) -> Cow<'a, str> {
+        self.config.path_normalization.normalize(req.uri().path())
+    }
+
+    /// Returns the context of a new trace, from the configured generator or random.
+    fn new_context(&self) -> SpanContext {
+        match &self.config.id_generator {
+            Some(generator) => generator(),
+            None => SpanContext::random(),
+        }
+    }
+
+    /// Returns the request id, generating one and setting it on the request when enabled.
+    fn request_id(&self, req: &mut Request) -> Option<(HeaderName, HeaderValue)> {
+        let header = HeaderName::try_from(self.config.request_id_header.as_deref()?).ok()?;
+        if let Some(id) = req.headers().get(&header) {
+            return Some((header, id.clone()));
+        }
+        if !self.config.generate_request_id {
+            return None;
+        }
+        // Not taken from the id generator, whose sequence is reserved for new traces.
+        let id = HeaderValue::try_from(SpanContext::random().trace_id.to_string()).ok()?;
+        req.headers_mut().insert(header.clone(), id.clone());
+        Some((header, id))
+    }
+
+    /// Returns the response headers carrying the context of the root span, which are empty for
+    /// requests that are not traced.
+    fn context_headers(&self, span: &Span) -> Vec<(HeaderName, HeaderValue)> {
+        let mut headers = Vec::new();
+        let Some(context) = SpanContext::from_span(span) else {
+            return headers;
+        };
+        if self.config.traceresponse || self.config.response_traceparent {
+            if let Ok(value) = HeaderValue::try_from(context.encode_w3c_traceparent()) {
+                if self.config.traceresponse {
+                    headers.push((HeaderName::from_static(TRACERESPONSE_HEADER), value.clone()));
+                }
+                if self.config.response_traceparent {
+                    headers.push((HeaderName::from_static(TRACEPARENT_HEADER), value));
+                }
+            }
+        }
+        if let Some(header) = &self.config.trace_id_header {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(header.as_str()),
+                HeaderValue::try_from(context.trace_id.to_string()),
+            ) {
+                headers.push((name, value));
+            }
+        }
+        headers
+    }
+
+    fn record_status_code(&self, span: &Span, status: StatusCode) {
+        span.add_properties(|| {
+            self.config
+                .semconv
+                .rename([(HTTP_RESPONSE_STATUS_CODE.into(), status.as_str().to_owned())])
+        });
+    }
+
+    /// Records the status of the span, classifying the response status with the copy of the
+    /// request taken for the status classifier, if any. The response headers are only read for the
+    /// `grpc-status` header.
+    #[cfg_attr(not(feature = "grpc"), allow(unused_variables))]
+    fn record_status(
+        &self,
+        span: &Span,
+        status: StatusCode,
+        headers: &HeaderMap,
+        head: Option<&Request>,
+    ) {
+        let status = match (&self.config.status_classifier, head) {
+            (Some(classifier), Some(head)) => classifier(head, status),
+            #[cfg(feature = "grpc")]
+            _ if grpc_status(headers).is_some_and(is_grpc_server_error) => SpanStatus::Error,
+            _ => SpanStatus::from_status(status),
+        };
+        match status {
+            SpanStatus::Unset => {}
+            SpanStatus::Ok => span.add_property(|| (OTEL_STATUS_CODE_PROPERTY, "OK")),
+            SpanStatus::Error => span.add_properties(|| {
+                [
+                    (ERROR_PROPERTY, "true"),
+                    (OTEL_STATUS_CODE_PROPERTY, "ERROR"),
+                ]
+            }),
+        }
+    }
+
+    /// Marks the span as slow when it exceeds the slow threshold, returning whether it does.
+    fn record_slow(&self, span: &Span) -> bool {
+        let slow = match (self.config.slow_threshold, span.elapsed()) {
+            (Some(threshold), Some(elapsed)) => elapsed > threshold,
+            _ => false,
+        };
+        if slow {
+            span.add_property(|| (SLOW_REQUEST_PROPERTY, "true"));
+        }
+        slow
+    }
+
+    /// Adds an event when the response is returned after the deadline of the client.
+    fn record_deadline(&self, span: &Span, deadline: Option<Instant>) {
+        let now = Instant::now();
+        if let Some(deadline) =
+            deadline.filter(|deadline| self.config.deadline_event && now > *deadline)
+        {
+            span.add_event(Event::new("deadline exceeded").with_property(|| {
+                let millis = now.duration_since(deadline).as_millis();
+                (DEADLINE_EXCEEDED_BY_PROPERTY, millis.to_string())
+            }));
+        }
+    }
+
+    /// Records the route and the status of the response, along with its properties.
+    fn record_response(
+        &self,
+        span: &Span,
+        resp: &mut Response,
+        route: Option<&PathPattern>,
+        params_path: Option<&str>,
+        head: Option<&Request>,
+    ) {
+        let route = resp.data::<PathPattern>().or(route);
+        self.record_route(span, route, params_path);
+        self.record_status_code(span, resp.status());
+        self.record_status(span, resp.status(), resp.headers(), head);
+        #[cfg(feature = "grpc")]
+        if let Some(code) = grpc_status(resp.headers()) {
+            span.add_property(|| grpc_status_property(code));
+        }
+        if !self.config.capture_response_headers.is_empty() {
+            span.add_properties(|| {
+                header_properties(
+                    HTTP_RESPONSE_HEADER,
+                    &self.config.capture_response_headers,
+                    resp.headers(),
+                )
+                .map(|(key, mut value)| {
+                    truncate(&mut value, self.config.max_attribute_len);
+                    (key, value)
+                })
+            });
+        }
+        if let Some(PendingProperties(properties)) = resp.extensions_mut().remove() {
+            span.add_properties(|| properties);
+        }
+        for customizer in &self.config.customizers {
+            customizer.on_response(span, resp);
+        }
+    }
+
+    /// Records the deadline and the slowness of the request, and cancels the span when the tail
+    /// decision rejects the status it ended with.
+    fn decide_retention(&self, span: &Span, status: StatusCode, deadline: Option<Instant>) {
+        self.record_deadline(span, deadline);
+        let keep_slow = self.record_slow(span) && self.config.keep_slow_requests;
+        if let (Some(decision), Some(elapsed)) = (&self.config.tail_decision, span.elapsed()) {
+            if !keep_slow && !decision(status, elapsed) {
+                span.cancel();
+            }
+        }
+    }
+
+    fn is_switched_off(&self) -> bool {
+        !self
+            .config
+            .switch
+            .as_ref()
+            .is_none_or(TracingSwitch::is_enabled)
+    }
+
+    fn is_skipped(&self, req: &Request) -> bool {
+        let path = req.uri().path();
+        self.config.skip_methods.contains(req.method())
+            || (self.config.skip_preflight && is_preflight(req))
+            || self.config.skip_paths.iter().any(|glob| glob.matches(path))
+            || !self
+                .config
+                .filters
+                .iter()
+                .all(|filter| filter.should_trace(req))
+    }
+
+    /// Applies the sample ratio and the rate limit to a trace started by this service.
+    fn keep_new_trace(&self, trace_id: TraceId) -> bool {
+        sample_ratio(trace_id, self.config.sample_ratio)
+            && self
+                .config
+                .rate_limiter
+                .as_ref()
+                .is_none_or(RateLimiter::try_acquire)
+    }
+}
+
+impl<E: Endpoint> Endpoint for FastraceEndpoint<E> {
+    type Output = Response;
+
+    #[cfg(feature = "disabled")]
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        self.inner.call(req).await.map(IntoResponse::into_response)
+    }
+
+    #[cfg(not(feature = "disabled"))]
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        // Forced requests are traced regardless of the filters, but not while tracing is switched
+        // off.
+        let forced = self.is_forced(&req);
+        if self.is_switched_off() || (!forced && self.is_skipped(&req)) {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        }
+
+        let start = self.config.server_timing.then(Instant::now);
+        let request_id = self.request_id(&mut req);
+        let queue_time = self.config.queue_time.then(|| queue_time(&req)).flatten();
+        let timeout = self
+            .config
+            .deadline
+            .then(|| request_timeout(&req))
+            .flatten();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        // The request is moved into the endpoint, so the status classifier is handed a copy.
+        let head = self
+            .config
+            .status_classifier
+            .as_ref()
+            .map(|_| request_head(&req));
+
+        let mut conflicting_parents = Vec::new();
+        let parent = if self.config.record_conflicting_parents {
+            let mut parents = self.extractor.extract_all(req.headers()).into_iter();
+            let parent = parents.next();
+            if let Some(parent) = parent {
+                conflicting_parents = parents
+                    .filter(|other| {
+                        other.trace_id != parent.trace_id || other.span_id != parent.span_id
+                    })
+                    .collect();
+            }
+            parent
+        } else {
+            self.extractor.extract(req.headers())
+        };
+
+        #[cfg(feature = "lambda")]
+        let parent = parent.or_else(|| lambda_parent(&req));
+
+        let invalid_traceparent = match parent {
+            None if self.config.new_root_on_invalid_parent => req
+                .headers()
+                .get(self.config.w3c.header())
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned()),
+            _ => None,
+        };
+
+        // The vendor state belongs to the `traceparent` header, and is dropped when the parent was
+        // read from another format, such as when `traceparent` is invalid.
+        let trace_state = match parent {
+            Some(parent) if self.is_w3c_parent(&req, &parent) => TraceState::extract(req.headers()),
+            _ => None,
+        };
+        let baggage = Baggage::extract(req.headers());
+
+        // A parent leaving the sampling decision to this service is sampled like a new trace, as
+        // are all parents when resampling them.
+        #[cfg(feature = "xray")]
+        let deferred = parent.is_some_and(|parent| defers_sampling(&req, &parent));
+        #[cfg(not(feature = "xray"))]
+        let deferred = false;
+        let resampled = parent.is_some() && (deferred || self.config.resample_parents);
+
+        let new_root = parent.is_none();
+        let parent = parent.or_else(|| invalid_traceparent.as_ref().map(|_| self.new_context()));
+        let parent = parent.or_else(|| self.config.trace_all_requests.then(|| self.new_context()));
+
+        let parent = if forced {
+            Some(parent.unwrap_or_else(|| self.new_context()).sampled(true))
+        } else if let Some(sampler) = &self.config.sampler {
+            let parent = parent.unwrap_or_else(|| self.new_context());
+            match sampler(&req) {
+                SamplingDecision::Record => Some(parent.sampled(true)),
+                SamplingDecision::PropagateOnly => Some(parent.sampled(false)),
+                SamplingDecision::Drop => None,
+            }
+        } else {
+            parent
+                .map(|parent| {
+                    if resampled {
+                        parent.sampled(true)
+                    } else {
+                        parent
+                    }
+                })
+                .filter(|parent| {
+                    parent.sampled || self.config.unsampled_parent == UnsampledParent::Propagate
+                })
+        };
+
+        let parent = parent.map(|parent| {
+            if (new_root || resampled) && !forced && parent.sampled {
+                parent.sampled(self.keep_new_trace(parent.trace_id))
+            } else {
+                parent
+            }
+        });
+
+        // The matched route is only known here when the middleware is applied inside a `Route`,
+        // otherwise it is read from the response.
+        let route = req.data::<PathPattern>().cloned().or_else(|| {
+            self.config
+                .path_templating
+                .then(|| PathPattern(template_path(&self.naming_path(&req)).into()))
+        });
+
+        #[cfg(feature = "graphql")]
+        let graphql = match &self.config.graphql_path {
+            Some(path) if parent.is_some() && req.uri().path() == path => {
+                graphql_operation(&mut req).await
+            }
+            _ => None,
+        };
+
+        let mut setup_span = None;
+        let span = if let Some(parent) = parent {
+            let name = self.span_name(&req, route.as_ref());
+            #[cfg(feature = "graphql")]
+            let name = match graphql.as_ref().and_then(|op| op.name.as_deref()) {
+                Some(operation_name) => Cow::Owned(format!("{name} {operation_name}")),
+                None => name,
+            };
+            let root = Span::root(name, parent);
+            if self.config.phase_spans {
+                setup_span = Some(Span::enter_with_parent(SETUP_SPAN_NAME, &root));
+            }
+
+            root.add_properties(|| request_properties(&self.config, &req));
+
+            #[cfg(feature = "openapi")]
+            if let Some(operation) = self
+                .config
+                .openapi
+                .find(req.method(), &self.naming_path(&req))
+            {
+                root.add_properties(|| operation.properties());
+            }
+
+            #[cfg(feature = "grpc")]
+            if let Some(method) = GrpcMethod::from_request(&req, original_path(&req)) {
+                root.add_properties(|| method.properties());
+            }
+
+            #[cfg(feature = "graphql")]
+            if let Some(operation) = &graphql {
+                root.add_properties(|| operation.properties());
+            }
+
+            #[cfg(feature = "lambda")]
+            root.add_properties(|| lambda_properties(&req));
+
+            if let Some(service_name) = &self.config.service_name {
+                root.add_property(|| (SERVICE_NAME, service_name.clone()));
+            }
+
+            if !self.config.attributes.is_empty() {
+                root.add_properties(|| self.config.attributes.iter().cloned());
+            }
+
+            for customizer in &self.config.customizers {
+                customizer.on_request(&root, &req);
+            }
+
+            if forced {
+                root.add_property(|| (FORCED_PROPERTY, "true"));
+            }
+
+            if let Some(queue_time) = queue_time {
+                let millis = queue_time.as_millis().to_string();
+                if self.config.queue_time_event {
+                    root.add_event(
+                        Event::new("request queued")
+                            .with_property(|| (QUEUE_TIME_PROPERTY, millis.clone())),
+                    );
+                }
+                root.add_property(|| (QUEUE_TIME_PROPERTY, millis));
+            }
+
+            if let Some(timeout) = timeout {
+                root.add_property(|| (REQUEST_TIMEOUT_PROPERTY, timeout.as_millis().to_string()));
+            }
+
+            if let Some((_, id)) = &request_id {
+                root.add_property(|| {
+                    let id = String::from_utf8_lossy(id.as_bytes()).into_owned();
+                    (REQUEST_ID_PROPERTY, id)
+                });
+            }
+
+            if let Some(traceparent) = invalid_traceparent {
+                root.add_event(
+                    Event::new("invalid traceparent")
+                        .with_property(|| (TRACEPARENT_HEADER, traceparent)),
+                );
+            }
+
+            if !conflicting_parents.is_empty() {
+                root.add_property(|| {
+                    let parents = conflicting_parents
+                        .iter()
+                        .map(SpanContext::encode_w3c_traceparent)
+                        .collect::<Vec<_>>();
+                    (CONFLICTING_PARENTS_PROPERTY, parents.join(","))
+                });
+            }
+
+            if let Some(trace_state) = &trace_state {
+                root.add_property(|| (TRACESTATE_PROPERTY, trace_state.as_str().to_string()));
+            }
+
+            if let Some(baggage) = &baggage {
+                root.add_properties(|| {
+                    baggage
+                        .iter()
+                        .filter(|(key, _)| self.config.baggage_properties.iter().any(|k| k == key))
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                });
+            }
+
+            root
+        } else {
+            Span::noop()
+        };
+        // Shared with the handlers extracting the `CurrentSpan`, the span ends once the last of
+        // them drops it.
+        let span = Arc::new(span);
+
+        let propagation = Arc::new(Propagation {
+            injector: self.injector.clone(),
+            #[cfg(feature = "reqwest")]
+            trace_state: trace_state.clone(),
+            #[cfg(feature = "reqwest")]
+            baggage: baggage.clone(),
+        });
+
+        if let Some(trace_state) = trace_state {
+            req.extensions_mut().insert(trace_state);
+        }
+        if let Some(baggage) = baggage {
+            req.extensions_mut().insert(baggage);
+        }
+        if let Some(context) = SpanContext::from_span(&span) {
+            req.extensions_mut().insert(context);
+            req.extensions_mut().insert(CurrentSpan(span.clone()));
+            req.set_data(self.route_names.clone());
+            if self.config.max_attribute_len != DEFAULT_MAX_ATTRIBUTE_LEN {
+                req.set_data(MaxAttributeLen(self.config.max_attribute_len));
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        let tracing_span = SpanContext::from_span(&span).map(|context| tracing_span(&context));
+
+        let context_headers = self.context_headers(&span);
+
+        if self.config.forward_context {
+            if let Some(context) = SpanContext::from_span(&span) {
+                self.injector.inject(&context, req.headers_mut());
+            }
+        }
+
+        let params_path = self
+            .config
+            .route_params
+            .then(|| original_path(&req).to_string());
+
+        let request_body_size = self.config.measure_request_body.then(|| {
+            let size = Arc::new(AtomicU64::new(0));
+            let counter = size.clone();
+            let body = observe_body(req.take_body(), 0, move |summary| {
+                counter.store(summary.size, Ordering::Relaxed);
+            });
+            req.set_body(body);
+            size
+        });
+
+        drop(setup_span);
+        let handler_span = (self.config.handler_span || self.config.phase_spans)
+            .then(|| Span::enter_with_parent(HANDLER_SPAN_NAME, &span));
+
+        let called = Instant::now();
+        let mut heartbeat = self
+            .config
+            .heartbeat
+            .filter(|_| span.elapsed().is_some())
+            .map(|interval| (interval, Box::pin(tokio::time::sleep(interval))));
+
+        let resp = {
+            let mut call = pin!(self.inner.call(req));
+            poll_fn(|cx| {
+                let _guard = handler_span.as_ref().unwrap_or(&span).set_local_parent();
+                let _propagation = set_propagation(&propagation);
+                #[cfg(feature = "tracing")]
+                let _tracing = tracing_span.as_ref().map(tracing::Span::enter);
+                match panic::catch_unwind(AssertUnwindSafe(|| call.as_mut().poll(cx))) {
+                    Ok(Poll::Pending) => {
+                        if let Some((interval, sleep)) = &mut heartbeat {
+                            if sleep.as_mut().poll(cx).is_ready() {
+                                span.add_event(Event::new(HEARTBEAT_EVENT_NAME).with_property(
+                                    || {
+                                        let millis = called.elapsed().as_millis();
+                                        (HEARTBEAT_ELAPSED_PROPERTY, millis.to_string())
+                                    },
+                                ));
+                                sleep
+                                    .as_mut()
+                                    .reset(tokio::time::Instant::now() + *interval);
+                                // Register the wakeup of the next heartbeat, which is left to a
+                                // later poll when it is already due.
+                                let _ = sleep.as_mut().poll(cx);
+                            }
+                        }
+                        Poll::Pending
+                    }
+                    Ok(poll) => poll,
+                    Err(payload) => {
+                        // Record the panic before the span is dropped while unwinding.
+                        self.record_status(
+                            &span,
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            &HeaderMap::new(),
+                            head.as_ref(),
+                        );
+                        span.add_event(Event::new("exception").with_properties(|| {
+                            panic_properties(&*payload, self.config.max_attribute_len)
+                        }));
+                        panic::resume_unwind(payload)
+                    }
+                }
+            })
+            .await
+        };
+        drop(handler_span);
+
+        if let Some(size) = request_body_size {
+            span.add_properties(|| {
+                let size = size.load(Ordering::Relaxed);
+                self.config
+                    .semconv
+                    .rename([(HTTP_REQUEST_BODY_SIZE.into(), size.to_string())])
+            });
+        }
+
+        let echo_request_id = request_id.filter(|_| self.config.generate_request_id);
+        let writes_headers =
+            echo_request_id.is_some() || !context_headers.is_empty() || start.is_some();
+        let (mut resp, failed) =
+            match resp {
+                Ok(resp) => (resp.into_response(), false),
+                Err(err) => {
+                    let route = err.data::<PathPattern>().or(route.as_ref());
+                    self.record_route(&span, route, params_path.as_deref());
+                    // The error is only converted into a response by outer layers, with the status
+                    // of the error.
+                    self.record_status_code(&span, err.status());
+                    self.record_status(&span, err.status(), &HeaderMap::new(), head.as_ref());
+                    span.add_event(Event::new("exception").with_properties(|| {
+                        exception_properties(&err, self.config.max_attribute_len)
+                    }));
+                    for customizer in &self.config.customizers {
+                        customizer.on_error(&span, &err);
+                    }
+                    if !writes_headers {
+                        self.decide_retention(&span, err.status(), deadline);
+                        return Err(err);
+                    }
+                    // The error is converted into its response, with the status of the error, so
+                    // that it carries the response headers as well, and is returned as an error
+                    // again once they are set.
+                    (err.into_response(), true)
+                }
+            };
+
+        if let Some((header, id)) = echo_request_id {
+            resp.headers_mut().entry(header).or_insert(id);
+        }
+        for (name, value) in context_headers {
+            resp.headers_mut().insert(name, value);
+        }
+        if let Some(start) = start {
+            let mut timing = format!("total;dur={:.1}", start.elapsed().as_secs_f64() * 1000.0);
+            if let Some(context) = SpanContext::from_span(&span) {
+                let traceparent = context.encode_w3c_traceparent();
+                timing.push_str(&format!(", traceparent;desc=\"{traceparent}\""));
+            }
+            if let Ok(timing) = HeaderValue::try_from(timing) {
+                resp.headers_mut().append(SERVER_TIMING_HEADER, timing);
+            }
+        }
+        // The route and the status of errors are already recorded.
+        if !failed {
+            self.record_response(
+                &span,
+                &mut resp,
+                route.as_ref(),
+                params_path.as_deref(),
+                head.as_ref(),
+            );
+        }
+
+        self.decide_retention(&span, resp.status(), deadline);
+
+        let snippet_len = if resp.status().is_client_error() || resp.status().is_server_error() {
+            self.config.error_body_snippet
+        } else {
+            0
+        };
+        let phase_spans = self.config.phase_spans;
+        if (self.config.measure_response_body
+            || snippet_len > 0
+            || phase_spans
+            || self.config.span_until_body_end
+            || self.config.ttfb)
+            && span.elapsed().is_some()
+        {
+            // The span is moved into the observer, ending it with the body.
+            let semconv = self.config.semconv;
+            let measure = self.config.measure_response_body;
+            let write_span =
+                phase_spans.then(|| Span::enter_with_parent(RESPONSE_WRITE_SPAN_NAME, &span));
+            let ttfb = self.config.ttfb.then(Instant::now);
+            let body = observe_body(resp.take_body(), snippet_len, move |summary| {
+                drop(write_span);
+                if measure {
+                    span.add_properties(|| {
+                        let size = summary.size.to_string();
+                        semconv.rename([(HTTP_RESPONSE_BODY_SIZE.into(), size)])
+                    });
+                }
+                if let (Some(returned), Some(first_data)) = (ttfb, summary.first_data) {
+                    span.add_property(|| {
+                        let millis = first_data.duration_since(returned).as_millis();
+                        (RESPONSE_TTFB_PROPERTY, millis.to_string())
+                    });
+                }
+                if !summary.head.is_empty() {
+                    span.add_property(|| {
+                        let snippet = String::from_utf8_lossy(&summary.head).into_owned();
+                        (RESPONSE_BODY_SNIPPET_PROPERTY, snippet)
+                    });
+                }
+            });
+            resp.set_body(body);
+        }
+
+        if failed {
+            // Outer error handlers, such as `catch_error`, still see an error, although one from a
+            // response, whose source is lost.
+            return Err(Error::from_response(resp));
+        }
+        Ok(resp)
+    }
+}
+
+/// Returns the `tracing` span entered while the request is handled, whose fields correlate the
+/// events logged with `tracing` with the root span.
+#[cfg(feature = "tracing")]
+fn tracing_span(context: &SpanContext) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        trace_id = %context.trace_id,
+        span_id = %context.span_id,
+    )
+}
+
+/// Returns the path before `Route::nest` stripped its prefix, which is the path matched by the
+/// route pattern. The original URI is left unset by requests built outside of a server, such as
+/// with `poem::test`, in which case the current path is the original one.
+fn original_path(req: &Request) -> &str {
+    match req.original_uri().path() {
+        "/" => req.uri().path(),
+        path => path,
+    }
+}
+
+/// Returns a copy of the method, URI, version and headers of the request, without its body.
+fn request_head(req: &Request) -> Request {
+    let mut head = Request::builder()
+        .method(req.method().clone())
+        .uri(req.uri().clone())
+        .version(req.version())
+        .finish();
+    *head.headers_mut() = req.headers().clone();
+    head
+}
+
+fn is_preflight(req: &Request) -> bool {
+    req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// Returns whether the parent context leaves the sampling decision to this service, as X-Ray trace
+/// headers with `Sampled=?` do.
+#[cfg(feature = "xray")]
+fn defers_sampling(req: &Request, parent: &SpanContext) -> bool {
+    #[cfg(feature = "lambda")]
+    if lambda_defers_sampling(req, parent) {
+        return true;
+    }
+    crate::propagation::xray::defers_sampling(req.headers(), parent)
+}
@@ -0,0 +1,145 @@
+//! Tracing of multipart uploads.
+
+use fastrace::Event;
+use fastrace::Span;
+use opentelemetry_semantic_conventions::trace::EXCEPTION_MESSAGE;
+use poem::FromRequest;
+use poem::Request;
+use poem::RequestBody;
+use poem::Result;
+use poem::error::ParseMultipartError;
+use poem::web::Field;
+use poem::web::Multipart;
+
+use crate::CurrentSpan;
+
+/// The name of the child span recorded for every part.
+const MULTIPART_PART_NAME: &str = "multipart.part";
+
+/// The property recording the name of the form field of a part.
+const MULTIPART_NAME_PROPERTY: &str = "multipart.part.name";
+
+/// The property recording the file name of a part.
+const MULTIPART_FILE_NAME_PROPERTY: &str = "multipart.part.file_name";
+
+/// The property recording the content type of a part.
+const MULTIPART_CONTENT_TYPE_PROPERTY: &str = "multipart.part.content_type";
+
+/// The property recording the size of a part.
+const MULTIPART_SIZE_PROPERTY: &str = "multipart.part.size";
+
+/// A [`Multipart`] extractor recording a child span of the root span for every part.
+///
+/// The span of a part starts once its headers are read and ends once its content is read, with
+/// the field name, file name, content type and size of the part. Read errors are recorded as
+/// exception events on the span.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::TracedMultipart;
+/// use poem::Result;
+/// use poem::handler;
+///
+/// #[handler]
+/// async fn upload(mut multipart: TracedMultipart) -> Result<String> {
+///     let mut total = 0;
+///     while let Some(field) = multipart.next_field().await? {
+///         total += field.bytes().await?.len();
+///     }
+///     Ok(format!("{total} bytes uploaded"))
+/// }
+/// ```
+pub struct TracedMultipart {
+    inner: Multipart,
+    span: CurrentSpan,
+}
+
+impl TracedMultipart {
+    /// Yields the next part, if any.
+    pub async fn next_field(&mut self) -> Result<Option<TracedField>, ParseMultipartError> {
+        let Some(field) = self.inner.next_field().await? else {
+            return Ok(None);
+        };
+
+        let span = Span::enter_with_parent(MULTIPART_PART_NAME, &self.span);
+        span.add_properties(|| {
+            [
+                field.name().map(|name| (MULTIPART_NAME_PROPERTY, name)),
+                field
+                    .file_name()
+                    .map(|file_name| (MULTIPART_FILE_NAME_PROPERTY, file_name)),
+                field
+                    .content_type()
+                    .map(|content_type| (MULTIPART_CONTENT_TYPE_PROPERTY, content_type)),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key, value.to_string()))
+        });
+
+        Ok(Some(TracedField { inner: field, span }))
+    }
+}
+
+impl<'a> FromRequest<'a> for TracedMultipart {
+    async fn from_request(req: &'a Request, body: &mut RequestBody) -> Result<Self> {
+        Ok(TracedMultipart {
+            inner: Multipart::from_request(req, body).await?,
+            span: CurrentSpan::from_request(req, body).await?,
+        })
+    }
+}
+
+/// A part of a [`TracedMultipart`] upload, whose span ends once its content is read or it is
+/// dropped.
+pub struct TracedField {
+    inner: Field,
+    span: Span,
+}
+
+impl TracedField {
+    /// Returns the content type of the part.
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type()
+    }
+
+    /// Returns the file name of the part.
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    /// Returns the name of the form field of the part.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// Reads the content of the part as bytes.
+    pub async fn bytes(self) -> Result<Vec<u8>, ParseMultipartError> {
+        let bytes = self.inner.bytes().await;
+        record_result(&self.span, bytes.as_ref().map(Vec::len));
+        bytes
+    }
+
+    /// Reads the content of the part as text.
+    pub async fn text(self) -> Result<String, ParseMultipartError> {
+        let text = self.inner.text().await;
+        record_result(&self.span, text.as_ref().map(String::len));
+        text
+    }
+
+    /// Returns the inner field, to read its content in another way. The span of the part ends
+    /// without its size.
+    pub fn into_inner(self) -> Field {
+        self.inner
+    }
+}
+
+fn record_result(span: &Span, size: Result<usize, &ParseMultipartError>) {
+    match size {
+        Ok(size) => span.add_property(|| (MULTIPART_SIZE_PROPERTY, size.to_string())),
+        Err(err) => span.add_event(
+            Event::new("exception").with_property(|| (EXCEPTION_MESSAGE, err.to_string())),
+        ),
+    }
+}
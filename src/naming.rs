@@ -0,0 +1,369 @@
+//! Naming of the root span.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+
+use poem::Endpoint;
+use poem::Middleware;
+use poem::PathPattern;
+use poem::Request;
+use poem::Result;
+use poem::http::Method;
+
+use crate::route::route_specificity;
+
+/// Overrides the name of the request span for the endpoints it wraps, giving individual routes a
+/// meaningful operation name without a global formatter.
+///
+/// When applied outside of [`FastraceMiddleware`](struct@crate::FastraceMiddleware), the root span
+/// is named after it. When applied inside, for example to a single route while the middleware wraps
+/// the whole [`Route`](poem::Route), the root span is started before the route is matched, so the
+/// middleware learns the name of the route from its first request and names the root span of the
+/// following requests after it. No span is created around the endpoint. Requests to a route
+/// without a name are named after a named route matching their path as well, such as
+/// `/users/me` after `/users/:id`, unless that route is given a name of its own.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::SpanName;
+/// use poem::EndpointExt;
+/// use poem::Route;
+/// use poem::get;
+/// use poem::handler;
+///
+/// #[handler]
+/// fn get_user() -> &'static str {
+///     "user"
+/// }
+///
+/// let app = Route::new()
+///     .at("/users/:id", get(get_user).with(SpanName::new("GetUser")))
+///     .with(FastraceMiddleware::new());
+/// ```
+#[derive(Clone, Debug)]
+pub struct SpanName(pub(crate) Cow<'static, str>);
+
+impl SpanName {
+    /// Creates a span name override.
+    pub fn new(name: impl Into<Cow<'static, str>>) -> Self {
+        Self(name.into())
+    }
+
+    /// Returns the span name.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for SpanName {
+    type Output = SpanNameEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        SpanNameEndpoint {
+            inner: ep,
+            name: self.clone(),
+        }
+    }
+}
+
+/// An endpoint wrapper created by [`SpanName`].
+pub struct SpanNameEndpoint<E> {
+    inner: E,
+    name: SpanName,
+}
+
+impl<E: Endpoint> Endpoint for SpanNameEndpoint<E> {
+    type Output = E::Output;
+
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        // Inside the middleware, teach it the name of the matched route.
+        if let (Some(names), Some(route)) = (req.data::<RouteNames>(), req.data::<PathPattern>()) {
+            names.learn(req.method(), &route.0, &self.name);
+        }
+        // Outside the middleware, let the `FastraceEndpoint` below pick the name up.
+        req.set_data(self.name.clone());
+        self.inner.call(req).await
+    }
+}
+
+/// The routes of a middleware given a name by [`SpanName`], learned from the requests they handled,
+/// as the route is only matched after the root span is started.
+#[derive(Clone, Default)]
+pub(crate) struct RouteNames(Arc<RwLock<Vec<RouteName>>>);
+
+struct RouteName {
+    method: Method,
+    pattern: String,
+    name: SpanName,
+}
+
+impl RouteNames {
+    /// Records the name given to a route.
+    pub(crate) fn learn(&self, method: &Method, pattern: &str, name: &SpanName) {
+        let known = |names: &[RouteName]| {
+            names.iter().any(|known| {
+                known.method == method && known.pattern == pattern && known.name.0 == name.0
+            })
+        };
+        if known(&self.0.read().unwrap_or_else(PoisonError::into_inner)) {
+            return;
+        }
+        let mut names = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        if known(&names) {
+            return;
+        }
+        names.retain(|known| known.method != method || known.pattern != pattern);
+        names.push(RouteName {
+            method: method.clone(),
+            pattern: pattern.to_string(),
+            name: name.clone(),
+        });
+    }
+
+    /// Finds the name of the named route matching the path of the request, preferring the most
+    /// specific one like the router does.
+    pub(crate) fn find(&self, method: &Method, path: &str) -> Option<Cow<'static, str>> {
+        let names = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        let (_, route) = names
+            .iter()
+            .filter(|known| known.method == method)
+            .filter_map(|known| Some((route_specificity(&known.pattern, path)?, known)))
+            .max_by_key(|(specificity, _)| *specificity)?;
+        Some(route.name.0.clone())
+    }
+}
+
+/// Replaces the path segments that look like identifiers, such as numbers, UUIDs and hashes, with
+/// `{id}`, so that `/users/123` becomes `/users/{id}`.
+pub(crate) fn template_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if is_identifier(segment) {
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_identifier(segment: &str) -> bool {
+    let is_number = !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit());
+    is_number || is_uuid(segment) || is_hash(segment)
+}
+
+fn is_uuid(segment: &str) -> bool {
+    segment.len() == 36
+        && segment.bytes().enumerate().all(|(i, b)| match i {
+            8 | 13 | 18 | 23 => b == b'-',
+            _ => b.is_ascii_hexdigit(),
+        })
+}
+
+/// Hex strings of at least 16 digits, such as object ids and content hashes. Words made of the
+/// letters `a` to `f` only are not mistaken for hashes, as they must contain a digit.
+fn is_hash(segment: &str) -> bool {
+    segment.len() >= 16
+        && segment.bytes().all(|b| b.is_ascii_hexdigit())
+        && segment.bytes().any(|b| b.is_ascii_digit())
+}
+
+/// Tracks the distinct span names, admitting new names until the budget is exhausted.
+pub(crate) struct NameLimiter {
+    max_names: usize,
+    names: Mutex<HashSet<String>>,
+}
+
+impl NameLimiter {
+    pub(crate) fn new(max_names: usize) -> Self {
+        Self {
+            max_names,
+            names: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Returns whether the name is already known or fits in the budget.
+    pub(crate) fn admit(&self, name: &str) -> bool {
+        let mut names = self.names.lock().unwrap_or_else(PoisonError::into_inner);
+        if names.contains(name) {
+            return true;
+        }
+        if names.len() >= self.max_names {
+            return false;
+        }
+        names.insert(name.to_string());
+        true
+    }
+}
+
+/// Options normalizing the request path before a span name is derived from it, so that variants
+/// such as `/Users/` and `/users` are named after the same operation.
+///
+/// The normalized path is used by
+/// [`FastraceMiddlewareBuilder::with_path_templating`](crate::FastraceMiddlewareBuilder::with_path_templating)
+/// and to match poem-openapi operations. The `url.path` property keeps the raw path.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::PathNormalization;
+///
+/// let middleware = FastraceMiddleware::builder()
+///     .with_path_templating(true)
+///     .with_path_normalization(
+///         PathNormalization::new()
+///             .with_trim_trailing_slash(true)
+///             .with_collapse_slashes(true)
+///             .with_lowercase(true),
+///     )
+///     .build();
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PathNormalization {
+    trim_trailing_slash: bool,
+    collapse_slashes: bool,
+    lowercase: bool,
+}
+
+impl PathNormalization {
+    /// Creates options leaving the path untouched.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes the trailing slash of the path, except for the root path `/`.
+    pub fn with_trim_trailing_slash(mut self, enable: bool) -> Self {
+        self.trim_trailing_slash = enable;
+        self
+    }
+
+    /// Collapses consecutive slashes into one.
+    pub fn with_collapse_slashes(mut self, enable: bool) -> Self {
+        self.collapse_slashes = enable;
+        self
+    }
+
+    /// Converts the path to ASCII lowercase.
+    pub fn with_lowercase(mut self, enable: bool) -> Self {
+        self.lowercase = enable;
+        self
+    }
+
+    pub(crate) fn normalize<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let mut path = Cow::Borrowed(path);
+        if self.collapse_slashes && path.contains("//") {
+            let mut collapsed = String::with_capacity(path.len());
+            for c in path.chars() {
+                if c != '/' || !collapsed.ends_with('/') {
+                    collapsed.push(c);
+                }
+            }
+            path = Cow::Owned(collapsed);
+        }
+        if self.trim_trailing_slash && path.len() > 1 && path.ends_with('/') {
+            path = Cow::Owned(path.trim_end_matches('/').to_string());
+            if path.is_empty() {
+                path = Cow::Borrowed("/");
+            }
+        }
+        if self.lowercase && path.bytes().any(|b| b.is_ascii_uppercase()) {
+            path = Cow::Owned(path.to_ascii_lowercase());
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_names_prefer_the_most_specific_route() {
+        let names = RouteNames::default();
+        names.learn(&Method::GET, "/users/:id", &SpanName::new("GetUser"));
+        assert_eq!(
+            names.find(&Method::GET, "/users/42").as_deref(),
+            Some("GetUser")
+        );
+        assert_eq!(
+            names.find(&Method::GET, "/users/me").as_deref(),
+            Some("GetUser")
+        );
+        assert_eq!(names.find(&Method::POST, "/users/42"), None);
+        assert_eq!(names.find(&Method::GET, "/users"), None);
+
+        names.learn(&Method::GET, "/users/me", &SpanName::new("GetMe"));
+        assert_eq!(
+            names.find(&Method::GET, "/users/me").as_deref(),
+            Some("GetMe")
+        );
+        names.learn(&Method::GET, "/users/me", &SpanName::new("GetCurrentUser"));
+        assert_eq!(
+            names.find(&Method::GET, "/users/me").as_deref(),
+            Some("GetCurrentUser")
+        );
+    }
+
+    #[test]
+    fn template_path_replaces_identifiers() {
+        assert_eq!(
+            template_path("/users/123/orders/7"),
+            "/users/{id}/orders/{id}"
+        );
+        assert_eq!(
+            template_path("/files/3f2504e0-4f89-11d3-9a0c-0305e82c3301"),
+            "/files/{id}"
+        );
+        assert_eq!(
+            template_path("/objects/507f1f77bcf86cd799439011"),
+            "/objects/{id}"
+        );
+    }
+
+    #[test]
+    fn template_path_keeps_words() {
+        assert_eq!(template_path("/users/me"), "/users/me");
+        assert_eq!(template_path("/v2/api"), "/v2/api");
+        assert_eq!(template_path("/defacedbeefcafe00"), "/{id}");
+        assert_eq!(
+            template_path("/deadbeefdeadbeefdead"),
+            "/deadbeefdeadbeefdead"
+        );
+        assert_eq!(template_path("/abc123"), "/abc123");
+        assert_eq!(template_path("/"), "/");
+        assert_eq!(template_path("/users//"), "/users//");
+    }
+
+    #[test]
+    fn name_limiter_admits_known_names_past_the_budget() {
+        let limiter = NameLimiter::new(2);
+        assert!(limiter.admit("GET /a"));
+        assert!(limiter.admit("GET /b"));
+        assert!(!limiter.admit("GET /c"));
+        assert!(limiter.admit("GET /a"));
+    }
+
+    #[test]
+    fn path_normalization_options() {
+        let all = PathNormalization::new()
+            .with_trim_trailing_slash(true)
+            .with_collapse_slashes(true)
+            .with_lowercase(true);
+        assert_eq!(all.normalize("//Users///42/"), "/users/42");
+        assert_eq!(all.normalize("///"), "/");
+        assert!(matches!(
+            PathNormalization::new().normalize("/A/"),
+            Cow::Borrowed("/A/")
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+//! Span naming after [poem-openapi](https://docs.rs/poem-openapi) operations.
+
+use poem::http::Method;
+use poem_openapi::OpenApi;
+
+/// The span property listing the tags of the OpenAPI operation.
+const OPENAPI_TAG_PROPERTY: &str = "openapi.tag";
+
+/// The span property recording the id of the OpenAPI operation.
+const OPENAPI_OPERATION_ID_PROPERTY: &str = "openapi.operation_id";
+
+/// The operations of the APIs registered with
+/// [`FastraceMiddlewareBuilder::with_openapi`](crate::FastraceMiddlewareBuilder::with_openapi).
+#[derive(Default)]
+pub(crate) struct OpenApiOperations(Vec<Operation>);
+
+pub(crate) struct Operation {
+    method: Method,
+    path: Vec<Segment>,
+    name: String,
+    operation_id: Option<&'static str>,
+    tags: Vec<&'static str>,
+}
+
+enum Segment {
+    Literal(String),
+    Param,
+}
+
+impl OpenApiOperations {
+    pub(crate) fn register<T: OpenApi>(&mut self) {
+        for api in T::meta() {
+            for path in api.paths {
+                for operation in path.operations {
+                    let name = match operation.operation_id {
+                        Some(operation_id) => operation_id.to_string(),
+                        None => format!("{} {}", operation.method, path.path),
+                    };
+                    self.0.push(Operation {
+                        method: operation.method,
+                        path: parse_path(&path.path),
+                        name,
+                        operation_id: operation.operation_id,
+                        tags: operation.tags,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Finds the operation handling the request, preferring literal segments over parameters
+    /// like the router does.
+    pub(crate) fn find(&self, method: &Method, path: &str) -> Option<&Operation> {
+        self.0
+            .iter()
+            .filter(|operation| operation.method == method && operation.matches(path))
+            .max_by_key(|operation| {
+                operation
+                    .path
+                    .iter()
+                    .filter(|segment| matches!(segment, Segment::Literal(_)))
+                    .count()
+            })
+    }
+}
+
+impl Operation {
+    /// The span name: the operation id, or the method and the path template.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn properties(&self) -> impl Iterator<Item = (&'static str, String)> + '_ {
+        let tags = (!self.tags.is_empty()).then(|| (OPENAPI_TAG_PROPERTY, self.tags.join(",")));
+        let operation_id = self
+            .operation_id
+            .map(|operation_id| (OPENAPI_OPERATION_ID_PROPERTY, operation_id.to_string()));
+        tags.into_iter().chain(operation_id)
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let mut segments = path.trim_matches('/').split('/');
+        let matches = self.path.iter().all(|expected| {
+            segments.next().is_some_and(|segment| match expected {
+                Segment::Literal(literal) => literal == segment,
+                Segment::Param => !segment.is_empty(),
+            })
+        });
+        matches && segments.next().is_none()
+    }
+}
+
+fn parse_path(path: &str) -> Vec<Segment> {
+    path.trim_matches('/')
+        .split('/')
+        .map(|segment| {
+            if segment.starts_with('{') && segment.ends_with('}') {
+                Segment::Param
+            } else {
+                Segment::Literal(segment.to_string())
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,185 @@
+//! Conversions between fastrace and OpenTelemetry span contexts.
+
+use std::str::FromStr;
+
+use fastrace::collector::SpanContext;
+use fastrace::collector::SpanId;
+use fastrace::collector::TraceId;
+use opentelemetry::Context;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::TraceFlags;
+use poem::FromRequest;
+use poem::Request;
+use poem::RequestBody;
+use poem::Result;
+
+use crate::propagation::TraceState;
+
+/// Converts a fastrace span context into an OpenTelemetry one, marked as remote as it was
+/// created by another tracer.
+///
+/// The trace state is only carried by OpenTelemetry contexts; pass the [`TraceState`] stored in the
+/// request extensions to keep it, or `None`. An invalid trace state is dropped.
+pub fn to_otel_span_context(
+    context: SpanContext,
+    trace_state: Option<&TraceState>,
+) -> opentelemetry::trace::SpanContext {
+    let flags = if context.sampled {
+        TraceFlags::SAMPLED
+    } else {
+        TraceFlags::default()
+    };
+    let trace_state = trace_state
+        .and_then(|trace_state| {
+            opentelemetry::trace::TraceState::from_str(trace_state.as_str()).ok()
+        })
+        .unwrap_or_default();
+    opentelemetry::trace::SpanContext::new(
+        opentelemetry::trace::TraceId::from(context.trace_id.0),
+        opentelemetry::trace::SpanId::from(context.span_id.0),
+        flags,
+        true,
+        trace_state,
+    )
+}
+
+/// Converts an OpenTelemetry span context into a fastrace one, for example to continue the trace of
+/// an OpenTelemetry instrumented library with fastrace spans. Returns `None` for invalid contexts,
+/// such as the context of a noop span.
+pub fn from_otel_span_context(context: &opentelemetry::trace::SpanContext) -> Option<SpanContext> {
+    context.is_valid().then(|| {
+        SpanContext::new(
+            TraceId(u128::from_be_bytes(context.trace_id().to_bytes())),
+            SpanId(u64::from_be_bytes(context.span_id().to_bytes())),
+        )
+        .sampled(context.is_sampled())
+    })
+}
+
+/// An extractor for an OpenTelemetry [`Context`] whose remote span is the root span of the
+/// request, so that the spans of OpenTelemetry instrumented libraries, such as SQL clients, are
+/// recorded in the same trace.
+///
+/// The context is built from the [`SpanContext`] and [`TraceState`] stored in the request
+/// extensions by [`FastraceMiddleware`](struct@crate::FastraceMiddleware). It is an empty context
+/// when the request is not traced. Pass it to OpenTelemetry instrumented futures with
+/// `FutureExt::with_context` rather than attaching it across `.await` points.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::OtelContext;
+/// use opentelemetry::trace::TraceContextExt;
+/// use poem::handler;
+///
+/// #[handler]
+/// fn query(OtelContext(cx): OtelContext) -> String {
+///     // Pass `cx` to an OpenTelemetry instrumented client...
+///     cx.span().span_context().trace_id().to_string()
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct OtelContext(pub Context);
+
+impl OtelContext {
+    /// Returns the context of the local parent span on the current thread, for code that does
+    /// not have access to the request, such as a spawned task inside
+    /// [`FutureExt::in_span`](fastrace::future::FutureExt::in_span).
+    pub fn current() -> Self {
+        OtelContext(otel_context(SpanContext::current_local_parent(), None))
+    }
+
+    /// Returns the inner context.
+    pub fn into_inner(self) -> Context {
+        self.0
+    }
+}
+
+impl<'a> FromRequest<'a> for OtelContext {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> Result<Self> {
+        let context = req.extensions().get::<SpanContext>().copied();
+        let trace_state = req.extensions().get::<TraceState>();
+        Ok(OtelContext(otel_context(context, trace_state)))
+    }
+}
+
+fn otel_context(context: Option<SpanContext>, trace_state: Option<&TraceState>) -> Context {
+    match context {
+        Some(context) => {
+            Context::new().with_remote_span_context(to_otel_span_context(context, trace_state))
+        }
+        None => Context::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::http::HeaderMap;
+
+    use super::*;
+
+    fn context() -> SpanContext {
+        SpanContext::new(
+            TraceId(0x0af7651916cd43dd8448eb211c80319c),
+            SpanId(0xb7ad6b7169203331),
+        )
+    }
+
+    fn trace_state(value: &str) -> TraceState {
+        let mut headers = HeaderMap::new();
+        headers.insert("tracestate", value.parse().unwrap());
+        TraceState::extract(&headers).unwrap()
+    }
+
+    #[test]
+    fn span_contexts_round_trip() {
+        let otel = to_otel_span_context(context(), None);
+        assert_eq!(
+            otel.trace_id().to_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+        assert_eq!(otel.span_id().to_string(), "b7ad6b7169203331");
+        assert!(otel.is_sampled());
+        assert!(otel.is_remote());
+        assert_eq!(from_otel_span_context(&otel), Some(context()));
+
+        let unsampled = context().sampled(false);
+        let otel = to_otel_span_context(unsampled, None);
+        assert!(!otel.is_sampled());
+        assert_eq!(from_otel_span_context(&otel), Some(unsampled));
+    }
+
+    #[test]
+    fn invalid_span_contexts_are_dropped() {
+        assert_eq!(
+            from_otel_span_context(&opentelemetry::trace::SpanContext::empty_context()),
+            None
+        );
+    }
+
+    #[test]
+    fn trace_state_is_kept_when_valid() {
+        let otel = to_otel_span_context(context(), Some(&trace_state("vendor=value,other=1")));
+        assert_eq!(otel.trace_state().header(), "vendor=value,other=1");
+
+        let otel = to_otel_span_context(context(), Some(&trace_state("not a list member")));
+        assert_eq!(otel.trace_state().header(), "");
+    }
+
+    #[tokio::test]
+    async fn otel_context_of_the_request() {
+        let req = Request::builder()
+            .extension(context())
+            .extension(trace_state("vendor=value"))
+            .finish();
+        let OtelContext(cx) = OtelContext::from_request_without_body(&req).await.unwrap();
+        let span_context = cx.span().span_context().clone();
+        assert_eq!(from_otel_span_context(&span_context), Some(context()));
+        assert_eq!(span_context.trace_state().header(), "vendor=value");
+
+        let OtelContext(cx) = OtelContext::from_request_without_body(&Request::default())
+            .await
+            .unwrap();
+        assert!(!cx.has_active_span());
+    }
+}
@@ -0,0 +1,310 @@
+//! [B3 propagation](https://github.com/openzipkin/b3-propagation) as used by Zipkin and
+//! Zipkin-compatible meshes.
+
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+use super::ContextExtractor;
+use super::ContextInjector;
+use super::header_str;
+use super::parse_span_id;
+use super::parse_trace_id;
+use super::set_header;
+
+const B3_HEADER: &str = "b3";
+const B3_TRACE_ID_HEADER: &str = "x-b3-traceid";
+const B3_SPAN_ID_HEADER: &str = "x-b3-spanid";
+const B3_PARENT_SPAN_ID_HEADER: &str = "x-b3-parentspanid";
+const B3_SAMPLED_HEADER: &str = "x-b3-sampled";
+const B3_FLAGS_HEADER: &str = "x-b3-flags";
+
+/// Decodes the parent context from the B3 multi-header format.
+///
+/// `X-B3-ParentSpanId` identifies the caller's own parent, so it is validated but not needed to
+/// continue the trace. A missing sampling decision is treated as sampled.
+fn decode_b3_multi(headers: &HeaderMap) -> Option<SpanContext> {
+    let trace_id = decode_trace_id(header_str(headers, B3_TRACE_ID_HEADER)?)?;
+    let span_id = parse_span_id(header_str(headers, B3_SPAN_ID_HEADER)?, 16)?;
+    if let Some(parent_span_id) = header_str(headers, B3_PARENT_SPAN_ID_HEADER) {
+        parse_span_id(parent_span_id, 16)?;
+    }
+
+    let debug = header_str(headers, B3_FLAGS_HEADER) == Some("1");
+    let sampled = match header_str(headers, B3_SAMPLED_HEADER) {
+        Some("1" | "true") | None => true,
+        Some("0" | "false") => debug,
+        Some(_) => return None,
+    };
+
+    Some(SpanContext::new(trace_id, span_id).sampled(sampled))
+}
+
+/// Extracts the parent context from the [B3](https://github.com/openzipkin/b3-propagation) headers.
+///
+/// As recommended by the B3 specification, the single `b3` header takes precedence over the
+/// multi-header format (`X-B3-TraceId`, `X-B3-SpanId`, `X-B3-Sampled`, ...). The context is
+/// injected into the single `b3` header, see [`B3Multi`] to inject the multi-header format.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct B3;
+
+impl ContextExtractor for B3 {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        decode_b3_single(headers).or_else(|| decode_b3_multi(headers))
+    }
+}
+
+impl ContextInjector for B3 {
+    fn inject(&self, context: &SpanContext, headers: &mut HeaderMap) {
+        let sampled = if context.sampled { 1 } else { 0 };
+        let value = format!(
+            "{:032x}-{:016x}-{sampled}",
+            context.trace_id.0, context.span_id.0
+        );
+        set_header(headers, B3_HEADER, value);
+    }
+}
+
+/// Propagates the context in the [B3](https://github.com/openzipkin/b3-propagation) multi-header
+/// format only, as expected by Zipkin deployments predating the single `b3` header.
+///
+/// The parent context is extracted from `X-B3-TraceId`, `X-B3-SpanId` and `X-B3-Sampled`, and the
+/// context is injected into the same headers. Use [`B3`] to accept both formats.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct B3Multi;
+
+impl ContextExtractor for B3Multi {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        decode_b3_multi(headers)
+    }
+}
+
+impl ContextInjector for B3Multi {
+    fn inject(&self, context: &SpanContext, headers: &mut HeaderMap) {
+        let sampled = if context.sampled { "1" } else { "0" };
+        set_header(
+            headers,
+            B3_TRACE_ID_HEADER,
+            format!("{:032x}", context.trace_id.0),
+        );
+        set_header(
+            headers,
+            B3_SPAN_ID_HEADER,
+            format!("{:016x}", context.span_id.0),
+        );
+        set_header(headers, B3_SAMPLED_HEADER, sampled.to_string());
+    }
+}
+
+/// Decodes the parent context from the single `b3` header,
+/// `{TraceId}-{SpanId}-{SamplingState}-{ParentSpanId}`, where the last two fields are optional.
+///
+/// A header that only carries a sampling state (`b3: 0`) has no context to continue and is ignored.
+fn decode_b3_single(headers: &HeaderMap) -> Option<SpanContext> {
+    let mut parts = header_str(headers, B3_HEADER)?.split('-');
+
+    let trace_id = decode_trace_id(parts.next()?)?;
+    let span_id = parse_span_id(parts.next()?, 16)?;
+    let sampled = match parts.next() {
+        Some("1" | "d") | None => true,
+        Some("0") => false,
+        Some(_) => return None,
+    };
+    if let Some(parent_span_id) = parts.next() {
+        parse_span_id(parent_span_id, 16)?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(SpanContext::new(trace_id, span_id).sampled(sampled))
+}
+
+/// B3 trace ids are either 64 or 128 bits long.
+fn decode_trace_id(s: &str) -> Option<TraceId> {
+    parse_trace_id(s, 32).or_else(|| parse_trace_id(s, 16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::headers;
+
+    const TRACE_ID: &str = "80f198ee56343ba864fe8b2a57d3eff7";
+    const SPAN_ID: &str = "e457b5a2e4d86bd1";
+
+    fn context(trace_id: u128, span_id: u64, sampled: bool) -> Option<SpanContext> {
+        Some(SpanContext::new(TraceId(trace_id), SpanId(span_id)).sampled(sampled))
+    }
+
+    #[test]
+    fn extracts_multi_headers() {
+        let headers = headers([
+            ("x-b3-traceid", TRACE_ID),
+            ("x-b3-spanid", SPAN_ID),
+            ("x-b3-parentspanid", "05e3ac9a4f6e3b90"),
+            ("x-b3-sampled", "1"),
+        ]);
+        assert_eq!(
+            B3.extract(&headers),
+            context(0x80f198ee56343ba864fe8b2a57d3eff7, 0xe457b5a2e4d86bd1, true)
+        );
+    }
+
+    #[test]
+    fn extracts_multi_header_sampling_states() {
+        let extract = |sampled: Option<&str>, flags: Option<&str>| {
+            let mut headers = headers([("x-b3-traceid", TRACE_ID), ("x-b3-spanid", SPAN_ID)]);
+            if let Some(sampled) = sampled {
+                headers.insert(B3_SAMPLED_HEADER, sampled.parse().unwrap());
+            }
+            if let Some(flags) = flags {
+                headers.insert(B3_FLAGS_HEADER, flags.parse().unwrap());
+            }
+            B3.extract(&headers).map(|context| context.sampled)
+        };
+        assert_eq!(extract(None, None), Some(true));
+        assert_eq!(extract(Some("true"), None), Some(true));
+        assert_eq!(extract(Some("0"), None), Some(false));
+        assert_eq!(extract(Some("false"), None), Some(false));
+        assert_eq!(extract(Some("0"), Some("1")), Some(true));
+        assert_eq!(extract(Some("yes"), None), None);
+    }
+
+    #[test]
+    fn extracts_multi_headers_with_64_bit_trace_id() {
+        let headers = headers([
+            ("x-b3-traceid", "64fe8b2a57d3eff7"),
+            ("x-b3-spanid", SPAN_ID),
+        ]);
+        assert_eq!(
+            B3.extract(&headers),
+            context(0x64fe8b2a57d3eff7, 0xe457b5a2e4d86bd1, true)
+        );
+    }
+
+    #[test]
+    fn rejects_multi_headers_with_zero_invalid_or_missing_ids() {
+        let zero_trace_id = "0".repeat(32);
+        let zero_span_id = "0".repeat(16);
+        let cases = [
+            headers([("x-b3-traceid", &zero_trace_id), ("x-b3-spanid", SPAN_ID)]),
+            headers([("x-b3-traceid", TRACE_ID), ("x-b3-spanid", &zero_span_id)]),
+            headers([("x-b3-traceid", TRACE_ID)]),
+            headers([("x-b3-spanid", SPAN_ID)]),
+            headers([
+                ("x-b3-traceid", TRACE_ID),
+                ("x-b3-spanid", SPAN_ID),
+                ("x-b3-parentspanid", "123"),
+            ]),
+        ];
+        for headers in cases {
+            assert_eq!(B3.extract(&headers), None, "{headers:?}");
+        }
+    }
+
+    #[test]
+    fn extracts_single_header() {
+        let headers = headers([("b3", &format!("{TRACE_ID}-{SPAN_ID}-1-05e3ac9a4f6e3b90"))]);
+        assert_eq!(
+            B3.extract(&headers),
+            context(0x80f198ee56343ba864fe8b2a57d3eff7, 0xe457b5a2e4d86bd1, true)
+        );
+    }
+
+    #[test]
+    fn extracts_single_header_sampling_states() {
+        let extract = |value: String| B3.extract(&headers([("b3", &value)]));
+        assert!(extract(format!("{TRACE_ID}-{SPAN_ID}")).unwrap().sampled);
+        assert!(extract(format!("{TRACE_ID}-{SPAN_ID}-d")).unwrap().sampled);
+        assert!(!extract(format!("{TRACE_ID}-{SPAN_ID}-0")).unwrap().sampled);
+        assert_eq!(extract(format!("{TRACE_ID}-{SPAN_ID}-2")), None);
+        assert_eq!(extract(format!("{TRACE_ID}-{SPAN_ID}-true")), None);
+    }
+
+    #[test]
+    fn extracts_single_header_with_64_bit_trace_id() {
+        let headers = headers([("b3", &format!("64fe8b2a57d3eff7-{SPAN_ID}-1"))]);
+        assert_eq!(
+            B3.extract(&headers),
+            context(0x64fe8b2a57d3eff7, 0xe457b5a2e4d86bd1, true)
+        );
+    }
+
+    #[test]
+    fn rejects_single_header_with_zero_ids() {
+        let zero_trace_id = format!("{}-{SPAN_ID}-1", "0".repeat(32));
+        let zero_span_id = format!("{TRACE_ID}-{}-1", "0".repeat(16));
+        assert_eq!(B3.extract(&headers([("b3", &zero_trace_id)])), None);
+        assert_eq!(B3.extract(&headers([("b3", &zero_span_id)])), None);
+    }
+
+    #[test]
+    fn rejects_single_header_with_missing_or_extra_fields() {
+        let extract = |value: String| B3.extract(&headers([("b3", &value)]));
+        assert_eq!(extract("0".to_string()), None);
+        assert_eq!(extract(TRACE_ID.to_string()), None);
+        assert_eq!(extract(format!("{TRACE_ID}-{SPAN_ID}-1-{SPAN_ID}-1")), None);
+        assert_eq!(extract(format!("{TRACE_ID}-{SPAN_ID}-1-123")), None);
+    }
+
+    #[test]
+    fn single_header_takes_precedence() {
+        let headers = headers([
+            ("b3", &format!("{TRACE_ID}-{SPAN_ID}-0")),
+            ("x-b3-traceid", "64fe8b2a57d3eff7"),
+            ("x-b3-spanid", "05e3ac9a4f6e3b90"),
+        ]);
+        assert_eq!(
+            B3.extract(&headers),
+            context(
+                0x80f198ee56343ba864fe8b2a57d3eff7,
+                0xe457b5a2e4d86bd1,
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn b3_multi_ignores_the_single_header() {
+        let both = headers([
+            ("b3", &format!("{TRACE_ID}-{SPAN_ID}-0")),
+            ("x-b3-traceid", "64fe8b2a57d3eff7"),
+            ("x-b3-spanid", "05e3ac9a4f6e3b90"),
+        ]);
+        assert_eq!(
+            B3Multi.extract(&both),
+            context(0x64fe8b2a57d3eff7, 0x05e3ac9a4f6e3b90, true)
+        );
+        assert_eq!(
+            B3Multi.extract(&headers([("b3", &format!("{TRACE_ID}-{SPAN_ID}"))])),
+            None
+        );
+    }
+
+    #[test]
+    fn injects_multi_headers() {
+        let context = SpanContext::new(TraceId(0xabc), SpanId(0x12)).sampled(true);
+        let mut headers = HeaderMap::new();
+        B3Multi.inject(&context, &mut headers);
+        assert_eq!(
+            headers[B3_TRACE_ID_HEADER],
+            "00000000000000000000000000000abc"
+        );
+        assert_eq!(headers[B3_SPAN_ID_HEADER], "0000000000000012");
+        assert_eq!(headers[B3_SAMPLED_HEADER], "1");
+        assert!(!headers.contains_key(B3_HEADER));
+        assert_eq!(B3Multi.extract(&headers), Some(context));
+    }
+
+    #[test]
+    fn injects_single_header() {
+        let context = SpanContext::new(TraceId(0xabc), SpanId(0x12)).sampled(false);
+        let mut headers = HeaderMap::new();
+        B3.inject(&context, &mut headers);
+        assert_eq!(
+            headers[B3_HEADER],
+            "00000000000000000000000000000abc-0000000000000012-0"
+        );
+        assert_eq!(B3.extract(&headers), Some(context));
+    }
+}
@@ -0,0 +1,108 @@
+//! [W3C Baggage](https://www.w3.org/TR/baggage/) propagation.
+
+use percent_encoding::percent_decode_str;
+use poem::http::HeaderMap;
+
+use crate::BAGGAGE_HEADER;
+
+/// The application-defined key-value pairs received in the `baggage` header.
+///
+/// The middleware stores it in the request extensions, so handlers can read it with
+/// `Option<Data<&Baggage>>`. Selected entries can also be recorded on the root span with
+/// [`FastraceMiddlewareBuilder::with_baggage_properties`](crate::FastraceMiddlewareBuilder::with_baggage_properties).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Baggage {
+    header: String,
+    entries: Vec<(String, String)>,
+}
+
+impl Baggage {
+    /// Returns the value of the entry with the given key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Iterates over the entries in the order they were received, with values percent-decoded.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the `baggage` header value as received, for forwarding to downstream requests.
+    pub fn as_str(&self) -> &str {
+        &self.header
+    }
+
+    /// Reads the `baggage` header, combining multiple header lines into a single list.
+    ///
+    /// Entry properties (`key=value;property`) are dropped, and malformed entries are skipped.
+    pub(crate) fn extract(headers: &HeaderMap) -> Option<Self> {
+        let mut header = String::new();
+        for value in headers.get_all(BAGGAGE_HEADER) {
+            let value = value.to_str().ok()?.trim();
+            if value.is_empty() {
+                continue;
+            }
+            if !header.is_empty() {
+                header.push(',');
+            }
+            header.push_str(value);
+        }
+        if header.is_empty() {
+            return None;
+        }
+
+        let entries = header
+            .split(',')
+            .filter_map(|member| {
+                let member = member.split(';').next()?;
+                let (key, value) = member.split_once('=')?;
+                let key = key.trim();
+                if key.is_empty() {
+                    return None;
+                }
+                let value = percent_decode_str(value.trim()).decode_utf8().ok()?;
+                Some((key.to_string(), value.into_owned()))
+            })
+            .collect();
+
+        Some(Self { header, entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::headers;
+
+    #[test]
+    fn extracts_entries() {
+        let mut headers = headers([(BAGGAGE_HEADER, "userId=alice, region = eu%20west ;ttl=60")]);
+        headers.append(BAGGAGE_HEADER, "tenant=acme".parse().unwrap());
+        let baggage = Baggage::extract(&headers).unwrap();
+
+        assert_eq!(baggage.get("userId"), Some("alice"));
+        assert_eq!(baggage.get("region"), Some("eu west"));
+        assert_eq!(baggage.get("ttl"), None);
+        assert_eq!(baggage.iter().count(), 3);
+        assert_eq!(
+            baggage.as_str(),
+            "userId=alice, region = eu%20west ;ttl=60,tenant=acme"
+        );
+    }
+
+    #[test]
+    fn skips_malformed_entries() {
+        let headers = headers([(BAGGAGE_HEADER, "novalue,=empty,bad=%ff,ok=1")]);
+        let baggage = Baggage::extract(&headers).unwrap();
+        assert_eq!(baggage.iter().collect::<Vec<_>>(), [("ok", "1")]);
+    }
+
+    #[test]
+    fn ignores_missing_header() {
+        assert_eq!(Baggage::extract(&HeaderMap::new()), None);
+        assert_eq!(Baggage::extract(&headers([(BAGGAGE_HEADER, " ")])), None);
+    }
+}
@@ -0,0 +1,90 @@
+//! [Google Cloud Trace](https://cloud.google.com/trace/docs/trace-context#legacy-http-header)
+//! propagation as injected by Google Cloud Load Balancing and Cloud Run.
+
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+use super::ContextExtractor;
+use super::header_str;
+use super::parse_trace_id;
+
+const X_CLOUD_TRACE_CONTEXT_HEADER: &str = "x-cloud-trace-context";
+
+/// Extracts the parent context from the Google Cloud `X-Cloud-Trace-Context` header,
+/// `{TRACE_ID}/{SPAN_ID};o={TRACE_TRUE}`.
+///
+/// The span id is decimal. A missing `o` option is treated as sampled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CloudTraceContext;
+
+impl ContextExtractor for CloudTraceContext {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        decode_cloud_trace_context(headers)
+    }
+}
+
+fn decode_cloud_trace_context(headers: &HeaderMap) -> Option<SpanContext> {
+    let value = header_str(headers, X_CLOUD_TRACE_CONTEXT_HEADER)?;
+    let (trace_id, rest) = value.split_once('/')?;
+    let (span_id, options) = match rest.split_once(';') {
+        Some((span_id, options)) => (span_id, Some(options)),
+        None => (rest, None),
+    };
+
+    let trace_id = parse_trace_id(trace_id, 32)?;
+    let span_id = span_id.parse::<u64>().ok().filter(|id| *id != 0)?;
+    let sampled = match options {
+        Some("o=1") | None => true,
+        Some("o=0") => false,
+        Some(_) => return None,
+    };
+
+    Some(SpanContext::new(trace_id, SpanId(span_id)).sampled(sampled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::headers;
+
+    const TRACE_ID: &str = "105445aa7843bc8bf206b12000100000";
+
+    fn extract(value: &str) -> Option<SpanContext> {
+        CloudTraceContext.extract(&headers([(X_CLOUD_TRACE_CONTEXT_HEADER, value)]))
+    }
+
+    #[test]
+    fn extracts_context() {
+        assert_eq!(
+            extract(&format!("{TRACE_ID}/1;o=1")),
+            Some(
+                SpanContext::new(TraceId(0x105445aa7843bc8bf206b12000100000), SpanId(1))
+                    .sampled(true)
+            )
+        );
+    }
+
+    #[test]
+    fn extracts_options() {
+        let sampled = |rest: &str| extract(&format!("{TRACE_ID}/{rest}")).map(|c| c.sampled);
+        assert_eq!(sampled("18446744073709551615"), Some(true));
+        assert_eq!(sampled("1;o=0"), Some(false));
+        assert_eq!(sampled("1;o=2"), None);
+        assert_eq!(sampled("1;o=1;extra"), None);
+    }
+
+    #[test]
+    fn rejects_zero_ids() {
+        assert_eq!(extract(&format!("{}/1;o=1", "0".repeat(32))), None);
+        assert_eq!(extract(&format!("{TRACE_ID}/0;o=1")), None);
+    }
+
+    #[test]
+    fn rejects_invalid_or_missing_fields() {
+        assert_eq!(extract(TRACE_ID), None);
+        assert_eq!(extract(&format!("{TRACE_ID}/")), None);
+        assert_eq!(extract(&format!("{TRACE_ID}/abc")), None);
+        assert_eq!(extract(&format!("{TRACE_ID}/18446744073709551616")), None);
+        assert_eq!(extract("f206b12000100000/1"), None);
+    }
+}
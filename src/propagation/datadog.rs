@@ -0,0 +1,160 @@
+//! [Datadog propagation](https://docs.datadoghq.com/tracing/trace_collection/trace_context_propagation/)
+//! as used by the Datadog tracing libraries.
+
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+use super::ContextExtractor;
+use super::ContextInjector;
+use super::header_str;
+use super::set_header;
+
+const TRACE_ID_HEADER: &str = "x-datadog-trace-id";
+const PARENT_ID_HEADER: &str = "x-datadog-parent-id";
+const SAMPLING_PRIORITY_HEADER: &str = "x-datadog-sampling-priority";
+const TAGS_HEADER: &str = "x-datadog-tags";
+
+/// Propagation tag carrying the upper 64 bits of a 128-bit trace id.
+const TRACE_ID_HIGH_TAG: &str = "_dd.p.tid";
+
+/// Extracts the parent context from the Datadog `x-datadog-*` headers.
+///
+/// Datadog ids are decimal. The trace id only carries the lower 64 bits, the upper 64 bits are
+/// taken from the `_dd.p.tid` tag in `x-datadog-tags` when present. A positive sampling priority
+/// (auto or user keep) maps to sampled, and a missing priority is treated as sampled. The context
+/// is injected the same way, with the auto keep or auto reject priority.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Datadog;
+
+impl ContextExtractor for Datadog {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        decode_datadog(headers)
+    }
+}
+
+impl ContextInjector for Datadog {
+    fn inject(&self, context: &SpanContext, headers: &mut HeaderMap) {
+        let trace_id_low = context.trace_id.0 as u64;
+        let trace_id_high = (context.trace_id.0 >> 64) as u64;
+        set_header(headers, TRACE_ID_HEADER, trace_id_low.to_string());
+        set_header(headers, PARENT_ID_HEADER, context.span_id.0.to_string());
+        let priority = if context.sampled { "1" } else { "0" };
+        set_header(headers, SAMPLING_PRIORITY_HEADER, priority.to_string());
+        if trace_id_high != 0 {
+            let tags = format!("{TRACE_ID_HIGH_TAG}={trace_id_high:016x}");
+            set_header(headers, TAGS_HEADER, tags);
+        }
+    }
+}
+
+fn decode_datadog(headers: &HeaderMap) -> Option<SpanContext> {
+    let trace_id_low = header_str(headers, TRACE_ID_HEADER)?.parse::<u64>().ok()?;
+    let parent_id = header_str(headers, PARENT_ID_HEADER)?.parse::<u64>().ok()?;
+    if trace_id_low == 0 || parent_id == 0 {
+        return None;
+    }
+
+    let trace_id_high = header_str(headers, TAGS_HEADER)
+        .and_then(|tags| {
+            tags.split(',')
+                .find_map(|tag| tag.strip_prefix(TRACE_ID_HIGH_TAG)?.strip_prefix('='))
+        })
+        .and_then(|high| u64::from_str_radix(high, 16).ok())
+        .unwrap_or_default();
+
+    let sampled = match header_str(headers, SAMPLING_PRIORITY_HEADER) {
+        Some(priority) => priority.parse::<i32>().ok()? > 0,
+        None => true,
+    };
+
+    let trace_id = TraceId(((trace_id_high as u128) << 64) | trace_id_low as u128);
+    Some(SpanContext::new(trace_id, SpanId(parent_id)).sampled(sampled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::headers;
+
+    #[test]
+    fn extracts_64_bit_trace_id() {
+        let headers = headers([
+            (TRACE_ID_HEADER, "1234567890123456789"),
+            (PARENT_ID_HEADER, "987654321"),
+            (SAMPLING_PRIORITY_HEADER, "1"),
+        ]);
+        assert_eq!(
+            Datadog.extract(&headers),
+            Some(SpanContext::new(TraceId(1234567890123456789), SpanId(987654321)).sampled(true))
+        );
+    }
+
+    #[test]
+    fn extracts_128_bit_trace_id_from_tags() {
+        let headers = headers([
+            (TRACE_ID_HEADER, "1"),
+            (PARENT_ID_HEADER, "2"),
+            (TAGS_HEADER, "_dd.p.dm=-1,_dd.p.tid=640cfd8d00000000"),
+        ]);
+        let context = Datadog.extract(&headers).unwrap();
+        assert_eq!(
+            context.trace_id,
+            TraceId(0x640cfd8d00000000_0000000000000001)
+        );
+    }
+
+    #[test]
+    fn extracts_sampling_priority() {
+        let sampled = |priority: Option<&str>| {
+            let mut headers = headers([(TRACE_ID_HEADER, "1"), (PARENT_ID_HEADER, "2")]);
+            if let Some(priority) = priority {
+                headers.insert(SAMPLING_PRIORITY_HEADER, priority.parse().unwrap());
+            }
+            Datadog.extract(&headers).map(|context| context.sampled)
+        };
+        assert_eq!(sampled(None), Some(true));
+        assert_eq!(sampled(Some("2")), Some(true));
+        assert_eq!(sampled(Some("0")), Some(false));
+        assert_eq!(sampled(Some("-1")), Some(false));
+        assert_eq!(sampled(Some("keep")), None);
+    }
+
+    #[test]
+    fn rejects_zero_invalid_or_missing_ids() {
+        let cases = [
+            headers([(TRACE_ID_HEADER, "0"), (PARENT_ID_HEADER, "2")]),
+            headers([(TRACE_ID_HEADER, "1"), (PARENT_ID_HEADER, "0")]),
+            headers([(TRACE_ID_HEADER, "0x1"), (PARENT_ID_HEADER, "2")]),
+            headers([
+                (TRACE_ID_HEADER, "18446744073709551616"),
+                (PARENT_ID_HEADER, "2"),
+            ]),
+            headers([(TRACE_ID_HEADER, "1")]),
+            headers([(PARENT_ID_HEADER, "2")]),
+        ];
+        for headers in cases {
+            assert_eq!(Datadog.extract(&headers), None, "{headers:?}");
+        }
+    }
+
+    #[test]
+    fn injects_context() {
+        let context = SpanContext::new(TraceId((7 << 64) | 42), SpanId(3)).sampled(false);
+        let mut headers = HeaderMap::new();
+        Datadog.inject(&context, &mut headers);
+        assert_eq!(headers[TRACE_ID_HEADER], "42");
+        assert_eq!(headers[PARENT_ID_HEADER], "3");
+        assert_eq!(headers[SAMPLING_PRIORITY_HEADER], "0");
+        assert_eq!(headers[TAGS_HEADER], "_dd.p.tid=0000000000000007");
+        assert_eq!(Datadog.extract(&headers), Some(context));
+    }
+
+    #[test]
+    fn injects_64_bit_trace_id_without_tags() {
+        let context = SpanContext::new(TraceId(42), SpanId(3)).sampled(true);
+        let mut headers = HeaderMap::new();
+        Datadog.inject(&context, &mut headers);
+        assert!(!headers.contains_key(TAGS_HEADER));
+        assert_eq!(Datadog.extract(&headers), Some(context));
+    }
+}
@@ -0,0 +1,149 @@
+//! The [`grpc-trace-bin`](https://github.com/census-instrumentation/opencensus-specs/blob/master/encodings/BinaryEncoding.md)
+//! binary trace context used by gRPC.
+
+use base64::Engine;
+use base64::alphabet;
+use base64::engine::DecodePaddingMode;
+use base64::engine::GeneralPurpose;
+use base64::engine::GeneralPurposeConfig;
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+use super::ContextExtractor;
+use super::header_str;
+
+const GRPC_TRACE_BIN_HEADER: &str = "grpc-trace-bin";
+
+/// Binary metadata is base64 encoded on the wire, with or without padding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+const VERSION: u8 = 0;
+const TRACE_ID_FIELD: u8 = 0;
+const SPAN_ID_FIELD: u8 = 1;
+const TRACE_OPTIONS_FIELD: u8 = 2;
+const SAMPLED_OPTION: u8 = 0x01;
+
+/// Extracts the parent context from the `grpc-trace-bin` metadata sent by gRPC clients.
+///
+/// The metadata is a version byte followed by the trace id, span id and trace options fields,
+/// each prefixed with its field id. A missing trace options field is treated as sampled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcTraceBin;
+
+impl ContextExtractor for GrpcTraceBin {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        let bytes = BASE64
+            .decode(header_str(headers, GRPC_TRACE_BIN_HEADER)?)
+            .ok()?;
+        decode(&bytes)
+    }
+}
+
+fn decode(bytes: &[u8]) -> Option<SpanContext> {
+    let (&version, mut rest) = bytes.split_first()?;
+    if version != VERSION {
+        return None;
+    }
+
+    let mut trace_id = None;
+    let mut span_id = None;
+    let mut sampled = true;
+    while let Some((&field, fields)) = rest.split_first() {
+        rest = match field {
+            TRACE_ID_FIELD => {
+                let (id, rest) = split_array::<16>(fields)?;
+                trace_id = Some(u128::from_be_bytes(id));
+                rest
+            }
+            SPAN_ID_FIELD => {
+                let (id, rest) = split_array::<8>(fields)?;
+                span_id = Some(u64::from_be_bytes(id));
+                rest
+            }
+            TRACE_OPTIONS_FIELD => {
+                let (options, rest) = split_array::<1>(fields)?;
+                sampled = options[0] & SAMPLED_OPTION != 0;
+                rest
+            }
+            // Fields are ordered by id and unknown fields cannot be skipped.
+            _ => break,
+        };
+    }
+
+    let trace_id = trace_id.filter(|id| *id != 0)?;
+    let span_id = span_id.filter(|id| *id != 0)?;
+    Some(SpanContext::new(TraceId(trace_id), SpanId(span_id)).sampled(sampled))
+}
+
+fn split_array<const N: usize>(bytes: &[u8]) -> Option<([u8; N], &[u8])> {
+    if bytes.len() < N {
+        return None;
+    }
+    let (head, rest) = bytes.split_at(N);
+    Some((head.try_into().ok()?, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(trace_id: u128, span_id: u64, options: Option<u8>) -> Vec<u8> {
+        let mut bytes = vec![VERSION, TRACE_ID_FIELD];
+        bytes.extend(trace_id.to_be_bytes());
+        bytes.push(SPAN_ID_FIELD);
+        bytes.extend(span_id.to_be_bytes());
+        if let Some(options) = options {
+            bytes.extend([TRACE_OPTIONS_FIELD, options]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn extracts_context_with_or_without_padding() {
+        let bytes = encode(0xabc, 0x12, Some(SAMPLED_OPTION));
+        let expected = Some(SpanContext::new(TraceId(0xabc), SpanId(0x12)).sampled(true));
+        for value in [
+            base64::engine::general_purpose::STANDARD.encode(&bytes),
+            base64::engine::general_purpose::STANDARD_NO_PAD.encode(&bytes),
+        ] {
+            let headers = crate::propagation::headers([(GRPC_TRACE_BIN_HEADER, &value)]);
+            assert_eq!(GrpcTraceBin.extract(&headers), expected);
+        }
+    }
+
+    #[test]
+    fn decodes_trace_options() {
+        assert!(decode(&encode(1, 2, None)).unwrap().sampled);
+        assert!(decode(&encode(1, 2, Some(SAMPLED_OPTION))).unwrap().sampled);
+        assert!(!decode(&encode(1, 2, Some(0))).unwrap().sampled);
+    }
+
+    #[test]
+    fn rejects_zero_ids() {
+        assert_eq!(decode(&encode(0, 2, None)), None);
+        assert_eq!(decode(&encode(1, 0, None)), None);
+    }
+
+    #[test]
+    fn rejects_unknown_version_and_truncated_fields() {
+        let mut unknown_version = encode(1, 2, None);
+        unknown_version[0] = 1;
+        assert_eq!(decode(&unknown_version), None);
+
+        let truncated = encode(1, 2, Some(SAMPLED_OPTION));
+        assert_eq!(decode(&truncated[..truncated.len() - 1]), None);
+        assert_eq!(decode(&truncated[..10]), None);
+        assert_eq!(decode(&[VERSION]), None);
+        assert_eq!(decode(&[]), None);
+    }
+
+    #[test]
+    fn stops_at_unknown_fields() {
+        let mut bytes = encode(1, 2, Some(0));
+        bytes.extend([3, 0xff]);
+        assert_eq!(decode(&bytes).map(|context| context.sampled), Some(false));
+    }
+}
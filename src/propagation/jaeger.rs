@@ -0,0 +1,161 @@
+//! [Jaeger propagation](https://www.jaegertracing.io/docs/1.21/client-libraries/#propagation-format)
+//! as emitted by the legacy jaeger-client libraries.
+
+use std::borrow::Cow;
+
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+use super::ContextExtractor;
+use super::ContextInjector;
+use super::header_str;
+use super::set_header;
+
+const UBER_TRACE_ID_HEADER: &str = "uber-trace-id";
+
+const SAMPLED_FLAG: u8 = 0x01;
+const DEBUG_FLAG: u8 = 0x02;
+
+/// Extracts the parent context from the Jaeger `uber-trace-id` header,
+/// `{trace-id}:{span-id}:{parent-span-id}:{flags}`.
+///
+/// Some clients URL-encode the header value, so `%3A` is accepted in place of `:`. The context is
+/// injected with an unset parent span id, which is deprecated.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Jaeger;
+
+impl ContextExtractor for Jaeger {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        decode_uber_trace_id(headers)
+    }
+}
+
+impl ContextInjector for Jaeger {
+    fn inject(&self, context: &SpanContext, headers: &mut HeaderMap) {
+        let flags = if context.sampled { SAMPLED_FLAG } else { 0 };
+        let value = format!(
+            "{:032x}:{:016x}:0:{flags:x}",
+            context.trace_id.0, context.span_id.0
+        );
+        set_header(headers, UBER_TRACE_ID_HEADER, value);
+    }
+}
+
+fn decode_uber_trace_id(headers: &HeaderMap) -> Option<SpanContext> {
+    let value = header_str(headers, UBER_TRACE_ID_HEADER)?;
+    let value = if value.contains('%') {
+        Cow::Owned(value.replace("%3A", ":").replace("%3a", ":"))
+    } else {
+        Cow::Borrowed(value)
+    };
+
+    let mut parts = value.split(':');
+    let (Some(trace_id), Some(span_id), Some(_parent_span_id), Some(flags), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return None;
+    };
+
+    let trace_id = parse_hex(trace_id, 32).and_then(|id| u128::from_str_radix(id, 16).ok())?;
+    let span_id = parse_hex(span_id, 16).and_then(|id| u64::from_str_radix(id, 16).ok())?;
+    if trace_id == 0 || span_id == 0 {
+        return None;
+    }
+    let flags = u8::from_str_radix(parse_hex(flags, 2)?, 16).ok()?;
+    let sampled = flags & (SAMPLED_FLAG | DEBUG_FLAG) != 0;
+
+    Some(SpanContext::new(TraceId(trace_id), SpanId(span_id)).sampled(sampled))
+}
+
+/// Jaeger ids are hex strings of variable length, without zero padding.
+fn parse_hex(s: &str, max_len: usize) -> Option<&str> {
+    (!s.is_empty() && s.len() <= max_len && s.bytes().all(|b| b.is_ascii_hexdigit())).then_some(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::headers;
+
+    fn extract(value: &str) -> Option<SpanContext> {
+        Jaeger.extract(&headers([(UBER_TRACE_ID_HEADER, value)]))
+    }
+
+    #[test]
+    fn extracts_context() {
+        assert_eq!(
+            extract("80f198ee56343ba864fe8b2a57d3eff7:e457b5a2e4d86bd1:05e3ac9a4f6e3b90:1"),
+            Some(
+                SpanContext::new(
+                    TraceId(0x80f198ee56343ba864fe8b2a57d3eff7),
+                    SpanId(0xe457b5a2e4d86bd1)
+                )
+                .sampled(true)
+            )
+        );
+    }
+
+    #[test]
+    fn extracts_url_encoded_context() {
+        let context =
+            extract("80f198ee56343ba864fe8b2a57d3eff7%3Ae457b5a2e4d86bd1%3a0%3A1").unwrap();
+        assert_eq!(context.span_id, SpanId(0xe457b5a2e4d86bd1));
+    }
+
+    #[test]
+    fn extracts_64_bit_and_unpadded_ids() {
+        assert_eq!(
+            extract("64fe8b2a57d3eff7:abc:0:1"),
+            Some(SpanContext::new(TraceId(0x64fe8b2a57d3eff7), SpanId(0xabc)).sampled(true))
+        );
+    }
+
+    #[test]
+    fn extracts_flags() {
+        let sampled = |flags: &str| extract(&format!("abc:def:0:{flags}")).map(|c| c.sampled);
+        assert_eq!(sampled("1"), Some(true));
+        assert_eq!(sampled("2"), Some(true));
+        assert_eq!(sampled("3"), Some(true));
+        assert_eq!(sampled("0"), Some(false));
+        assert_eq!(sampled("4"), Some(false));
+        assert_eq!(sampled("x"), None);
+        assert_eq!(sampled("100"), None);
+    }
+
+    #[test]
+    fn rejects_zero_ids() {
+        assert_eq!(extract("0:e457b5a2e4d86bd1:0:1"), None);
+        assert_eq!(
+            extract(&format!("{}:e457b5a2e4d86bd1:0:1", "0".repeat(32))),
+            None
+        );
+        assert_eq!(extract("80f198ee56343ba8:0:0:1"), None);
+    }
+
+    #[test]
+    fn rejects_missing_or_extra_fields() {
+        assert_eq!(extract("80f198ee56343ba8:e457b5a2e4d86bd1:1"), None);
+        assert_eq!(extract("80f198ee56343ba8:e457b5a2e4d86bd1:0:1:0"), None);
+        assert_eq!(extract("80f198ee56343ba8::0:1"), None);
+        assert_eq!(
+            extract(&format!("{}:e457b5a2e4d86bd1:0:1", "f".repeat(33))),
+            None
+        );
+    }
+
+    #[test]
+    fn injects_context() {
+        let context = SpanContext::new(TraceId(0xabc), SpanId(0x12)).sampled(true);
+        let mut headers = HeaderMap::new();
+        Jaeger.inject(&context, &mut headers);
+        assert_eq!(
+            headers[UBER_TRACE_ID_HEADER],
+            "00000000000000000000000000000abc:0000000000000012:0:1"
+        );
+        assert_eq!(Jaeger.extract(&headers), Some(context));
+    }
+}
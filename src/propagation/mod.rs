@@ -0,0 +1,342 @@
+//! Extraction of the parent [`SpanContext`] from incoming request headers, and injection of the
+//! current one into outgoing request headers.
+//!
+//! [`FastraceMiddleware`](struct@crate::FastraceMiddleware) reads the parent context through a
+//! [`ContextExtractor`], and [`inject_context`](crate::inject_context) writes the current context
+//! through a [`ContextInjector`]. This module provides an extractor for each supported header
+//! format and an injector for the most common ones, and custom formats can be plugged in by
+//! implementing the traits or passing a closure.
+
+mod b3;
+mod baggage;
+mod cloud_trace;
+mod datadog;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod jaeger;
+#[cfg(feature = "skywalking")]
+mod skywalking;
+mod w3c;
+#[cfg(feature = "xray")]
+pub(crate) mod xray;
+
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+use poem::http::HeaderName;
+use poem::http::HeaderValue;
+
+pub use self::b3::B3;
+pub use self::b3::B3Multi;
+pub use self::baggage::Baggage;
+pub use self::cloud_trace::CloudTraceContext;
+pub use self::datadog::Datadog;
+#[cfg(feature = "grpc")]
+pub use self::grpc::GrpcTraceBin;
+pub use self::jaeger::Jaeger;
+#[cfg(feature = "skywalking")]
+pub use self::skywalking::SkyWalking;
+pub use self::w3c::TraceState;
+pub use self::w3c::W3CTraceContext;
+#[cfg(feature = "xray")]
+pub use self::xray::XRay;
+
+/// Extracts the parent [`SpanContext`] of a request from its headers.
+///
+/// Returning `None` means the request carries no usable context in this format.
+///
+/// # Example
+///
+/// ```
+/// use fastrace::prelude::*;
+/// use fastrace_poem::FastraceMiddleware;
+/// use poem::http::HeaderMap;
+///
+/// let middleware = FastraceMiddleware::builder()
+///     .with_extractor(|headers: &HeaderMap| {
+///         let trace_id = headers
+///             .get("x-request-trace")?
+///             .to_str()
+///             .ok()?
+///             .parse()
+///             .ok()?;
+///         Some(SpanContext::new(trace_id, SpanId::default()))
+///     })
+///     .build();
+/// ```
+pub trait ContextExtractor: Send + Sync + 'static {
+    /// Extracts the parent context from the request headers.
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext>;
+
+    /// Extracts every context found in the request headers, in order of precedence.
+    ///
+    /// This is used to detect requests carrying conflicting contexts in different formats. The
+    /// default implementation returns the result of [`extract`](Self::extract).
+    fn extract_all(&self, headers: &HeaderMap) -> Vec<SpanContext> {
+        self.extract(headers).into_iter().collect()
+    }
+}
+
+impl<F> ContextExtractor for F
+where F: Fn(&HeaderMap) -> Option<SpanContext> + Send + Sync + 'static
+{
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        self(headers)
+    }
+}
+
+/// An ordered list of extractors where the first successful extraction wins.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::propagation::B3;
+/// use fastrace_poem::propagation::ExtractorChain;
+/// use fastrace_poem::propagation::W3CTraceContext;
+///
+/// // Prefer W3C Trace Context, but still accept legacy callers sending B3.
+/// let chain = ExtractorChain::new().with(W3CTraceContext::new()).with(B3);
+/// let middleware = FastraceMiddleware::builder().with_extractor(chain).build();
+/// ```
+#[derive(Default)]
+pub struct ExtractorChain {
+    extractors: Vec<Box<dyn ContextExtractor>>,
+}
+
+impl ExtractorChain {
+    /// Creates an empty chain, which never extracts a context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the chain used by default, trying every built-in format in turn.
+    ///
+    /// W3C Trace Context takes precedence over B3, followed by Jaeger, Datadog, Google Cloud Trace
+    /// and, with the `xray`, `skywalking` and `grpc` features, AWS X-Ray, SkyWalking and gRPC's
+    /// `grpc-trace-bin`.
+    pub fn builtin() -> Self {
+        Self::builtin_with(W3CTraceContext::new())
+    }
+
+    /// Creates the default chain with a custom W3C Trace Context extractor.
+    pub(crate) fn builtin_with(w3c: W3CTraceContext) -> Self {
+        let chain = Self::new()
+            .with(w3c)
+            .with(B3)
+            .with(Jaeger)
+            .with(Datadog)
+            .with(CloudTraceContext);
+
+        #[cfg(feature = "xray")]
+        let chain = chain.with(XRay);
+
+        #[cfg(feature = "skywalking")]
+        let chain = chain.with(SkyWalking);
+
+        #[cfg(feature = "grpc")]
+        let chain = chain.with(GrpcTraceBin);
+
+        chain
+    }
+
+    /// Appends an extractor, which is tried after all extractors already in the chain.
+    pub fn with(mut self, extractor: impl ContextExtractor) -> Self {
+        self.extractors.push(Box::new(extractor));
+        self
+    }
+}
+
+impl ContextExtractor for ExtractorChain {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        self.extractors
+            .iter()
+            .find_map(|extractor| extractor.extract(headers))
+    }
+
+    fn extract_all(&self, headers: &HeaderMap) -> Vec<SpanContext> {
+        self.extractors
+            .iter()
+            .flat_map(|extractor| extractor.extract_all(headers))
+            .collect()
+    }
+}
+
+/// Writes a [`SpanContext`] into the headers of an outgoing request.
+///
+/// # Example
+///
+/// ```
+/// use fastrace::prelude::*;
+/// use fastrace_poem::FastraceMiddleware;
+/// use poem::http::HeaderMap;
+///
+/// let middleware = FastraceMiddleware::builder()
+///     .with_injector(|context: &SpanContext, headers: &mut HeaderMap| {
+///         headers.insert(
+///             "x-request-trace",
+///             context.trace_id.to_string().parse().unwrap(),
+///         );
+///     })
+///     .build();
+/// ```
+pub trait ContextInjector: Send + Sync + 'static {
+    /// Writes the context into the request headers.
+    fn inject(&self, context: &SpanContext, headers: &mut HeaderMap);
+}
+
+impl<F> ContextInjector for F
+where F: Fn(&SpanContext, &mut HeaderMap) + Send + Sync + 'static
+{
+    fn inject(&self, context: &SpanContext, headers: &mut HeaderMap) {
+        self(context, headers)
+    }
+}
+
+/// A list of injectors that all write the context, for downstream services accepting different
+/// formats.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::propagation::B3;
+/// use fastrace_poem::propagation::InjectorChain;
+/// use fastrace_poem::propagation::W3CTraceContext;
+///
+/// let chain = InjectorChain::new().with(W3CTraceContext::new()).with(B3);
+/// let middleware = FastraceMiddleware::builder().with_injector(chain).build();
+/// ```
+#[derive(Default)]
+pub struct InjectorChain {
+    injectors: Vec<Box<dyn ContextInjector>>,
+}
+
+impl InjectorChain {
+    /// Creates an empty chain, which never writes a context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an injector, which writes after all injectors already in the chain.
+    pub fn with(mut self, injector: impl ContextInjector) -> Self {
+        self.injectors.push(Box::new(injector));
+        self
+    }
+}
+
+impl ContextInjector for InjectorChain {
+    fn inject(&self, context: &SpanContext, headers: &mut HeaderMap) {
+        for injector in &self.injectors {
+            injector.inject(context, headers);
+        }
+    }
+}
+
+/// Sets the header, ignoring invalid names and values.
+fn set_header(headers: &mut HeaderMap, name: &str, value: String) {
+    if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+        headers.insert(name, value);
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// Parses a non-zero trace id made of exactly `len` hex digits.
+fn parse_trace_id(s: &str, len: usize) -> Option<TraceId> {
+    if s.len() != len || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u128::from_str_radix(s, 16)
+        .ok()
+        .filter(|id| *id != 0)
+        .map(TraceId)
+}
+
+/// Parses a non-zero span id made of exactly `len` hex digits.
+fn parse_span_id(s: &str, len: usize) -> Option<SpanId> {
+    if s.len() != len || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u64::from_str_radix(s, 16)
+        .ok()
+        .filter(|id| *id != 0)
+        .map(SpanId)
+}
+
+/// Builds the headers of a request from name and value pairs.
+#[cfg(test)]
+fn headers<const N: usize>(pairs: [(&'static str, &str); N]) -> HeaderMap {
+    pairs
+        .into_iter()
+        .map(|(name, value)| {
+            let value = HeaderValue::from_str(value).expect("invalid header value");
+            (HeaderName::from_static(name), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_trace_id_requires_non_zero_hex_digits_of_the_exact_length() {
+        assert_eq!(
+            parse_trace_id("0af7651916cd43dd8448eb211c80319c", 32),
+            Some(TraceId(0x0af7651916cd43dd8448eb211c80319c))
+        );
+        assert_eq!(parse_trace_id("00000000000000000000000000000000", 32), None);
+        assert_eq!(parse_trace_id("0af7651916cd43dd", 32), None);
+        assert_eq!(parse_trace_id("0af7651916cd43dd8448eb211c80319z", 32), None);
+        assert_eq!(parse_trace_id("+af7651916cd43dd8448eb211c80319c", 32), None);
+    }
+
+    #[test]
+    fn parse_span_id_requires_non_zero_hex_digits_of_the_exact_length() {
+        assert_eq!(
+            parse_span_id("b7ad6b7169203331", 16),
+            Some(SpanId(0xb7ad6b7169203331))
+        );
+        assert_eq!(parse_span_id("0000000000000000", 16), None);
+        assert_eq!(parse_span_id("b7ad6b71", 16), None);
+        assert_eq!(parse_span_id("b7ad6b716920333g", 16), None);
+    }
+
+    #[test]
+    fn extractor_chain_uses_the_first_format_present() {
+        let chain = ExtractorChain::new().with(W3CTraceContext::new()).with(B3);
+        let headers = headers([
+            ("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1"),
+            (
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            ),
+        ]);
+
+        let context = chain.extract(&headers).unwrap();
+        assert_eq!(
+            context.trace_id,
+            TraceId(0x0af7651916cd43dd8448eb211c80319c)
+        );
+        assert_eq!(chain.extract_all(&headers).len(), 2);
+    }
+
+    #[test]
+    fn injector_chain_writes_every_format() {
+        let chain = InjectorChain::new().with(W3CTraceContext::new()).with(B3);
+        let context = SpanContext::new(TraceId(1), SpanId(2)).sampled(true);
+        let mut headers = HeaderMap::new();
+        chain.inject(&context, &mut headers);
+
+        assert_eq!(
+            headers["traceparent"],
+            "00-00000000000000000000000000000001-0000000000000002-01"
+        );
+        assert_eq!(
+            headers["b3"],
+            "00000000000000000000000000000001-0000000000000002-1"
+        );
+    }
+}
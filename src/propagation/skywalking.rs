@@ -0,0 +1,172 @@
+//! [SkyWalking Cross Process Propagation Headers Protocol v3](https://skywalking.apache.org/docs/main/next/en/api/x-process-propagation-headers-protocol/).
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+use super::ContextExtractor;
+use super::header_str;
+
+const SW8_HEADER: &str = "sw8";
+
+/// Extracts the parent context from the SkyWalking `sw8` header,
+/// `{sample}-{traceId}-{segmentId}-{spanId}-{service}-{instance}-{endpoint}-{address}`, where
+/// every field but `sample` and `spanId` is base64 encoded.
+///
+/// SkyWalking ids are free-form strings rather than fixed-size integers. A trace id made of 32 hex
+/// digits (optionally as a dashed UUID) is used as is; any other trace id, as well as the
+/// `{segmentId}` and `{spanId}` pair identifying the parent span, is mapped to an id with the
+/// FNV-1a hash so that every service derives the same ids from the same header. An all-zero hex
+/// trace id is invalid and ignored.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SkyWalking;
+
+impl ContextExtractor for SkyWalking {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        decode_sw8(headers)
+    }
+}
+
+fn decode_sw8(headers: &HeaderMap) -> Option<SpanContext> {
+    let mut parts = header_str(headers, SW8_HEADER)?.split('-');
+
+    let sampled = match parts.next()? {
+        "1" => true,
+        "0" => false,
+        _ => return None,
+    };
+    let trace_id = STANDARD.decode(parts.next()?).ok()?;
+    let segment_id = STANDARD.decode(parts.next()?).ok()?;
+    let span_id = parts.next()?.parse::<i32>().ok()?;
+    if parts.count() != 4 {
+        return None;
+    }
+
+    let hex_trace_id = std::str::from_utf8(&trace_id)
+        .ok()
+        .map(|id| id.replace('-', ""))
+        .filter(|id| id.len() == 32)
+        .and_then(|id| u128::from_str_radix(&id, 16).ok());
+    let trace_id = match hex_trace_id {
+        Some(0) => return None,
+        Some(trace_id) => trace_id,
+        None => fnv1a_128(&trace_id),
+    };
+    let span_id = fnv1a_64(&[segment_id.as_slice(), &span_id.to_be_bytes()].concat());
+
+    Some(SpanContext::new(TraceId(trace_id), SpanId(span_id)).sampled(sampled))
+}
+
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+fn fnv1a_128(bytes: &[u8]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u128).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::headers;
+
+    fn sw8(sample: &str, trace_id: &str, segment_id: &str, span_id: &str) -> String {
+        let encode = |value: &str| STANDARD.encode(value);
+        format!(
+            "{sample}-{}-{}-{span_id}-{}-{}-{}-{}",
+            encode(trace_id),
+            encode(segment_id),
+            encode("service"),
+            encode("instance"),
+            encode("/users"),
+            encode("10.0.0.1:8080"),
+        )
+    }
+
+    fn extract(value: &str) -> Option<SpanContext> {
+        SkyWalking.extract(&headers([(SW8_HEADER, value)]))
+    }
+
+    #[test]
+    fn extracts_hex_trace_id() {
+        let context = extract(&sw8(
+            "1",
+            "0af7651916cd43dd8448eb211c80319c",
+            "segment",
+            "3",
+        ))
+        .unwrap();
+        assert_eq!(
+            context.trace_id,
+            TraceId(0x0af7651916cd43dd8448eb211c80319c)
+        );
+        assert!(context.sampled);
+    }
+
+    #[test]
+    fn extracts_uuid_trace_id() {
+        let context = extract(&sw8("0", "0af76519-16cd-43dd-8448-eb211c80319c", "s", "0")).unwrap();
+        assert_eq!(
+            context.trace_id,
+            TraceId(0x0af7651916cd43dd8448eb211c80319c)
+        );
+        assert!(!context.sampled);
+    }
+
+    #[test]
+    fn hashes_free_form_ids() {
+        let first = extract(&sw8("1", "a1b2.c3d4.16000000000000001", "seg.1", "1")).unwrap();
+        let again = extract(&sw8("1", "a1b2.c3d4.16000000000000001", "seg.1", "1")).unwrap();
+        let other_span = extract(&sw8("1", "a1b2.c3d4.16000000000000001", "seg.1", "2")).unwrap();
+        assert_eq!(first, again);
+        assert_eq!(
+            first.trace_id,
+            TraceId(fnv1a_128(b"a1b2.c3d4.16000000000000001"))
+        );
+        assert_eq!(first.trace_id, other_span.trace_id);
+        assert_ne!(first.span_id, other_span.span_id);
+    }
+
+    #[test]
+    fn rejects_zero_trace_id() {
+        assert_eq!(extract(&sw8("1", &"0".repeat(32), "segment", "1")), None);
+    }
+
+    #[test]
+    fn rejects_bad_sample_flag() {
+        assert_eq!(extract(&sw8("2", "trace", "segment", "1")), None);
+        assert_eq!(extract(&sw8("true", "trace", "segment", "1")), None);
+    }
+
+    #[test]
+    fn rejects_invalid_span_id() {
+        assert_eq!(extract(&sw8("1", "trace", "segment", "x")), None);
+    }
+
+    #[test]
+    fn rejects_missing_or_extra_fields() {
+        let value = sw8("1", "trace", "segment", "1");
+        let (without_address, _) = value.rsplit_once('-').unwrap();
+        assert_eq!(extract(without_address), None);
+        assert_eq!(
+            extract(&format!("{value}-{}", STANDARD.encode("extra"))),
+            None
+        );
+        assert_eq!(extract("1"), None);
+        assert_eq!(
+            extract(&format!("1-{}-!!-1-a-b-c-d", STANDARD.encode("trace"))),
+            None
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! [W3C Trace Context](https://www.w3.org/TR/trace-context/) propagation.
+
+use std::borrow::Cow;
+
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+use super::ContextExtractor;
+use super::ContextInjector;
+use super::header_str;
+use super::set_header;
+use crate::TRACEPARENT_HEADER;
+use crate::TRACESTATE_HEADER;
+
+/// Extracts the parent context from the W3C `traceparent` header, and injects it into the same
+/// header.
+#[derive(Clone, Debug)]
+pub struct W3CTraceContext {
+    header: Cow<'static, str>,
+}
+
+impl Default for W3CTraceContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl W3CTraceContext {
+    /// Creates an extractor reading the standard `traceparent` header.
+    pub fn new() -> Self {
+        Self {
+            header: Cow::Borrowed(TRACEPARENT_HEADER),
+        }
+    }
+
+    /// Creates an extractor reading a `traceparent`-formatted value from another header, for
+    /// gateways that rename the context header.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fastrace_poem::propagation::W3CTraceContext;
+    ///
+    /// let extractor = W3CTraceContext::with_header("x-internal-traceparent");
+    /// ```
+    pub fn with_header(name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            header: match name.into() {
+                Cow::Borrowed(name) if !name.bytes().any(|b| b.is_ascii_uppercase()) => {
+                    Cow::Borrowed(name)
+                }
+                name => Cow::Owned(name.to_ascii_lowercase()),
+            },
+        }
+    }
+
+    /// Returns the name of the header the context is read from.
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+}
+
+impl ContextExtractor for W3CTraceContext {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        header_str(headers, &self.header).and_then(SpanContext::decode_w3c_traceparent)
+    }
+}
+
+impl ContextInjector for W3CTraceContext {
+    fn inject(&self, context: &SpanContext, headers: &mut HeaderMap) {
+        set_header(headers, &self.header, context.encode_w3c_traceparent());
+    }
+}
+
+/// The vendor-specific [`tracestate`](https://www.w3.org/TR/trace-context/#tracestate-header)
+/// received along with the `traceparent` header.
+///
+/// It is only kept when the parent context of the request was read from a valid `traceparent`,
+/// as the specification forbids propagating it otherwise.
+///
+/// The middleware stores it in the request extensions, so handlers can read it with
+/// `Option<Data<&TraceState>>` and forward it to downstream requests or responses.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::TRACESTATE_HEADER;
+/// use fastrace_poem::propagation::TraceState;
+/// use poem::handler;
+/// use poem::http::HeaderMap;
+/// use poem::web::Data;
+///
+/// #[handler]
+/// fn forward(trace_state: Option<Data<&TraceState>>) {
+///     let mut headers = HeaderMap::new();
+///     if let Some(trace_state) = trace_state {
+///         headers.insert(TRACESTATE_HEADER, trace_state.as_str().parse().unwrap());
+///     }
+///     // Send the downstream request...
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceState(String);
+
+impl TraceState {
+    /// Returns the `tracestate` header value.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Reads the `tracestate` header, combining multiple header lines into a single list as
+    /// required by the specification.
+    ///
+    /// Only call this when the `traceparent` header was valid; a `tracestate` without it must be
+    /// ignored.
+    pub(crate) fn extract(headers: &HeaderMap) -> Option<Self> {
+        let mut state = String::new();
+        for value in headers.get_all(TRACESTATE_HEADER) {
+            let value = value.to_str().ok()?.trim();
+            if value.is_empty() {
+                continue;
+            }
+            if !state.is_empty() {
+                state.push(',');
+            }
+            state.push_str(value);
+        }
+
+        (!state.is_empty()).then_some(Self(state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::headers;
+
+    const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    #[test]
+    fn extracts_traceparent() {
+        assert_eq!(
+            W3CTraceContext::new().extract(&headers([(TRACEPARENT_HEADER, TRACEPARENT)])),
+            Some(
+                SpanContext::new(
+                    TraceId(0x0af7651916cd43dd8448eb211c80319c),
+                    SpanId(0xb7ad6b7169203331)
+                )
+                .sampled(true)
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_traceparent() {
+        let cases = [
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-00",
+            "01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        ];
+        for value in cases {
+            let headers = headers([(TRACEPARENT_HEADER, value)]);
+            assert_eq!(W3CTraceContext::new().extract(&headers), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn combines_tracestate_lines() {
+        let mut headers = headers([(TRACESTATE_HEADER, "congo=t61rcWkgMzE")]);
+        headers.append(TRACESTATE_HEADER, " ".parse().unwrap());
+        headers.append(TRACESTATE_HEADER, "rojo=00f067aa0ba902b7".parse().unwrap());
+        assert_eq!(
+            TraceState::extract(&headers).unwrap().as_str(),
+            "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7"
+        );
+        assert_eq!(TraceState::extract(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn extracts_from_renamed_header() {
+        let extractor = W3CTraceContext::with_header("X-Internal-Traceparent");
+        assert_eq!(extractor.header(), "x-internal-traceparent");
+        let headers = headers([("x-internal-traceparent", TRACEPARENT)]);
+        assert!(extractor.extract(&headers).is_some());
+        assert_eq!(W3CTraceContext::new().extract(&headers), None);
+    }
+
+    #[test]
+    fn injects_traceparent() {
+        let context = SpanContext::new(TraceId(0xabc), SpanId(0x12)).sampled(false);
+        let mut headers = HeaderMap::new();
+        W3CTraceContext::new().inject(&context, &mut headers);
+        assert_eq!(
+            headers[TRACEPARENT_HEADER],
+            "00-00000000000000000000000000000abc-0000000000000012-00"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! [AWS X-Ray](https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html#xray-concepts-tracingheader)
+//! propagation as injected by ALB and API Gateway.
+
+use fastrace::prelude::*;
+use poem::http::HeaderMap;
+
+use super::ContextExtractor;
+use super::header_str;
+use super::parse_span_id;
+use super::parse_trace_id;
+
+const X_AMZN_TRACE_ID_HEADER: &str = "x-amzn-trace-id";
+
+/// Extracts the parent context from the AWS X-Ray `X-Amzn-Trace-Id` header,
+/// `Root=1-{epoch}-{unique};Parent={span-id};Sampled={0|1|?}`.
+///
+/// A header without a `Parent` segment, as sent by a load balancer that does not sample, has no
+/// span to continue from and is ignored. Other segments such as `Self` and `Lineage` are skipped,
+/// as are empty segments left by a trailing `;`. `Sampled=?` leaves the sampling decision to the
+/// receiver, so the middleware samples the request like a new trace.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct XRay;
+
+impl ContextExtractor for XRay {
+    fn extract(&self, headers: &HeaderMap) -> Option<SpanContext> {
+        decode_amzn_trace_id(header_str(headers, X_AMZN_TRACE_ID_HEADER)?)
+    }
+}
+
+/// Decodes an `X-Amzn-Trace-Id` value, which is also passed to Lambda functions by their runtime.
+pub(crate) fn decode_amzn_trace_id(value: &str) -> Option<SpanContext> {
+    let mut trace_id = None;
+    let mut span_id = None;
+    let mut sampled = true;
+
+    for segment in value.split(';').map(str::trim) {
+        if segment.is_empty() {
+            continue;
+        }
+        match segment.split_once('=')? {
+            ("Root", root) => trace_id = Some(decode_root(root)?),
+            ("Parent", parent) => span_id = Some(parse_span_id(parent, 16)?),
+            ("Sampled", "0") => sampled = false,
+            ("Sampled", "1" | "?") => sampled = true,
+            ("Sampled", _) => return None,
+            _ => {}
+        }
+    }
+
+    Some(SpanContext::new(trace_id?, span_id?).sampled(sampled))
+}
+
+/// Returns whether the request's `X-Amzn-Trace-Id` header carries the parent context and leaves
+/// its sampling decision to the receiver.
+pub(crate) fn defers_sampling(headers: &HeaderMap, parent: &SpanContext) -> bool {
+    header_str(headers, X_AMZN_TRACE_ID_HEADER)
+        .is_some_and(|value| amzn_trace_id_defers_sampling(value, parent))
+}
+
+/// Returns whether an `X-Amzn-Trace-Id` value carries the parent context with `Sampled=?`.
+pub(crate) fn amzn_trace_id_defers_sampling(value: &str, parent: &SpanContext) -> bool {
+    value
+        .split(';')
+        .any(|segment| segment.trim() == "Sampled=?")
+        && decode_amzn_trace_id(value).is_some_and(|context| {
+            context.trace_id == parent.trace_id && context.span_id == parent.span_id
+        })
+}
+
+/// X-Ray trace ids have the form `1-{8 hex digits epoch}-{24 hex digits}` which together make up
+/// the 128-bit trace id.
+fn decode_root(root: &str) -> Option<TraceId> {
+    let mut parts = root.split('-');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("1"), Some(epoch), Some(unique), None) if epoch.len() == 8 && unique.len() == 24 => {
+            parse_trace_id(&format!("{epoch}{unique}"), 32)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::headers;
+
+    fn extract(value: &str) -> Option<SpanContext> {
+        XRay.extract(&headers([(X_AMZN_TRACE_ID_HEADER, value)]))
+    }
+
+    #[test]
+    fn extracts_context() {
+        assert_eq!(
+            extract("Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"),
+            Some(
+                SpanContext::new(
+                    TraceId(0x5759e988bd862e3fe1be46a994272793),
+                    SpanId(0x53995c3f42cd8ad8)
+                )
+                .sampled(true)
+            )
+        );
+    }
+
+    #[test]
+    fn extracts_sampling_decisions() {
+        let sampled = |decision: &str| {
+            extract(&format!(
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8{decision}"
+            ))
+            .map(|context| context.sampled)
+        };
+        assert_eq!(sampled(""), Some(true));
+        assert_eq!(sampled(";Sampled=?"), Some(true));
+        assert_eq!(sampled(";Sampled=0"), Some(false));
+        assert_eq!(sampled(";Sampled=2"), None);
+    }
+
+    #[test]
+    fn skips_empty_segments() {
+        let context =
+            extract("Root=1-5759e988-bd862e3fe1be46a994272793;;Parent=53995c3f42cd8ad8;Sampled=1;");
+        assert_eq!(context.unwrap().span_id, SpanId(0x53995c3f42cd8ad8));
+    }
+
+    #[test]
+    fn defers_sampling_for_unknown_decisions() {
+        let parent = SpanContext::new(
+            TraceId(0x5759e988bd862e3fe1be46a994272793),
+            SpanId(0x53995c3f42cd8ad8),
+        );
+        let defers =
+            |value: &str| defers_sampling(&headers([(X_AMZN_TRACE_ID_HEADER, value)]), &parent);
+        assert!(defers(
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=?"
+        ));
+        assert!(!defers(
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
+        ));
+        assert!(!defers(
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8"
+        ));
+        assert!(!defers(
+            "Root=1-5759e988-bd862e3fe1be46a994272794;Parent=53995c3f42cd8ad8;Sampled=?"
+        ));
+    }
+
+    #[test]
+    fn skips_other_segments() {
+        let context = extract(
+            "Self=1-67891234-12456789abcdef012345678;Root=1-5759e988-bd862e3fe1be46a994272793; \
+             Parent=53995c3f42cd8ad8;Lineage=a87bd80c:1|68fd508a:5",
+        );
+        assert_eq!(context.unwrap().span_id, SpanId(0x53995c3f42cd8ad8));
+    }
+
+    #[test]
+    fn rejects_zero_ids() {
+        assert_eq!(
+            extract("Root=1-00000000-000000000000000000000000;Parent=53995c3f42cd8ad8"),
+            None
+        );
+        assert_eq!(
+            extract("Root=1-5759e988-bd862e3fe1be46a994272793;Parent=0000000000000000"),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_missing_or_malformed_segments() {
+        let cases = [
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=0",
+            "Parent=53995c3f42cd8ad8;Sampled=1",
+            "Root=2-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8",
+            "Root=1-5759e98-bd862e3fe1be46a9942727930;Parent=53995c3f42cd8ad8",
+            "Root=1-5759e988-bd862e3fe1be46a994272793-0;Parent=53995c3f42cd8ad8",
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad",
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;garbage",
+        ];
+        for value in cases {
+            assert_eq!(extract(value), None, "{value}");
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Properties recorded on the root span from the responses of handlers.
+
+use std::borrow::Cow;
+
+use poem::Response;
+
+/// The properties added by handlers, moved onto the root span by the middleware.
+#[derive(Clone, Default)]
+pub(crate) struct PendingProperties(pub(crate) Vec<(Cow<'static, str>, Cow<'static, str>)>);
+
+/// An extension trait for [`Response`] to record properties on the root span of the request.
+///
+/// The properties are kept in the response until it passes back through
+/// [`FastraceMiddleware`](struct@crate::FastraceMiddleware), which records them on the root span.
+/// They are dropped when the request is not traced.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::ResponseExt;
+/// use poem::Response;
+/// use poem::handler;
+///
+/// #[handler]
+/// fn avatar() -> Response {
+///     Response::builder()
+///         .body("...")
+///         .with_span_property("cache", "hit")
+/// }
+/// ```
+pub trait ResponseExt {
+    /// Adds a property to the root span of the request.
+    fn add_span_property(
+        &mut self,
+        key: impl Into<Cow<'static, str>>,
+        value: impl Into<Cow<'static, str>>,
+    );
+
+    /// Adds a property to the root span of the request, and returns the response.
+    fn with_span_property(
+        mut self,
+        key: impl Into<Cow<'static, str>>,
+        value: impl Into<Cow<'static, str>>,
+    ) -> Self
+    where
+        Self: Sized,
+    {
+        self.add_span_property(key, value);
+        self
+    }
+}
+
+impl ResponseExt for Response {
+    fn add_span_property(
+        &mut self,
+        key: impl Into<Cow<'static, str>>,
+        value: impl Into<Cow<'static, str>>,
+    ) {
+        let extensions = self.extensions_mut();
+        if extensions.get::<PendingProperties>().is_none() {
+            extensions.insert(PendingProperties::default());
+        }
+        if let Some(properties) = extensions.get_mut::<PendingProperties>() {
+            properties.0.push((key.into(), value.into()));
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Path parameters of the matched route.
+
+use percent_encoding::percent_decode_str;
+
+/// The prefix of the span properties recording the path parameters.
+pub(crate) const ROUTE_PARAMS_PREFIX: &str = "http.route.params.";
+
+/// The value recorded for redacted path parameters.
+pub(crate) const REDACTED: &str = "REDACTED";
+
+/// Extracts the named parameters of a poem route pattern, such as `/users/:id` or `/files/*path`,
+/// from the path it matched.
+///
+/// Parameters sharing a segment with static text, such as `/v:version`, are not extracted.
+pub(crate) fn route_params<'a>(pattern: &'a str, path: &str) -> Vec<(&'a str, String)> {
+    let mut params = Vec::new();
+    let mut segments = path.trim_start_matches('/').split('/');
+
+    for expected in pattern.trim_start_matches('/').split('/') {
+        if let Some(name) = expected.strip_prefix('*') {
+            let rest = segments.collect::<Vec<_>>().join("/");
+            if !name.is_empty() {
+                params.push((name, decode(&rest)));
+            }
+            break;
+        }
+
+        let Some(segment) = segments.next() else {
+            break;
+        };
+        if let Some(name) = expected.strip_prefix(':') {
+            // Strip the regex of `:name<regex>` parameters.
+            let name = name.split('<').next().unwrap_or(name);
+            params.push((name, decode(segment)));
+        }
+    }
+
+    params
+}
+
+/// Returns whether a poem route pattern matches the path, along with the number of its static
+/// segments, to rank the patterns matching a path.
+///
+/// The regexes of `:name<regex>` parameters are not checked.
+pub(crate) fn route_specificity(pattern: &str, path: &str) -> Option<usize> {
+    let mut specificity = 0;
+    let mut segments = path.trim_start_matches('/').split('/');
+
+    for expected in pattern.trim_start_matches('/').split('/') {
+        if expected.starts_with('*') {
+            return Some(specificity);
+        }
+        let segment = segments.next()?;
+        if expected.contains(':') {
+            if segment.is_empty() {
+                return None;
+            }
+        } else if expected == segment {
+            specificity += 1;
+        } else {
+            return None;
+        }
+    }
+
+    segments.next().is_none().then_some(specificity)
+}
+
+fn decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_specificity_counts_static_segments() {
+        assert_eq!(route_specificity("/users/me", "/users/me"), Some(2));
+        assert_eq!(route_specificity("/users/:id", "/users/me"), Some(1));
+        assert_eq!(route_specificity("/files/*path", "/files/a/b"), Some(1));
+        assert!(route_specificity("/", "/").is_some());
+    }
+
+    #[test]
+    fn route_specificity_rejects_other_paths() {
+        assert_eq!(route_specificity("/users/:id", "/users"), None);
+        assert_eq!(route_specificity("/users/:id", "/users/"), None);
+        assert_eq!(route_specificity("/users/:id", "/users/1/orders"), None);
+        assert_eq!(route_specificity("/users/me", "/users/you"), None);
+    }
+
+    #[test]
+    fn route_params_extracts_named_parameters() {
+        assert_eq!(
+            route_params("/users/:id/orders/:order_id", "/users/42/orders/a%20b"),
+            [("id", "42".to_string()), ("order_id", "a b".to_string())]
+        );
+        assert_eq!(route_params("/items/:id<\\d+>", "/items/7"), [(
+            "id",
+            "7".to_string()
+        )]);
+        assert_eq!(route_params("/files/*path", "/files/css/site.css"), [(
+            "path",
+            "css/site.css".to_string()
+        )]);
+        assert_eq!(route_params("/files/*", "/files/a/b"), []);
+        assert_eq!(route_params("/v:version", "/v1"), []);
+    }
+}
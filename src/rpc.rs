@@ -0,0 +1,75 @@
+//! The RPC semantic conventions for gRPC requests served with poem-grpc.
+
+use poem::Request;
+use poem::http::HeaderMap;
+use poem::http::header;
+
+/// The remote procedure call system, which is not exported by the semantic conventions crate
+/// without its experimental attributes.
+const RPC_SYSTEM: &str = "rpc.system";
+
+/// The full name of the called service, such as `helloworld.Greeter`.
+const RPC_SERVICE: &str = "rpc.service";
+
+/// The name of the called method, such as `SayHello`.
+const RPC_METHOD: &str = "rpc.method";
+
+/// The numeric status code of the call.
+const RPC_GRPC_STATUS_CODE: &str = "rpc.grpc.status_code";
+
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+const GRPC_STATUS: &str = "grpc-status";
+
+/// The gRPC method called by a request.
+pub(crate) struct GrpcMethod<'a> {
+    pub(crate) service: &'a str,
+    pub(crate) method: &'a str,
+}
+
+impl<'a> GrpcMethod<'a> {
+    /// Returns the called method, for requests with a gRPC content type to a
+    /// `/{package.Service}/{Method}` path.
+    pub(crate) fn from_request(req: &Request, path: &'a str) -> Option<Self> {
+        req.headers()
+            .get(header::CONTENT_TYPE)?
+            .to_str()
+            .ok()?
+            .starts_with(GRPC_CONTENT_TYPE)
+            .then_some(())?;
+        let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+        (!service.is_empty() && !method.is_empty() && !method.contains('/'))
+            .then_some(GrpcMethod { service, method })
+    }
+
+    /// The span name recommended by the semantic conventions.
+    pub(crate) fn span_name(&self) -> String {
+        format!("{}/{}", self.service, self.method)
+    }
+
+    pub(crate) fn properties(&self) -> [(&'static str, String); 3] {
+        [
+            (RPC_SYSTEM, "grpc".to_string()),
+            (RPC_SERVICE, self.service.to_string()),
+            (RPC_METHOD, self.method.to_string()),
+        ]
+    }
+}
+
+/// Returns the status code of a call failed before sending any message, which is sent in the
+/// response headers. The status of other calls is sent in the trailers, after the response is
+/// returned.
+pub(crate) fn grpc_status(headers: &HeaderMap) -> Option<u16> {
+    headers.get(GRPC_STATUS)?.to_str().ok()?.parse().ok()
+}
+
+/// Returns the property recording the status code of a call.
+pub(crate) fn grpc_status_property(code: u16) -> (&'static str, String) {
+    (RPC_GRPC_STATUS_CODE, code.to_string())
+}
+
+/// Returns whether the status code is a server error, following the semantic conventions for
+/// server spans: `UNKNOWN`, `DEADLINE_EXCEEDED`, `UNIMPLEMENTED`, `INTERNAL`, `UNAVAILABLE` and
+/// `DATA_LOSS`.
+pub(crate) fn is_grpc_server_error(code: u16) -> bool {
+    matches!(code, 2 | 4 | 12 | 13 | 14 | 15)
+}
@@ -0,0 +1,129 @@
+//! Per-request sampling decisions.
+
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Instant;
+
+use fastrace::collector::TraceId;
+
+/// The decision of a sampler set with
+/// [`FastraceMiddlewareBuilder::with_sampler`](crate::FastraceMiddlewareBuilder::with_sampler).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplingDecision {
+    /// Create a sampled root span, continuing the parent context if there is one and starting a
+    /// new trace otherwise.
+    Record,
+    /// Create a root span that is not reported but keeps propagating the context, so that spans
+    /// created by downstream services are still linked to the trace.
+    PropagateOnly,
+    /// Do not trace the request.
+    Drop,
+}
+
+/// Returns whether a new trace is kept when sampling the given ratio of traces.
+///
+/// Like OpenTelemetry's `TraceIdRatioBased` sampler, the decision only depends on the lower 64 bits
+/// of the trace id, so that it is the same wherever it is taken for a trace.
+pub(crate) fn sample_ratio(trace_id: TraceId, ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    (trace_id.0 as u64) < (ratio * u64::MAX as f64) as u64
+}
+
+/// A token bucket limiting the number of traces started per second.
+pub(crate) struct RateLimiter {
+    max_per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(max_per_second: u32) -> Self {
+        let max_per_second = max_per_second as f64;
+        Self {
+            max_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: max_per_second,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token from the bucket, returning whether one was available.
+    pub(crate) fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.max_per_second).min(self.max_per_second);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn sample_ratio_keeps_everything_or_nothing_at_the_bounds() {
+        for trace_id in [TraceId(1), TraceId(u64::MAX as u128), TraceId(u128::MAX)] {
+            assert!(sample_ratio(trace_id, 1.0));
+            assert!(sample_ratio(trace_id, 2.0));
+            assert!(!sample_ratio(trace_id, 0.0));
+            assert!(!sample_ratio(trace_id, -1.0));
+        }
+    }
+
+    #[test]
+    fn sample_ratio_only_depends_on_the_lower_64_bits() {
+        let low = u64::MAX as u128 / 4;
+        assert!(sample_ratio(TraceId(low), 0.5));
+        assert!(sample_ratio(TraceId((u64::MAX as u128) << 64 | low), 0.5));
+        assert!(!sample_ratio(TraceId(u64::MAX as u128 / 4 * 3), 0.5));
+    }
+
+    #[test]
+    fn sample_ratio_keeps_about_the_given_ratio() {
+        let kept = (0..10_000u128)
+            .map(|n| TraceId(n.wrapping_mul(0x9e3779b97f4a7c15)))
+            .filter(|trace_id| sample_ratio(*trace_id, 0.25))
+            .count();
+        assert!((2_200..2_800).contains(&kept), "{kept}");
+    }
+
+    #[test]
+    fn rate_limiter_allows_a_burst_up_to_the_rate() {
+        let limiter = RateLimiter::new(3);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn rate_limiter_refills_over_time() {
+        let limiter = RateLimiter::new(1000);
+        while limiter.try_acquire() {}
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.try_acquire());
+    }
+
+    #[test]
+    fn rate_limiter_with_zero_rate_allows_nothing() {
+        assert!(!RateLimiter::new(0).try_acquire());
+    }
+}
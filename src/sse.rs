@@ -0,0 +1,172 @@
+//! Tracing of server-sent event streams.
+
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+
+use fastrace::Event;
+use fastrace::Span;
+use futures_core::Stream;
+use poem::web::sse;
+
+use crate::CurrentSpan;
+
+/// The name of the span event and child spans recorded for every server-sent event.
+const SSE_EVENT_NAME: &str = "sse.event";
+
+/// The property recording the type of a server-sent event.
+const SSE_EVENT_TYPE_PROPERTY: &str = "sse.event.type";
+
+/// The property recording the id of a server-sent event.
+const SSE_EVENT_ID_PROPERTY: &str = "sse.event.id";
+
+/// The property recording the size of the data of a server-sent event.
+const SSE_EVENT_SIZE_PROPERTY: &str = "sse.event.size";
+
+/// The property marking the child span of an event that was never sent, as the stream ended or
+/// the client disconnected.
+const SSE_EVENT_SENT_PROPERTY: &str = "sse.event.sent";
+
+/// The property recording the reconnection time sent to the client.
+const SSE_RETRY_PROPERTY: &str = "sse.retry_ms";
+
+/// The root span property recording the number of events sent.
+const SSE_EVENTS_PROPERTY: &str = "sse.events";
+
+/// The root span property recording how long the stream was open.
+const SSE_DURATION_PROPERTY: &str = "sse.duration_ms";
+
+/// The default maximum number of events recorded as span events or child spans.
+const DEFAULT_MAX_EVENTS: usize = 128;
+
+/// A stream of server-sent events recording every event on the root span of the request.
+///
+/// Every event sent is recorded as an `sse.event` span event with its type, id and data size.
+/// With [`with_event_spans`](Self::with_event_spans), a child span is recorded instead, covering
+/// the wait for the event, and the last span is marked with `sse.event.sent=false` when waiting
+/// was cut short by the end of the stream or a disconnect. Only the first
+/// [`with_max_events`](Self::with_max_events) events are recorded, so that long-lived streams do
+/// not grow the trace without bound. When the stream ends or the client disconnects, the number of
+/// events sent and how long the stream was open are recorded on the root span.
+///
+/// The stream holds the root span open until it is dropped.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use fastrace_poem::CurrentSpan;
+/// use fastrace_poem::TracedEvents;
+/// use poem::handler;
+/// use poem::web::sse::Event;
+/// use poem::web::sse::SSE;
+///
+/// #[handler]
+/// fn ticks(span: CurrentSpan) -> SSE {
+///     let events = futures_util::stream::iter(0..3).map(|n| Event::message(n.to_string()));
+///     SSE::new(TracedEvents::new(events, span)).keep_alive(Duration::from_secs(5))
+/// }
+/// # use futures_util::StreamExt;
+/// ```
+pub struct TracedEvents<S> {
+    inner: Pin<Box<S>>,
+    span: CurrentSpan,
+    event_spans: bool,
+    max_events: usize,
+    waiting: Option<Span>,
+    events: u64,
+    start: Instant,
+}
+
+impl<S: Stream<Item = sse::Event>> TracedEvents<S> {
+    /// Wraps the stream of events to record them on the given span.
+    pub fn new(events: S, span: CurrentSpan) -> Self {
+        TracedEvents {
+            inner: Box::pin(events),
+            span,
+            event_spans: false,
+            max_events: DEFAULT_MAX_EVENTS,
+            waiting: None,
+            events: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Records a child span for every event, from the previous event to this one, instead of a
+    /// span event.
+    pub fn with_event_spans(mut self, enable: bool) -> Self {
+        self.event_spans = enable;
+        self
+    }
+
+    /// Sets the maximum number of events recorded, after which events are only counted. Defaults
+    /// to 128.
+    pub fn with_max_events(mut self, max_events: usize) -> Self {
+        self.max_events = max_events;
+        self
+    }
+}
+
+impl<S: Stream<Item = sse::Event>> Stream for TracedEvents<S> {
+    type Item = sse::Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<sse::Event>> {
+        let this = self.get_mut();
+        let record = this.events < this.max_events as u64;
+        if this.event_spans && record && this.waiting.is_none() {
+            this.waiting = Some(Span::enter_with_parent(SSE_EVENT_NAME, &this.span));
+        }
+
+        let event = this.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(event)) = &event {
+            this.events += 1;
+            let properties = || event_properties(event);
+            match this.waiting.take() {
+                Some(span) => span.add_properties(properties),
+                None if record => this
+                    .span
+                    .add_event(Event::new(SSE_EVENT_NAME).with_properties(properties)),
+                None => {}
+            }
+        }
+        event
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> Drop for TracedEvents<S> {
+    fn drop(&mut self) {
+        if let Some(span) = self.waiting.take() {
+            span.add_property(|| (SSE_EVENT_SENT_PROPERTY, "false"));
+        }
+        self.span.add_properties(|| {
+            let duration = self.start.elapsed().as_millis();
+            [
+                (SSE_EVENTS_PROPERTY, self.events.to_string()),
+                (SSE_DURATION_PROPERTY, duration.to_string()),
+            ]
+        });
+    }
+}
+
+fn event_properties(event: &sse::Event) -> Vec<(&'static str, String)> {
+    match event {
+        sse::Event::Message { id, event, data } => {
+            let event_type = if event.is_empty() { "message" } else { event };
+            let mut properties = vec![
+                (SSE_EVENT_TYPE_PROPERTY, event_type.to_string()),
+                (SSE_EVENT_SIZE_PROPERTY, data.len().to_string()),
+            ];
+            if !id.is_empty() {
+                properties.push((SSE_EVENT_ID_PROPERTY, id.clone()));
+            }
+            properties
+        }
+        sse::Event::Retry { retry } => vec![(SSE_RETRY_PROPERTY, retry.to_string())],
+    }
+}
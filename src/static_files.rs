@@ -0,0 +1,187 @@
+//! Properties of requests served by Poem's static file endpoints.
+
+use percent_encoding::percent_decode_str;
+use poem::Endpoint;
+use poem::IntoResponse;
+use poem::Middleware;
+use poem::Request;
+use poem::Response;
+use poem::Result;
+use poem::http::HeaderMap;
+use poem::http::StatusCode;
+use poem::http::header;
+
+use crate::CurrentSpan;
+
+/// The property recording the path of the served file, relative to the served directory.
+const STATIC_FILE_PATH_PROPERTY: &str = "static_file.path";
+
+/// The property recording the size of the served file, in full even when a range of it was
+/// requested.
+const STATIC_FILE_SIZE_PROPERTY: &str = "static_file.size";
+
+/// The property recording whether a conditional request was answered with
+/// `304 Not Modified`.
+const STATIC_FILE_NOT_MODIFIED_PROPERTY: &str = "static_file.not_modified";
+
+/// Records the file served by one of Poem's static file endpoints on the request span of
+/// [`FastraceMiddleware`](struct@crate::FastraceMiddleware).
+///
+/// The path of the served file, its size and whether a conditional request was answered with
+/// `304 Not Modified` are recorded as the `static_file.path`, `static_file.size` and
+/// `static_file.not_modified` properties. The size is the size of the whole file, read from the
+/// `Content-Range` header of `206 Partial Content` responses, as opposed to the size of the
+/// response body. Nothing is recorded when the request is not traced.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::TracedStaticFiles;
+/// use poem::EndpointExt;
+/// use poem::Route;
+/// use poem::endpoint::StaticFileEndpoint;
+/// use poem::endpoint::StaticFilesEndpoint;
+///
+/// let app = Route::new()
+///     .nest(
+///         "/assets",
+///         StaticFilesEndpoint::new("./public").with(TracedStaticFiles::new()),
+///     )
+///     .at(
+///         "/favicon.ico",
+///         StaticFileEndpoint::new("./favicon.ico").with(TracedStaticFiles::single_file()),
+///     )
+///     .with(FastraceMiddleware::new());
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracedStaticFiles {
+    single_file: bool,
+}
+
+impl TracedStaticFiles {
+    /// Records the files of `StaticFilesEndpoint` or `EmbeddedFilesEndpoint`, serving the file at
+    /// the request path relative to the served directory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the file of `StaticFileEndpoint` or `EmbeddedFileEndpoint`, serving a single file,
+    /// without its path.
+    pub fn single_file() -> Self {
+        Self { single_file: true }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for TracedStaticFiles {
+    type Output = TracedStaticFilesEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        TracedStaticFilesEndpoint {
+            inner: ep,
+            single_file: self.single_file,
+        }
+    }
+}
+
+/// An endpoint wrapper created by [`TracedStaticFiles`].
+pub struct TracedStaticFilesEndpoint<E> {
+    inner: E,
+    single_file: bool,
+}
+
+impl<E: Endpoint> Endpoint for TracedStaticFilesEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let Some(span) = req.extensions().get::<CurrentSpan>().cloned() else {
+            return self.inner.call(req).await.map(IntoResponse::into_response);
+        };
+
+        // The path of the file, resolved like the endpoint does relative to the served directory.
+        let path = (!self.single_file).then(|| {
+            let path = req.uri().path().trim_matches('/');
+            percent_decode_str(path).decode_utf8_lossy().into_owned()
+        });
+        let resp = self.inner.call(req).await.map(IntoResponse::into_response);
+        match &resp {
+            Ok(resp) => {
+                span.add_properties(|| static_file_properties(path, resp.status(), resp.headers()))
+            }
+            Err(err) => span
+                .add_properties(|| static_file_properties(path, err.status(), &HeaderMap::new())),
+        }
+        resp
+    }
+}
+
+/// Returns the properties of a static file response, or of the error returned instead.
+fn static_file_properties(
+    path: Option<String>,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Vec<(&'static str, String)> {
+    let mut properties = Vec::new();
+    if let Some(path) = path {
+        properties.push((STATIC_FILE_PATH_PROPERTY, path));
+    }
+    let header_value = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let size = match status {
+        // The size of the file follows the range, as in `bytes 0-99/1234`, unless unknown.
+        StatusCode::PARTIAL_CONTENT => header_value(header::CONTENT_RANGE)
+            .and_then(|range| range.rsplit_once('/'))
+            .map(|(_, size)| size)
+            .filter(|size| *size != "*"),
+        status if status.is_success() => header_value(header::CONTENT_LENGTH),
+        _ => None,
+    };
+    if let Some(size) = size {
+        properties.push((STATIC_FILE_SIZE_PROPERTY, size.to_string()));
+    }
+    let not_modified = status == StatusCode::NOT_MODIFIED;
+    properties.push((STATIC_FILE_NOT_MODIFIED_PROPERTY, not_modified.to_string()));
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(status: StatusCode, headers: &[(header::HeaderName, &str)]) -> Option<String> {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.parse().unwrap()))
+            .collect();
+        static_file_properties(None, status, &headers)
+            .into_iter()
+            .find(|(key, _)| *key == STATIC_FILE_SIZE_PROPERTY)
+            .map(|(_, size)| size)
+    }
+
+    #[test]
+    fn size_of_the_whole_file() {
+        assert_eq!(
+            size(StatusCode::OK, &[(header::CONTENT_LENGTH, "1234")]).as_deref(),
+            Some("1234")
+        );
+        assert_eq!(
+            size(StatusCode::PARTIAL_CONTENT, &[
+                (header::CONTENT_LENGTH, "100"),
+                (header::CONTENT_RANGE, "bytes 0-99/1234"),
+            ])
+            .as_deref(),
+            Some("1234")
+        );
+        assert_eq!(
+            size(StatusCode::PARTIAL_CONTENT, &[
+                (header::CONTENT_LENGTH, "100"),
+                (header::CONTENT_RANGE, "bytes 0-99/*"),
+            ]),
+            None
+        );
+        assert_eq!(
+            size(StatusCode::NOT_FOUND, &[(header::CONTENT_LENGTH, "9")]),
+            None
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Utilities to assert the spans recorded by the middleware in integration tests.
+
+use std::mem;
+use std::sync::Once;
+use std::sync::PoisonError;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use fastrace::collector::Config;
+use fastrace::collector::Reporter;
+use fastrace::collector::SpanContext;
+use fastrace::collector::SpanId;
+use fastrace::collector::SpanRecord;
+use fastrace::collector::TraceId;
+use poem::Body;
+use poem::Endpoint;
+use poem::Response;
+use poem::test::TestRequestBuilder;
+use poem::test::TestResponse;
+use tokio::sync::Mutex;
+
+/// The requests sent by [`collect_spans`] one at a time, as the spans are reported globally.
+static COLLECTING: Mutex<()> = Mutex::const_new(());
+
+/// The spans reported since the last call to [`collect_spans`].
+static SPANS: std::sync::Mutex<Vec<SpanRecord>> = std::sync::Mutex::new(Vec::new());
+
+struct MemoryReporter;
+
+impl Reporter for MemoryReporter {
+    fn report(&mut self, mut spans: Vec<SpanRecord>) {
+        reported_spans().append(&mut spans);
+    }
+}
+
+fn reported_spans() -> std::sync::MutexGuard<'static, Vec<SpanRecord>> {
+    SPANS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Sends a [`TestClient`](poem::test::TestClient) request and returns its response along with the
+/// spans recorded while it was handled.
+///
+/// An in-memory reporter is installed on the first call, replacing the reporter of the process.
+/// The response body is read before the spans are collected, so that spans ending with the body
+/// are included, and is available again in the returned response. Spans still held by
+/// background tasks once the body is read are not included.
+///
+/// Calls are serialized, so tests running in parallel only see the spans of their own request,
+/// as long as no other code reports spans at the same time. No spans are recorded with the
+/// `disabled` feature.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::test::collect_spans;
+/// use poem::EndpointExt;
+/// use poem::Route;
+/// use poem::get;
+/// use poem::handler;
+/// use poem::test::TestClient;
+///
+/// #[handler]
+/// fn ping() -> &'static str {
+///     "pong"
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let app = Route::new().at("/ping", get(ping).with(FastraceMiddleware::new()));
+/// let cli = TestClient::new(app);
+///
+/// let request = cli.get("/ping").header(
+///     "traceparent",
+///     "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+/// );
+/// let (resp, spans) = collect_spans(request).await;
+///
+/// resp.assert_text("pong").await;
+/// # #[cfg(not(feature = "disabled"))]
+/// # {
+/// assert_eq!(spans.len(), 1);
+/// assert_eq!(spans[0].name, "GET /ping");
+/// # }
+/// # }
+/// ```
+pub async fn collect_spans<E: Endpoint>(
+    request: TestRequestBuilder<'_, E>,
+) -> (TestResponse, Vec<SpanRecord>) {
+    static INSTALL: Once = Once::new();
+
+    let _collecting = COLLECTING.lock().await;
+    INSTALL.call_once(|| fastrace::set_reporter(MemoryReporter, Config::default()));
+    // Drop the spans reported since the last call.
+    fastrace::flush();
+    reported_spans().clear();
+
+    let TestResponse(resp) = request.send().await;
+    let (parts, body) = resp.into_parts();
+    let body = body
+        .into_bytes()
+        .await
+        .expect("failed to read the response body");
+    let resp = Response::from_parts(parts, Body::from(body));
+
+    fastrace::flush();
+    let spans = mem::take(&mut *reported_spans());
+    (TestResponse(resp), spans)
+}
+
+/// Returns a generator of sequential ids for
+/// [`FastraceMiddlewareBuilder::with_id_generator`](crate::FastraceMiddlewareBuilder::with_id_generator),
+/// whose `n`-th context has the trace id and span id `n`, starting at 1, so that the traces
+/// started by the middleware have the same trace ids and parent span ids on every run.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::test::sequential_ids;
+///
+/// let middleware = FastraceMiddleware::builder()
+///     .with_trace_all_requests(true)
+///     .with_id_generator(sequential_ids())
+///     .build();
+/// ```
+pub fn sequential_ids() -> impl Fn() -> SpanContext + Send + Sync + 'static {
+    let next = AtomicU64::new(1);
+    move || {
+        let id = next.fetch_add(1, Ordering::Relaxed);
+        SpanContext::new(TraceId(id.into()), SpanId(id))
+    }
+}
+
+/// Returns the span with the given name, panicking with the names of the recorded spans when
+/// there is none.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::FastraceMiddleware;
+/// use fastrace_poem::test::assert_parented_to;
+/// use fastrace_poem::test::assert_property;
+/// use fastrace_poem::test::assert_span_named;
+/// use fastrace_poem::test::collect_spans;
+/// use poem::EndpointExt;
+/// use poem::Route;
+/// use poem::get;
+/// use poem::handler;
+/// use poem::test::TestClient;
+///
+/// #[handler]
+/// #[fastrace::trace(name = "ping")]
+/// fn ping() -> &'static str {
+///     "pong"
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let app = Route::new().at("/ping", get(ping).with(FastraceMiddleware::new()));
+/// let cli = TestClient::new(app);
+///
+/// let request = cli.get("/ping").header(
+///     "traceparent",
+///     "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+/// );
+/// let (_, spans) = collect_spans(request).await;
+///
+/// # #[cfg(not(feature = "disabled"))]
+/// # {
+/// let root = assert_span_named(&spans, "GET /ping");
+/// assert_property(root, "http.route", "/ping");
+/// assert_parented_to(assert_span_named(&spans, "ping"), root);
+/// # }
+/// # }
+/// ```
+#[track_caller]
+pub fn assert_span_named<'a>(spans: &'a [SpanRecord], name: &str) -> &'a SpanRecord {
+    match spans.iter().find(|span| span.name == name) {
+        Some(span) => span,
+        None => {
+            let names = spans
+                .iter()
+                .map(|span| format!("`{}`", span.name))
+                .collect::<Vec<_>>();
+            panic!(
+                "no span named `{name}`, recorded spans: [{}]",
+                names.join(", ")
+            )
+        }
+    }
+}
+
+/// Asserts that the span has the property with the given value, panicking with the value of the
+/// property or the properties of the span otherwise.
+#[track_caller]
+pub fn assert_property(span: &SpanRecord, key: &str, value: &str) {
+    match span.properties.iter().find(|(k, _)| k == key) {
+        Some((_, v)) if v == value => {}
+        Some((_, v)) => panic!(
+            "span `{}` has property `{key}` = `{v}`, expected `{value}`",
+            span.name
+        ),
+        None => {
+            let properties = span
+                .properties
+                .iter()
+                .map(|(k, v)| format!("`{k}` = `{v}`"))
+                .collect::<Vec<_>>();
+            panic!(
+                "span `{}` has no property `{key}`, recorded properties: [{}]",
+                span.name,
+                properties.join(", ")
+            )
+        }
+    }
+}
+
+/// Asserts that the span is a child of the parent span in the same trace, panicking with the
+/// actual parent otherwise.
+#[track_caller]
+pub fn assert_parented_to(span: &SpanRecord, parent: &SpanRecord) {
+    if span.trace_id != parent.trace_id {
+        panic!(
+            "span `{}` is in trace {}, expected the trace {} of its parent `{}`",
+            span.name, span.trace_id, parent.trace_id, parent.name
+        );
+    }
+    if span.parent_id != parent.span_id {
+        panic!(
+            "span `{}` is a child of span {}, expected `{}` ({})",
+            span.name, span.parent_id, parent.name, parent.span_id
+        );
+    }
+}
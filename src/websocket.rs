@@ -0,0 +1,192 @@
+//! Tracing of WebSocket connections.
+
+use std::io;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+
+use fastrace::Event;
+use futures_core::Stream;
+use futures_sink::Sink;
+use poem::web::websocket::Message;
+
+use crate::CurrentSpan;
+
+/// The name of the span event recorded for every message.
+const WEBSOCKET_MESSAGE_NAME: &str = "websocket.message";
+
+/// The property recording whether a message was `received` or `sent`.
+const WEBSOCKET_DIRECTION_PROPERTY: &str = "websocket.message.direction";
+
+/// The property recording the type of a message.
+const WEBSOCKET_TYPE_PROPERTY: &str = "websocket.message.type";
+
+/// The property recording the size of the payload of a message.
+const WEBSOCKET_SIZE_PROPERTY: &str = "websocket.message.size";
+
+/// The property recording the code of a close message.
+const WEBSOCKET_CLOSE_CODE_PROPERTY: &str = "websocket.close.code";
+
+/// The root span property recording the number of messages received.
+const WEBSOCKET_RECEIVED_PROPERTY: &str = "websocket.messages.received";
+
+/// The root span property recording the number of messages sent.
+const WEBSOCKET_SENT_PROPERTY: &str = "websocket.messages.sent";
+
+/// The root span property recording how long the connection was open.
+const WEBSOCKET_DURATION_PROPERTY: &str = "websocket.duration_ms";
+
+/// The default maximum number of messages recorded as span events.
+const DEFAULT_MAX_EVENTS: usize = 128;
+
+/// A WebSocket stream recording every message on the root span of the upgrade request.
+///
+/// Every message received or sent is recorded as a `websocket.message` span event with its
+/// direction, type and payload size, up to [`with_max_events`](Self::with_max_events) messages so
+/// that long-lived connections do not grow the span without bound. When the stream is dropped, the
+/// number of messages in each direction and how long the connection was open are recorded on the
+/// root span, which the stream holds open until then.
+///
+/// The stream implements [`Stream`] and [`Sink`] like the wrapped stream, so it can be split
+/// into a sender and a receiver.
+///
+/// # Example
+///
+/// ```
+/// use fastrace_poem::CurrentSpan;
+/// use fastrace_poem::TracedWebSocket;
+/// use futures_util::SinkExt;
+/// use futures_util::StreamExt;
+/// use poem::IntoResponse;
+/// use poem::handler;
+/// use poem::web::websocket::WebSocket;
+///
+/// #[handler]
+/// fn echo(ws: WebSocket, span: CurrentSpan) -> impl IntoResponse {
+///     ws.on_upgrade(move |socket| async move {
+///         let mut socket = TracedWebSocket::new(socket, span);
+///         while let Some(Ok(message)) = socket.next().await {
+///             if socket.send(message).await.is_err() {
+///                 break;
+///             }
+///         }
+///     })
+/// }
+/// ```
+pub struct TracedWebSocket<S> {
+    inner: Pin<Box<S>>,
+    span: CurrentSpan,
+    max_events: usize,
+    received: u64,
+    sent: u64,
+    start: Instant,
+}
+
+impl<S> TracedWebSocket<S> {
+    /// Wraps the WebSocket stream to record its messages on the given span.
+    pub fn new(socket: S, span: CurrentSpan) -> Self {
+        TracedWebSocket {
+            inner: Box::pin(socket),
+            span,
+            max_events: DEFAULT_MAX_EVENTS,
+            received: 0,
+            sent: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Sets the maximum number of messages recorded as span events, after which messages are only
+    /// counted. Defaults to 128.
+    pub fn with_max_events(mut self, max_events: usize) -> Self {
+        self.max_events = max_events;
+        self
+    }
+
+    /// Records a message, once counted.
+    fn record(&self, direction: &'static str, message: &Message) {
+        if self.received + self.sent > self.max_events as u64 {
+            return;
+        }
+        self.span.add_event(
+            Event::new(WEBSOCKET_MESSAGE_NAME)
+                .with_properties(|| message_properties(direction, message)),
+        );
+    }
+}
+
+impl<S: Stream<Item = io::Result<Message>>> Stream for TracedWebSocket<S> {
+    type Item = io::Result<Message>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<io::Result<Message>>> {
+        let this = self.get_mut();
+        let message = this.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(message))) = &message {
+            this.received += 1;
+            this.record("received", message);
+        }
+        message
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S: Sink<Message>> Sink<Message> for TracedWebSocket<S> {
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.get_mut().inner.as_mut().poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: Message) -> Result<(), S::Error> {
+        let this = self.get_mut();
+        this.sent += 1;
+        this.record("sent", &message);
+        this.inner.as_mut().start_send(message)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.get_mut().inner.as_mut().poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.get_mut().inner.as_mut().poll_close(cx)
+    }
+}
+
+impl<S> Drop for TracedWebSocket<S> {
+    fn drop(&mut self) {
+        self.span.add_properties(|| {
+            let duration = self.start.elapsed().as_millis();
+            [
+                (WEBSOCKET_RECEIVED_PROPERTY, self.received.to_string()),
+                (WEBSOCKET_SENT_PROPERTY, self.sent.to_string()),
+                (WEBSOCKET_DURATION_PROPERTY, duration.to_string()),
+            ]
+        });
+    }
+}
+
+fn message_properties(direction: &'static str, message: &Message) -> Vec<(&'static str, String)> {
+    let (message_type, size) = match message {
+        Message::Text(text) => ("text", text.len()),
+        Message::Binary(data) => ("binary", data.len()),
+        Message::Ping(data) => ("ping", data.len()),
+        Message::Pong(data) => ("pong", data.len()),
+        Message::Close(frame) => (
+            "close",
+            frame.as_ref().map_or(0, |(_, reason)| reason.len()),
+        ),
+    };
+    let mut properties = vec![
+        (WEBSOCKET_DIRECTION_PROPERTY, direction.to_string()),
+        (WEBSOCKET_TYPE_PROPERTY, message_type.to_string()),
+        (WEBSOCKET_SIZE_PROPERTY, size.to_string()),
+    ];
+    if let Message::Close(Some((code, _))) = message {
+        properties.push((WEBSOCKET_CLOSE_CODE_PROPERTY, u16::from(*code).to_string()));
+    }
+    properties
+}
@@ -0,0 +1,166 @@
+//! The attributes recorded on the root span of requests.
+
+mod common;
+
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use common::TRACEPARENT;
+use common::client;
+use common::ping;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::http::StatusCode;
+use poem::test::TestClient;
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn route_is_the_matched_pattern() {
+    let cli = client(FastraceMiddleware::builder());
+    let (resp, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("pong").await;
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "http.route", "/ping");
+    assert_property(root, "http.response.status_code", "200");
+
+    let cli = TestClient::new(Route::new().at(
+        "/users/:id",
+        ping.with(FastraceMiddleware::builder().build()),
+    ));
+    let (_, spans) = collect_spans(cli.get("/users/42").header("traceparent", TRACEPARENT)).await;
+    assert_property(
+        assert_span_named(&spans, "GET /users/:id"),
+        "http.route",
+        "/users/:id",
+    );
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn route_params() {
+    let cli = TestClient::new(
+        Route::new().at("/users/:id/tokens/:token", ping).with(
+            FastraceMiddleware::builder()
+                .with_route_params(true)
+                .with_redacted_route_params(["token"])
+                .build(),
+        ),
+    );
+
+    let (_, spans) = collect_spans(
+        cli.get("/users/42/tokens/secret")
+            .header("traceparent", TRACEPARENT),
+    )
+    .await;
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "http.route.params.id", "42");
+    assert_property(root, "http.route.params.token", "REDACTED");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn query_with_redacted_params() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_query(true)
+            .with_redacted_query_params(["token"]),
+    );
+
+    let (_, spans) = collect_spans(
+        cli.get("/ping?token=secret&page=2")
+            .header("traceparent", TRACEPARENT),
+    )
+    .await;
+    assert_property(
+        assert_span_named(&spans, "GET"),
+        "url.query",
+        "token=REDACTED&page=2",
+    );
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn max_attribute_len() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_query(true)
+            .with_max_attribute_len(8),
+    );
+
+    let (_, spans) = collect_spans(
+        cli.get("/ping?page=2&sort=name")
+            .header("traceparent", TRACEPARENT),
+    )
+    .await;
+    assert_property(assert_span_named(&spans, "GET"), "url.query", "page=...");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn status_code_of_unregistered_statuses() {
+    #[handler]
+    fn unregistered() -> StatusCode {
+        StatusCode::from_u16(599).unwrap()
+    }
+
+    let cli = TestClient::new(
+        Route::new()
+            .at("/unregistered", unregistered)
+            .with(FastraceMiddleware::new()),
+    );
+
+    let (_, spans) =
+        collect_spans(cli.get("/unregistered").header("traceparent", TRACEPARENT)).await;
+    assert_property(
+        assert_span_named(&spans, "GET"),
+        "http.response.status_code",
+        "599",
+    );
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn queue_time() {
+    let cli = client(FastraceMiddleware::builder().with_queue_time(true));
+
+    let queued = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() - Duration::from_secs(2);
+    let request = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("x-request-start", format!("t={}", queued.as_micros()));
+    let (_, spans) = collect_spans(request).await;
+    let root = assert_span_named(&spans, "GET");
+    let (_, millis) = root
+        .properties
+        .iter()
+        .find(|(key, _)| key == "http.request.queue_time_ms")
+        .expect("no queue time");
+    assert!(
+        (2000..3000).contains(&millis.parse::<u64>().unwrap()),
+        "{millis}"
+    );
+}
@@ -0,0 +1,130 @@
+//! The properties of the response body, recorded on the root span once the body is sent.
+
+mod common;
+
+use std::time::Duration;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::FastraceMiddlewareBuilder;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use futures_util::StreamExt;
+use futures_util::stream;
+use poem::Body;
+use poem::Endpoint;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+
+/// Streams a body without a `Content-Length` header.
+#[handler]
+fn chunks() -> Body {
+    Body::from_bytes_stream(stream::iter(
+        ["hello ", "world"].map(Ok::<_, std::io::Error>),
+    ))
+}
+
+/// The time the first chunk of [`late_chunks`] and the last of [`slow_chunks`] are sent after.
+const DELAY: Duration = Duration::from_millis(50);
+
+/// Streams a body whose last chunk is sent after [`DELAY`].
+#[handler]
+fn slow_chunks() -> Body {
+    Body::from_bytes_stream(stream::iter(["hello ", "world"]).then(|chunk| async move {
+        if chunk == "world" {
+            tokio::time::sleep(DELAY).await;
+        }
+        Ok::<_, std::io::Error>(chunk)
+    }))
+}
+
+/// Streams a body whose first chunk is sent after [`DELAY`].
+#[handler]
+fn late_chunks() -> Body {
+    Body::from_bytes_stream(stream::iter(["hello ", "world"]).then(|chunk| async move {
+        if chunk == "hello " {
+            tokio::time::sleep(DELAY).await;
+        }
+        Ok::<_, std::io::Error>(chunk)
+    }))
+}
+
+fn client(middleware: FastraceMiddlewareBuilder) -> TestClient<impl Endpoint> {
+    TestClient::new(
+        Route::new()
+            .at("/chunks", chunks)
+            .at("/slow_chunks", slow_chunks)
+            .at("/late_chunks", late_chunks)
+            .with(middleware.build()),
+    )
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn response_body_size_of_streams() {
+    let cli = client(FastraceMiddleware::builder().with_measure_response_body(true));
+
+    let (resp, spans) = collect_spans(cli.get("/chunks").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("hello world").await;
+    assert_property(
+        assert_span_named(&spans, "GET"),
+        "http.response.body.size",
+        "11",
+    );
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn span_until_body_end() {
+    let cli = client(FastraceMiddleware::builder().with_span_until_body_end(true));
+    let (resp, spans) =
+        collect_spans(cli.get("/slow_chunks").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("hello world").await;
+    let root = assert_span_named(&spans, "GET");
+    assert!(
+        root.duration_ns >= DELAY.as_nanos() as u64,
+        "{}",
+        root.duration_ns
+    );
+
+    let cli = client(FastraceMiddleware::builder());
+    let (_, spans) =
+        collect_spans(cli.get("/slow_chunks").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    assert!(
+        root.duration_ns < DELAY.as_nanos() as u64,
+        "{}",
+        root.duration_ns
+    );
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn ttfb() {
+    let cli = client(FastraceMiddleware::builder().with_ttfb(true));
+
+    let (_, spans) =
+        collect_spans(cli.get("/late_chunks").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    let (_, millis) = root
+        .properties
+        .iter()
+        .find(|(key, _)| key == "http.response.ttfb_ms")
+        .expect("no time to first byte");
+    assert!(
+        millis.parse::<u128>().unwrap() >= DELAY.as_millis(),
+        "{millis}"
+    );
+}
@@ -0,0 +1,62 @@
+//! The spans collected for `TestClient` requests.
+
+mod common;
+
+use common::TRACEPARENT;
+use common::client;
+use fastrace::collector::SpanId;
+use fastrace::collector::TraceId;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use fastrace_poem::test::sequential_ids;
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn collects_the_spans_of_the_request() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (resp, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+
+    resp.assert_text("pong").await;
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].name, "GET");
+}
+
+#[tokio::test]
+async fn leaves_out_the_spans_of_earlier_requests() {
+    let cli = client(FastraceMiddleware::builder());
+
+    cli.get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .send()
+        .await
+        .assert_status_is_ok();
+    let (resp, spans) = collect_spans(cli.get("/ping")).await;
+
+    resp.assert_text("pong").await;
+    assert!(spans.is_empty());
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn sequential_ids_number_the_traces() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_trace_all_requests(true)
+            .with_id_generator(sequential_ids()),
+    );
+
+    for id in 1..=3 {
+        let (_, spans) = collect_spans(cli.get("/ping")).await;
+        let root = assert_span_named(&spans, "GET");
+        assert_eq!(root.trace_id, TraceId(id.into()));
+        assert_eq!(root.parent_id, SpanId(id));
+    }
+}
@@ -0,0 +1,46 @@
+//! Fixtures shared by the integration tests.
+
+#![allow(dead_code)]
+
+use fastrace_poem::FastraceMiddlewareBuilder;
+use poem::Endpoint;
+use poem::EndpointExt;
+use poem::Error;
+use poem::Result;
+use poem::Route;
+use poem::handler;
+use poem::http::StatusCode;
+use poem::test::TestClient;
+
+/// A sampled W3C parent context.
+pub const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+/// The trace id of [`TRACEPARENT`].
+pub const TRACE_ID: u128 = 0x0af7651916cd43dd8448eb211c80319c;
+
+/// The span id of [`TRACEPARENT`].
+pub const SPAN_ID: u64 = 0xb7ad6b7169203331;
+
+#[handler]
+pub fn ping() -> &'static str {
+    "pong"
+}
+
+#[handler]
+pub fn fail() -> Result<&'static str> {
+    Err(Error::from_string(
+        "database unavailable",
+        StatusCode::INTERNAL_SERVER_ERROR,
+    ))
+}
+
+/// Returns a client for an app serving [`ping`] at `/ping` and [`fail`] at `/fail`, wrapped by
+/// the middleware.
+pub fn client(middleware: FastraceMiddlewareBuilder) -> TestClient<impl Endpoint> {
+    TestClient::new(
+        Route::new()
+            .at("/ping", ping)
+            .at("/fail", fail)
+            .with(middleware.build()),
+    )
+}
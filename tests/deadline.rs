@@ -0,0 +1,130 @@
+//! The deadline of the client recorded on the root span, and the event of late responses.
+
+mod common;
+
+use std::time::Duration;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::FastraceMiddlewareBuilder;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::Endpoint;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+
+#[handler]
+async fn slow() -> &'static str {
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    "done"
+}
+
+fn client(middleware: FastraceMiddlewareBuilder) -> TestClient<impl Endpoint> {
+    TestClient::new(Route::new().at("/slow", slow).with(middleware.build()))
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn request_timeout_of_the_client() {
+    let cli = client(FastraceMiddleware::builder().with_deadline(true));
+
+    let (_, spans) = collect_spans(
+        cli.get("/slow")
+            .header("traceparent", TRACEPARENT)
+            .header("grpc-timeout", "2S"),
+    )
+    .await;
+    assert_property(
+        assert_span_named(&spans, "GET"),
+        "http.request.timeout_ms",
+        "2000",
+    );
+
+    let (_, spans) = collect_spans(
+        cli.get("/slow")
+            .header("traceparent", TRACEPARENT)
+            .header("x-request-timeout", "250ms"),
+    )
+    .await;
+    assert_property(
+        assert_span_named(&spans, "GET"),
+        "http.request.timeout_ms",
+        "250",
+    );
+
+    let (_, spans) = collect_spans(cli.get("/slow").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    assert!(
+        !root
+            .properties
+            .iter()
+            .any(|(k, _)| k == "http.request.timeout_ms")
+    );
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn late_responses_add_a_deadline_event() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_deadline(true)
+            .with_deadline_event(true),
+    );
+
+    let (resp, spans) = collect_spans(
+        cli.get("/slow")
+            .header("traceparent", TRACEPARENT)
+            .header("grpc-timeout", "10m"),
+    )
+    .await;
+    resp.assert_text("done").await;
+    let event = assert_span_named(&spans, "GET")
+        .events
+        .iter()
+        .find(|event| event.name == "deadline exceeded")
+        .expect("no deadline event");
+    let (_, late) = event
+        .properties
+        .iter()
+        .find(|(k, _)| k == "exceeded_by_ms")
+        .expect("no exceeded_by_ms property");
+    assert!(late.parse::<u64>().unwrap() >= 30, "exceeded by {late}ms");
+
+    let (_, spans) = collect_spans(
+        cli.get("/slow")
+            .header("traceparent", TRACEPARENT)
+            .header("grpc-timeout", "10S"),
+    )
+    .await;
+    let root = assert_span_named(&spans, "GET");
+    assert!(
+        !root
+            .events
+            .iter()
+            .any(|event| event.name == "deadline exceeded")
+    );
+
+    let cli = client(FastraceMiddleware::builder().with_deadline(true));
+    let (_, spans) = collect_spans(
+        cli.get("/slow")
+            .header("traceparent", TRACEPARENT)
+            .header("grpc-timeout", "10m"),
+    )
+    .await;
+    let root = assert_span_named(&spans, "GET");
+    assert!(
+        !root
+            .events
+            .iter()
+            .any(|event| event.name == "deadline exceeded")
+    );
+}
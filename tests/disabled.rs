@@ -0,0 +1,36 @@
+//! The passthrough middleware compiled with the `disabled` feature.
+
+#![cfg(feature = "disabled")]
+
+mod common;
+
+use common::TRACEPARENT;
+use common::client;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::collect_spans;
+use poem::http::StatusCode;
+
+#[tokio::test]
+async fn requests_are_passed_through() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_trace_all_requests(true)
+            .with_force_trace_header("x-force-trace")
+            .with_traceresponse(true)
+            .with_server_timing(true),
+    );
+
+    let request = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("x-force-trace", "1");
+    let (resp, spans) = collect_spans(request).await;
+    resp.assert_header_is_not_exist("traceresponse");
+    resp.assert_header_is_not_exist("server-timing");
+    resp.assert_text("pong").await;
+    assert!(spans.is_empty());
+
+    let (resp, spans) = collect_spans(cli.get("/fail")).await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(spans.is_empty());
+}
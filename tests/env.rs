@@ -0,0 +1,84 @@
+//! The configuration read from the OpenTelemetry environment variables.
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace::collector::SpanContext;
+use fastrace_poem::FastraceMiddlewareBuilder;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Request;
+use poem::handler;
+use poem::test::TestClient;
+
+const UNSAMPLED_TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00";
+
+/// Returns whether the request carries a span context, and whether it is sampled.
+#[handler]
+fn context(req: &Request) -> String {
+    match req.extensions().get::<SpanContext>() {
+        Some(context) => format!("sampled={}", context.sampled),
+        None => "none".to_string(),
+    }
+}
+
+/// Returns whether a request without a parent, with a sampled parent and with an unsampled parent
+/// is recorded with the middleware configured for the sampler.
+async fn recorded(sampler: &str, arg: Option<&str>) -> [bool; 3] {
+    // The variables are only read here, by the only test of this file.
+    std::env::set_var("OTEL_TRACES_SAMPLER", sampler);
+    match arg {
+        Some(arg) => std::env::set_var("OTEL_TRACES_SAMPLER_ARG", arg),
+        None => std::env::remove_var("OTEL_TRACES_SAMPLER_ARG"),
+    }
+    let cli = TestClient::new(context.with(FastraceMiddlewareBuilder::from_env().build()));
+
+    let mut recorded = [false; 3];
+    for (recorded, parent) in
+        recorded
+            .iter_mut()
+            .zip([None, Some(TRACEPARENT), Some(UNSAMPLED_TRACEPARENT)])
+    {
+        let request = match parent {
+            Some(parent) => cli.get("/").header("traceparent", parent),
+            None => cli.get("/"),
+        };
+        let (_, spans) = collect_spans(request).await;
+        *recorded = !spans.is_empty();
+    }
+    recorded
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn traces_sampler() {
+    assert_eq!(recorded("always_on", None).await, [true, true, true]);
+    assert_eq!(recorded("always_off", None).await, [false, false, false]);
+    assert_eq!(recorded("traceidratio", Some("1.0")).await, [
+        true, true, true
+    ]);
+    assert_eq!(recorded("traceidratio", Some("0.0")).await, [
+        false, false, false
+    ]);
+    assert_eq!(recorded("parentbased_always_on", None).await, [
+        true, true, false
+    ]);
+    assert_eq!(recorded("parentbased_always_off", None).await, [
+        false, true, false
+    ]);
+    assert_eq!(recorded("parentbased_traceidratio", Some("0.0")).await, [
+        false, true, false
+    ]);
+
+    // Without recording them, `always_off` keeps propagating parent contexts, but does not start
+    // new traces.
+    std::env::set_var("OTEL_TRACES_SAMPLER", "always_off");
+    let cli = TestClient::new(context.with(FastraceMiddlewareBuilder::from_env().build()));
+    let (resp, _) = collect_spans(cli.get("/").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("sampled=false").await;
+    let (resp, _) = collect_spans(cli.get("/")).await;
+    resp.assert_text("none").await;
+}
@@ -0,0 +1,209 @@
+//! The status recorded on the span of failed requests, and the retention of traces by status.
+
+mod common;
+
+use common::TRACEPARENT;
+use common::client;
+use common::fail;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::SpanStatus;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::http::Method;
+use poem::http::StatusCode;
+use poem::middleware::CatchPanic;
+use poem::test::TestClient;
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn errors_only_drops_other_traces() {
+    let cli =
+        client(FastraceMiddleware::builder().with_errors_only(StatusCode::INTERNAL_SERVER_ERROR));
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    assert!(spans.is_empty());
+    let (resp, spans) = collect_spans(cli.get("/missing").header("traceparent", TRACEPARENT)).await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+    assert!(spans.is_empty());
+
+    let (_, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    assert_property(assert_span_named(&spans, "GET"), "error", "true");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn tail_decision_receives_the_status_of_errors() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_tail_decision(|status, _| status == StatusCode::NOT_FOUND),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    assert!(spans.is_empty());
+
+    let (_, spans) = collect_spans(cli.get("/missing").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn server_errors_are_marked_as_errors() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (resp, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "http.response.status_code", "500");
+    assert_property(root, "error", "true");
+    assert_property(root, "otel.status_code", "ERROR");
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    assert!(!root.properties.iter().any(|(k, _)| k == "error"));
+    assert!(!root.properties.iter().any(|(k, _)| k == "otel.status_code"));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn client_errors_leave_the_status_unset() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (resp, spans) = collect_spans(cli.get("/missing").header("traceparent", TRACEPARENT)).await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "http.response.status_code", "404");
+    assert!(!root.properties.iter().any(|(k, _)| k == "error"));
+    assert!(!root.properties.iter().any(|(k, _)| k == "otel.status_code"));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn status_classifier_receives_the_request() {
+    let cli = client(
+        FastraceMiddleware::builder().with_status_classifier(|req, status| {
+            match (req.method(), req.uri().path(), status) {
+                (&Method::GET, "/missing", StatusCode::NOT_FOUND) => SpanStatus::Error,
+                (_, _, status) if status.is_server_error() => SpanStatus::Unset,
+                _ => SpanStatus::Ok,
+            }
+        }),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/missing").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "error", "true");
+    assert_property(root, "otel.status_code", "ERROR");
+
+    let (_, spans) = collect_spans(cli.post("/missing").header("traceparent", TRACEPARENT)).await;
+    assert_property(assert_span_named(&spans, "POST"), "otel.status_code", "OK");
+
+    let (_, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    assert!(!root.properties.iter().any(|(k, _)| k == "otel.status_code"));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn errors_are_recorded_as_exceptions() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (_, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    let exception = root
+        .events
+        .iter()
+        .find(|event| event.name == "exception")
+        .expect("no exception event");
+    let property = |key: &str| {
+        exception
+            .properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_ref())
+    };
+    assert_eq!(property("exception.type"), Some("poem::Error"));
+    assert_eq!(property("exception.message"), Some("database unavailable"));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn panics_are_recorded_as_exceptions() {
+    #[handler]
+    fn panic() -> &'static str {
+        panic!("connection pool poisoned")
+    }
+
+    let cli = TestClient::new(
+        Route::new()
+            .at("/panic", panic)
+            .with(FastraceMiddleware::new())
+            .with(CatchPanic::new()),
+    );
+
+    let (resp, spans) = collect_spans(cli.get("/panic").header("traceparent", TRACEPARENT)).await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "error", "true");
+    let exception = root
+        .events
+        .iter()
+        .find(|event| event.name == "exception")
+        .expect("no exception event");
+    assert!(
+        exception
+            .properties
+            .iter()
+            .any(|(k, v)| k == "exception.type" && v == "panic")
+    );
+    assert!(
+        exception
+            .properties
+            .iter()
+            .any(|(k, v)| k == "exception.message" && v == "connection pool poisoned")
+    );
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn status_and_route_of_errors() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (_, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "http.route", "/fail");
+    assert_property(root, "http.response.status_code", "500");
+
+    let cli = TestClient::new(Route::new().at("/fail", fail.with(FastraceMiddleware::new())));
+    let (_, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET /fail");
+    assert_property(root, "http.route", "/fail");
+    assert_property(root, "http.response.status_code", "500");
+}
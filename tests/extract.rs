@@ -0,0 +1,62 @@
+//! The extractors of handlers tied to the root span.
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::Traced;
+use fastrace_poem::test::assert_parented_to;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::http::StatusCode;
+use poem::test::TestClient;
+use poem::web::Json;
+use serde_json::Value;
+
+#[handler]
+fn create(Traced(Json(order)): Traced<Json<Value>>) -> String {
+    order["id"].to_string()
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn traced_extractors_record_a_child_span() {
+    let cli = TestClient::new(
+        Route::new()
+            .at("/orders", create)
+            .with(FastraceMiddleware::new()),
+    );
+
+    let (resp, spans) = collect_spans(
+        cli.post("/orders")
+            .header("traceparent", TRACEPARENT)
+            .body_json(&serde_json::json!({ "id": 7 })),
+    )
+    .await;
+    resp.assert_text("7").await;
+    let extractor = assert_span_named(&spans, "Json<Value>");
+    assert_parented_to(extractor, assert_span_named(&spans, "POST"));
+    assert!(extractor.events.is_empty());
+
+    let (resp, spans) = collect_spans(
+        cli.post("/orders")
+            .header("traceparent", TRACEPARENT)
+            .content_type("application/json")
+            .body("{"),
+    )
+    .await;
+    resp.assert_status(StatusCode::BAD_REQUEST);
+    let extractor = assert_span_named(&spans, "Json<Value>");
+    assert!(
+        extractor
+            .events
+            .iter()
+            .any(|event| event.name == "exception")
+    );
+}
@@ -0,0 +1,136 @@
+//! Requests skipped by path, method, user agent and the tracing switch.
+
+mod common;
+
+use common::TRACEPARENT;
+use common::client;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::filter::TraceFilter;
+use fastrace_poem::filter::TracingSwitch;
+use fastrace_poem::test::collect_spans;
+use poem::Endpoint;
+use poem::http::Method;
+use poem::test::TestClient;
+
+/// Sends a request with a sampled parent and returns whether it was traced.
+async fn traced(cli: &TestClient<impl Endpoint>, method: Method, path: &str) -> bool {
+    let request = cli.request(method, path).header("traceparent", TRACEPARENT);
+    let (_, spans) = collect_spans(request).await;
+    !spans.is_empty()
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn skip_paths() {
+    let cli = client(FastraceMiddleware::builder().with_skip_paths(["/f*"]));
+
+    assert!(traced(&cli, Method::GET, "/ping").await);
+    assert!(!traced(&cli, Method::GET, "/fail").await);
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn filter_closure() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_filter(|req: &poem::Request| !req.headers().contains_key("x-internal")),
+    );
+
+    assert!(traced(&cli, Method::GET, "/ping").await);
+    let request = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("x-internal", "1");
+    let (resp, spans) = collect_spans(request).await;
+    resp.assert_text("pong").await;
+    assert!(spans.is_empty());
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn ignore_probes() {
+    let cli = client(FastraceMiddleware::builder().with_filter(TraceFilter::ignore_probes()));
+
+    let probe = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("user-agent", "kube-probe/1.29");
+    let (_, spans) = collect_spans(probe).await;
+    assert!(spans.is_empty());
+
+    let browser = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("user-agent", "Mozilla/5.0");
+    let (_, spans) = collect_spans(browser).await;
+    assert!(!spans.is_empty());
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn skip_methods() {
+    let cli = client(FastraceMiddleware::builder().with_skip_methods([Method::HEAD]));
+
+    assert!(traced(&cli, Method::GET, "/ping").await);
+    assert!(!traced(&cli, Method::HEAD, "/ping").await);
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn skip_preflight() {
+    let cli = client(FastraceMiddleware::builder().with_skip_preflight(true));
+
+    let preflight = cli
+        .request(Method::OPTIONS, "/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("origin", "https://example.com")
+        .header("access-control-request-method", "POST");
+    let (_, spans) = collect_spans(preflight).await;
+    assert!(spans.is_empty());
+
+    assert!(traced(&cli, Method::OPTIONS, "/ping").await);
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn switch_disables_tracing() {
+    let switch = TracingSwitch::new();
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_switch(switch.clone())
+            .with_force_trace_header("x-force-trace"),
+    );
+
+    assert!(traced(&cli, Method::GET, "/ping").await);
+
+    switch.disable();
+    assert!(!traced(&cli, Method::GET, "/ping").await);
+    // Forcing a trace does not bypass the switch.
+    let forced = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("x-force-trace", "1");
+    let (_, spans) = collect_spans(forced).await;
+    assert!(spans.is_empty());
+
+    switch.enable();
+    assert!(traced(&cli, Method::GET, "/ping").await);
+}
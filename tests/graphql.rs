@@ -0,0 +1,95 @@
+//! The GraphQL operation appended to the name of the root span.
+
+#![cfg(feature = "graphql")]
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::Endpoint;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+use poem::web::Json;
+use serde_json::Value;
+use serde_json::json;
+
+/// Echoes the GraphQL request, to check the body reaches the endpoint unchanged.
+#[handler]
+fn graphql(Json(request): Json<Value>) -> Json<Value> {
+    Json(request)
+}
+
+fn client() -> TestClient<impl Endpoint> {
+    TestClient::new(
+        Route::new()
+            .at("/graphql", poem::get(common::ping).post(graphql))
+            .with(
+                FastraceMiddleware::builder()
+                    .with_graphql_path("/graphql")
+                    .build(),
+            ),
+    )
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn operation_of_json_bodies() {
+    let cli = client();
+    let request = json!({
+        "query": "query GetUser { user { id } } mutation DeleteUser { deleteUser }",
+        "operationName": "DeleteUser",
+    });
+
+    let (resp, spans) = collect_spans(
+        cli.post("/graphql")
+            .header("traceparent", TRACEPARENT)
+            .body_json(&request),
+    )
+    .await;
+    resp.assert_json(&request).await;
+    let root = assert_span_named(&spans, "POST DeleteUser");
+    assert_property(root, "graphql.operation.name", "DeleteUser");
+    assert_property(root, "graphql.operation.type", "mutation");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn operation_of_get_queries() {
+    let cli = client();
+
+    let (_, spans) = collect_spans(
+        cli.get("/graphql")
+            .query("query", &"query GetUser { user { id } }")
+            .header("traceparent", TRACEPARENT),
+    )
+    .await;
+    let root = assert_span_named(&spans, "GET GetUser");
+    assert_property(root, "graphql.operation.name", "GetUser");
+    assert_property(root, "graphql.operation.type", "query");
+
+    let (_, spans) = collect_spans(
+        cli.get("/graphql")
+            .query("query", &"{ user { id } }")
+            .header("traceparent", TRACEPARENT),
+    )
+    .await;
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "graphql.operation.type", "query");
+    assert!(
+        !root
+            .properties
+            .iter()
+            .any(|(k, _)| k == "graphql.operation.name")
+    );
+}
@@ -0,0 +1,70 @@
+//! The heartbeat events recorded while long-running requests are handled.
+
+mod common;
+
+use std::time::Duration;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+
+#[handler]
+async fn slow() -> &'static str {
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    "done"
+}
+
+/// Sends a request to [`slow`] and returns the number of heartbeats of its root span.
+async fn heartbeats(interval: Duration) -> usize {
+    let cli = TestClient::new(
+        Route::new().at("/slow", slow).with(
+            FastraceMiddleware::builder()
+                .with_heartbeat(interval)
+                .build(),
+        ),
+    );
+
+    let (resp, spans) = collect_spans(cli.get("/slow").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("done").await;
+    let root = assert_span_named(&spans, "GET");
+    root.events
+        .iter()
+        .filter(|event| event.name == "still running")
+        .count()
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn heartbeat_events() {
+    let count = heartbeats(Duration::from_millis(20)).await;
+    assert!((1..=2).contains(&count), "{count} heartbeats");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn zero_interval_disables_the_heartbeat() {
+    assert_eq!(heartbeats(Duration::ZERO).await, 0);
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn short_intervals_record_at_most_one_heartbeat_per_poll() {
+    // Tokio timers have a resolution of a millisecond, so the handler is polled at most once per
+    // millisecond for the heartbeat.
+    let count = heartbeats(Duration::from_nanos(1)).await;
+    assert!((1..=60).contains(&count), "{count} heartbeats");
+}
@@ -0,0 +1,146 @@
+//! The context propagated to the outgoing requests of handlers.
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::FastraceMiddlewareBuilder;
+use fastrace_poem::propagation::B3;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::Endpoint;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::http::HeaderMap;
+use poem::test::TestClient;
+
+/// Returns the headers written by `inject_context`, as `name=value` lines.
+#[handler]
+fn outgoing() -> String {
+    let mut headers = HeaderMap::new();
+    fastrace_poem::inject_context(&mut headers);
+    headers
+        .iter()
+        .map(|(name, value)| format!("{name}={}", value.to_str().unwrap()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the headers written by `with_trace_context` into a reqwest request, as `name=value`
+/// lines.
+#[cfg(feature = "reqwest")]
+#[handler]
+fn outgoing_reqwest() -> String {
+    use fastrace_poem::RequestBuilderExt;
+
+    let req = reqwest::Client::new()
+        .get("http://inventory/items")
+        .with_trace_context()
+        .build()
+        .unwrap();
+    req.headers()
+        .iter()
+        .map(|(name, value)| format!("{name}={}", value.to_str().unwrap()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn client(middleware: FastraceMiddlewareBuilder) -> TestClient<impl Endpoint> {
+    let route = Route::new().at("/outgoing", outgoing);
+    #[cfg(feature = "reqwest")]
+    let route = route.at("/outgoing_reqwest", outgoing_reqwest);
+    TestClient::new(route.with(middleware.build()))
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn injects_the_context_of_the_root_span() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (resp, spans) =
+        collect_spans(cli.get("/outgoing").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    resp.assert_text(format!(
+        "traceparent=00-{:032x}-{:016x}-01",
+        root.trace_id.0, root.span_id.0
+    ))
+    .await;
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn injects_with_the_configured_injector() {
+    let cli = client(FastraceMiddleware::builder().with_injector(B3));
+
+    let (resp, spans) =
+        collect_spans(cli.get("/outgoing").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    resp.assert_text(format!(
+        "b3={:032x}-{:016x}-1",
+        root.trace_id.0, root.span_id.0
+    ))
+    .await;
+}
+
+#[tokio::test]
+async fn injects_nothing_for_untraced_requests() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (resp, _) = collect_spans(cli.get("/outgoing")).await;
+    resp.assert_text("").await;
+}
+
+#[tokio::test]
+#[cfg(feature = "reqwest")]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn reqwest_requests_carry_the_trace_state_and_baggage() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (resp, spans) = collect_spans(
+        cli.get("/outgoing_reqwest")
+            .header("traceparent", TRACEPARENT)
+            .header("tracestate", "vendor=value")
+            .header("baggage", "tenant=acme"),
+    )
+    .await;
+    let root = assert_span_named(&spans, "GET");
+    resp.assert_text(format!(
+        "traceparent=00-{:032x}-{:016x}-01\ntracestate=vendor=value\nbaggage=tenant=acme",
+        root.trace_id.0, root.span_id.0
+    ))
+    .await;
+}
+
+#[tokio::test]
+#[cfg(feature = "reqwest")]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn reqwest_requests_carry_the_trace_state_and_baggage_only_with_traceparent() {
+    let cli = client(FastraceMiddleware::builder().with_injector(B3));
+
+    let (resp, spans) = collect_spans(
+        cli.get("/outgoing_reqwest")
+            .header("traceparent", TRACEPARENT)
+            .header("tracestate", "vendor=value")
+            .header("baggage", "tenant=acme"),
+    )
+    .await;
+    let root = assert_span_named(&spans, "GET");
+    resp.assert_text(format!(
+        "b3={:032x}-{:016x}-1",
+        root.trace_id.0, root.span_id.0
+    ))
+    .await;
+}
@@ -0,0 +1,107 @@
+//! The trace and span ids added to the `log` records of traced requests.
+
+#![cfg(feature = "log")]
+
+mod common;
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use common::TRACE_ID;
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::TraceIdLogger;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use log::LevelFilter;
+use log::Log;
+use log::Metadata;
+use log::Record;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+
+/// The records logged so far.
+static RECORDS: Mutex<Vec<LogRecord>> = Mutex::new(Vec::new());
+
+#[derive(Debug, PartialEq)]
+struct LogRecord {
+    message: String,
+    trace_id: Option<String>,
+    span_id: Option<String>,
+}
+
+struct MemoryLogger;
+
+impl Log for MemoryLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        // Skips the records of dependencies, such as the spans `tracing` logs with its `log`
+        // feature.
+        if record.target() != module_path!() {
+            return;
+        }
+        let value = |key: &str| {
+            record
+                .key_values()
+                .get(key.into())
+                .map(|value| value.to_string())
+        };
+        RECORDS.lock().unwrap().push(LogRecord {
+            message: record.args().to_string(),
+            trace_id: value("trace_id"),
+            span_id: value("span_id"),
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: TraceIdLogger<MemoryLogger> = TraceIdLogger::new(MemoryLogger);
+
+#[handler]
+async fn logging() -> &'static str {
+    log::info!("loading");
+    tokio::time::sleep(Duration::from_millis(1)).await;
+    log::info!("loaded");
+    "done"
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn records_carry_the_ids_of_their_request() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Info);
+    let cli = TestClient::new(
+        Route::new()
+            .at("/logging", logging)
+            .with(FastraceMiddleware::new()),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/logging").header("traceparent", TRACEPARENT)).await;
+    log::info!("idle");
+
+    let root = assert_span_named(&spans, "GET");
+    let traced = |message: &str| LogRecord {
+        message: message.to_string(),
+        trace_id: Some(format!("{TRACE_ID:032x}")),
+        span_id: Some(root.span_id.to_string()),
+    };
+    let idle = LogRecord {
+        message: "idle".to_string(),
+        trace_id: None,
+        span_id: None,
+    };
+    assert_eq!(*RECORDS.lock().unwrap(), [
+        traced("loading"),
+        traced("loaded"),
+        idle
+    ]);
+}
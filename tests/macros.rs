@@ -0,0 +1,55 @@
+//! The spans of the handlers defined with `#[traced_handler]`.
+
+#![cfg(feature = "macros")]
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::assert_parented_to;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use fastrace_poem::traced_handler;
+use poem::EndpointExt;
+use poem::Route;
+use poem::test::TestClient;
+use poem::web::Path;
+
+#[traced_handler]
+async fn get_user(Path(id): Path<u64>) -> String {
+    format!("user {id}")
+}
+
+#[traced_handler(name = "health check")]
+fn health() -> &'static str {
+    "ok"
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn traced_handlers_record_a_child_span() {
+    let cli = TestClient::new(
+        Route::new()
+            .at("/users/:id", get_user)
+            .at("/health", health)
+            .with(FastraceMiddleware::new()),
+    );
+
+    let (resp, spans) =
+        collect_spans(cli.get("/users/42").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("user 42").await;
+    assert_parented_to(
+        assert_span_named(&spans, "get_user"),
+        assert_span_named(&spans, "GET"),
+    );
+
+    let (resp, spans) = collect_spans(cli.get("/health").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("ok").await;
+    assert_parented_to(
+        assert_span_named(&spans, "health check"),
+        assert_span_named(&spans, "GET"),
+    );
+}
@@ -0,0 +1,62 @@
+//! The spans recorded for the parts of multipart uploads.
+
+#![cfg(feature = "multipart")]
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::TracedMultipart;
+use fastrace_poem::test::assert_parented_to;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Result;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+use poem::test::TestForm;
+use poem::test::TestFormField;
+
+#[handler]
+async fn upload(mut multipart: TracedMultipart) -> Result<String> {
+    let mut total = 0;
+    while let Some(field) = multipart.next_field().await? {
+        total += field.bytes().await?.len();
+    }
+    Ok(format!("{total} bytes uploaded"))
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn parts_are_recorded_as_child_spans() {
+    let cli = TestClient::new(
+        Route::new()
+            .at("/upload", upload)
+            .with(FastraceMiddleware::new()),
+    );
+
+    let form = TestForm::new().field(
+        TestFormField::bytes(b"0123456789".to_vec())
+            .name("avatar")
+            .filename("avatar.png")
+            .content_type("image/png"),
+    );
+    let (resp, spans) = collect_spans(
+        cli.post("/upload")
+            .header("traceparent", TRACEPARENT)
+            .multipart(form),
+    )
+    .await;
+    resp.assert_text("10 bytes uploaded").await;
+    let part = assert_span_named(&spans, "multipart.part");
+    assert_parented_to(part, assert_span_named(&spans, "POST"));
+    assert_property(part, "multipart.part.name", "avatar");
+    assert_property(part, "multipart.part.file_name", "avatar.png");
+    assert_property(part, "multipart.part.content_type", "image/png");
+    assert_property(part, "multipart.part.size", "10");
+}
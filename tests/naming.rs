@@ -0,0 +1,126 @@
+//! The name of the root span.
+
+mod common;
+
+use common::TRACEPARENT;
+use common::ping;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::PathNormalization;
+use fastrace_poem::SpanName;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Route;
+use poem::test::TestClient;
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn span_name_outside_the_middleware() {
+    let cli = TestClient::new(
+        Route::new().at(
+            "/users/:id",
+            ping.with(FastraceMiddleware::new())
+                .with(SpanName::new("GetUser")),
+        ),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/users/42").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GetUser");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn span_name_inside_the_middleware() {
+    let cli = TestClient::new(
+        Route::new()
+            .at("/users/:id", ping.with(SpanName::new("GetUser")))
+            .at("/ping", ping)
+            .with(FastraceMiddleware::new()),
+    );
+
+    // The name is learned from the first request to the route.
+    let (_, spans) = collect_spans(cli.get("/users/42").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET");
+    let (_, spans) = collect_spans(cli.get("/users/7").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GetUser");
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn path_templating() {
+    let cli = TestClient::new(
+        Route::new().at("/users/:id", ping).with(
+            FastraceMiddleware::builder()
+                .with_path_templating(true)
+                .build(),
+        ),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/users/42").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET /users/{id}");
+    // The matched route carried by the response takes precedence over the template.
+    assert_property(root, "http.route", "/users/:id");
+
+    let (_, spans) = collect_spans(cli.get("/users/me").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET /users/me");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn max_span_names() {
+    let cli = TestClient::new(
+        Route::new().at("/users/:id", ping).with(
+            FastraceMiddleware::builder()
+                .with_path_templating(true)
+                .with_max_span_names(1)
+                .build(),
+        ),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/users/42").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET /users/{id}");
+    let (_, spans) = collect_spans(cli.get("/users/me").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "HTTP GET");
+    let (_, spans) = collect_spans(cli.get("/users/7").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET /users/{id}");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn path_normalization() {
+    let cli = TestClient::new(
+        Route::new().at("/Users/:id/", ping).with(
+            FastraceMiddleware::builder()
+                .with_path_templating(true)
+                .with_path_normalization(
+                    PathNormalization::new()
+                        .with_trim_trailing_slash(true)
+                        .with_lowercase(true),
+                )
+                .build(),
+        ),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/Users/42/").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET /users/{id}");
+    assert_property(root, "url.path", "/Users/42/");
+}
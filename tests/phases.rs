@@ -0,0 +1,49 @@
+//! The child spans of the root span covering the phases of the request.
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace::collector::SpanRecord;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::assert_parented_to;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+
+#[handler]
+#[fastrace::trace(name = "work")]
+fn work() -> &'static str {
+    "done"
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn phase_spans() {
+    let cli = TestClient::new(
+        Route::new()
+            .at("/work", work)
+            .with(FastraceMiddleware::builder().with_phase_spans(true).build()),
+    );
+
+    let (resp, spans) = collect_spans(cli.get("/work").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("done").await;
+    let root = assert_span_named(&spans, "GET");
+    let setup = assert_span_named(&spans, "setup");
+    let handler = assert_span_named(&spans, "handler");
+    let write = assert_span_named(&spans, "response.write");
+    for phase in [setup, handler, write] {
+        assert_parented_to(phase, root);
+    }
+    assert_parented_to(assert_span_named(&spans, "work"), handler);
+
+    let end = |span: &SpanRecord| span.begin_time_unix_ns + span.duration_ns;
+    assert!(end(setup) <= handler.begin_time_unix_ns);
+    assert!(end(handler) <= write.begin_time_unix_ns);
+    assert!(end(write) <= end(root));
+}
@@ -0,0 +1,325 @@
+//! The parent context extracted from the propagation formats.
+
+mod common;
+
+use common::SPAN_ID;
+use common::TRACE_ID;
+use common::TRACEPARENT;
+use common::client;
+use fastrace::collector::SpanContext;
+use fastrace::collector::SpanId;
+use fastrace::collector::TraceId;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::propagation::B3;
+use fastrace_poem::propagation::Baggage;
+use fastrace_poem::propagation::ExtractorChain;
+use fastrace_poem::propagation::W3CTraceContext;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Request;
+use poem::handler;
+use poem::http::HeaderMap;
+use poem::test::TestClient;
+
+/// Sends a request with the given headers and asserts that its root span continues the trace.
+async fn assert_continues(headers: &[(&str, &str)], trace_id: u128, parent_id: u64) {
+    let cli = client(FastraceMiddleware::builder());
+
+    let request = headers
+        .iter()
+        .fold(cli.get("/ping"), |request, (name, value)| {
+            request.header(*name, *value)
+        });
+    let (resp, spans) = collect_spans(request).await;
+
+    resp.assert_text("pong").await;
+    let root = assert_span_named(&spans, "GET");
+    assert_eq!(root.trace_id, TraceId(trace_id));
+    assert_eq!(root.parent_id, SpanId(parent_id));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn b3_multiple_headers() {
+    assert_continues(
+        &[
+            ("x-b3-traceid", "0af7651916cd43dd8448eb211c80319c"),
+            ("x-b3-spanid", "b7ad6b7169203331"),
+            ("x-b3-sampled", "1"),
+        ],
+        TRACE_ID,
+        SPAN_ID,
+    )
+    .await;
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn b3_single_header() {
+    assert_continues(
+        &[("b3", "0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-1")],
+        TRACE_ID,
+        SPAN_ID,
+    )
+    .await;
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn jaeger() {
+    assert_continues(
+        &[(
+            "uber-trace-id",
+            "0af7651916cd43dd8448eb211c80319c:b7ad6b7169203331:0:1",
+        )],
+        TRACE_ID,
+        SPAN_ID,
+    )
+    .await;
+}
+
+#[cfg(feature = "xray")]
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn xray() {
+    assert_continues(
+        &[(
+            "x-amzn-trace-id",
+            "Root=1-0af76519-16cd43dd8448eb211c80319c;Parent=b7ad6b7169203331;Sampled=1;",
+        )],
+        TRACE_ID,
+        SPAN_ID,
+    )
+    .await;
+}
+
+#[cfg(feature = "xray")]
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn xray_without_sampling_decision_is_sampled_like_a_new_trace() {
+    let cli = client(FastraceMiddleware::builder().with_sample_ratio(0.0));
+    let root = "Root=1-0af76519-16cd43dd8448eb211c80319c;Parent=b7ad6b7169203331";
+
+    let deferred = cli
+        .get("/ping")
+        .header("x-amzn-trace-id", format!("{root};Sampled=?"));
+    let (_, spans) = collect_spans(deferred).await;
+    assert!(spans.is_empty());
+
+    let sampled = cli
+        .get("/ping")
+        .header("x-amzn-trace-id", format!("{root};Sampled=1"));
+    let (_, spans) = collect_spans(sampled).await;
+    assert_span_named(&spans, "GET");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn datadog() {
+    assert_continues(
+        &[
+            ("x-datadog-trace-id", "1234567890123456789"),
+            ("x-datadog-parent-id", "987654321"),
+        ],
+        1234567890123456789,
+        987654321,
+    )
+    .await;
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn cloud_trace_context() {
+    assert_continues(
+        &[(
+            "x-cloud-trace-context",
+            "0af7651916cd43dd8448eb211c80319c/13235353014750950193;o=1",
+        )],
+        TRACE_ID,
+        SPAN_ID,
+    )
+    .await;
+}
+
+#[cfg(feature = "skywalking")]
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn skywalking() {
+    let cli = client(FastraceMiddleware::builder());
+
+    // The trace id is `0af7651916cd43dd8448eb211c80319c`, the parent segment `segment`.
+    let sw8 = "1-MGFmNzY1MTkxNmNkNDNkZDg0NDhlYjIxMWM4MDMxOWM=-c2VnbWVudA==-3-c2VydmljZQ==-\
+               aW5zdGFuY2U=-L3Bpbmc=-MTAuMC4wLjE6ODA4MA==";
+    let (_, spans) = collect_spans(cli.get("/ping").header("sw8", sw8)).await;
+
+    let root = assert_span_named(&spans, "GET");
+    assert_eq!(root.trace_id, TraceId(TRACE_ID));
+    assert_ne!(root.parent_id, SpanId(0));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn w3c_trace_context() {
+    assert_continues(&[("traceparent", TRACEPARENT)], TRACE_ID, SPAN_ID).await;
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn custom_extractor() {
+    let extractor = |headers: &HeaderMap| {
+        let trace_id = headers.get("x-trace")?.to_str().ok()?.parse().ok()?;
+        Some(SpanContext::new(TraceId(trace_id), SpanId(7)))
+    };
+    let cli = client(FastraceMiddleware::builder().with_extractor(extractor));
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("x-trace", "42")).await;
+    let root = assert_span_named(&spans, "GET");
+    assert_eq!(root.trace_id, TraceId(42));
+    assert_eq!(root.parent_id, SpanId(7));
+
+    // The built-in formats are not read anymore.
+    let (_, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    assert!(spans.is_empty());
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn w3c_takes_precedence_over_b3() {
+    assert_continues(
+        &[
+            ("traceparent", TRACEPARENT),
+            ("b3", "4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1"),
+        ],
+        TRACE_ID,
+        SPAN_ID,
+    )
+    .await;
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn extractor_chain_precedence() {
+    let chain = ExtractorChain::new().with(B3).with(W3CTraceContext::new());
+    let cli = client(FastraceMiddleware::builder().with_extractor(chain));
+
+    let request = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("b3", "4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1");
+    let (_, spans) = collect_spans(request).await;
+
+    let root = assert_span_named(&spans, "GET");
+    assert_eq!(root.trace_id, TraceId(0x4bf92f3577b34da6a3ce929d0e0e4736));
+    assert_eq!(root.parent_id, SpanId(0x00f067aa0ba902b7));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn tracestate_of_traceparent() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let request = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("tracestate", "congo=t61rcWkgMzE");
+    let (_, spans) = collect_spans(request).await;
+    assert_property(
+        assert_span_named(&spans, "GET"),
+        "w3c.tracestate",
+        "congo=t61rcWkgMzE",
+    );
+
+    // The vendor state is dropped with a parent read from another format.
+    let request = cli
+        .get("/ping")
+        .header("b3", "4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1")
+        .header("tracestate", "congo=t61rcWkgMzE");
+    let (_, spans) = collect_spans(request).await;
+    let root = assert_span_named(&spans, "GET");
+    assert!(!root.properties.iter().any(|(k, _)| k == "w3c.tracestate"));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn baggage() {
+    #[handler]
+    fn user(req: &Request) -> String {
+        let baggage = req.extensions().get::<Baggage>();
+        baggage
+            .and_then(|baggage| baggage.get("user.id"))
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    let middleware = FastraceMiddleware::builder().with_baggage_properties(["tenant.id"]);
+    let cli = TestClient::new(user.with(middleware.build()));
+
+    let request = cli
+        .get("/")
+        .header("traceparent", TRACEPARENT)
+        .header("baggage", "tenant.id=acme,user.id=42");
+    let (resp, spans) = collect_spans(request).await;
+
+    resp.assert_text("42").await;
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "tenant.id", "acme");
+    assert!(!root.properties.iter().any(|(k, _)| k == "user.id"));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn renamed_trace_header() {
+    let cli = client(FastraceMiddleware::builder().with_trace_header("x-traceparent"));
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("x-traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    assert_eq!(root.trace_id, TraceId(TRACE_ID));
+    assert_eq!(root.parent_id, SpanId(SPAN_ID));
+}
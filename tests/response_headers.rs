@@ -0,0 +1,144 @@
+//! The trace context and request id returned in the response headers.
+
+mod common;
+
+use common::TRACEPARENT;
+use common::client;
+use common::fail;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use fastrace_poem::test::sequential_ids;
+use poem::EndpointExt;
+use poem::Error;
+use poem::Route;
+use poem::http::HeaderValue;
+use poem::http::StatusCode;
+use poem::test::TestClient;
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn traceresponse() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_trace_all_requests(true)
+            .with_id_generator(sequential_ids())
+            .with_traceresponse(true),
+    );
+
+    let (resp, spans) = collect_spans(cli.get("/ping")).await;
+    let root = assert_span_named(&spans, "GET");
+    resp.assert_header(
+        "traceresponse",
+        format!("00-{}-{}-01", root.trace_id, root.span_id),
+    );
+
+    let (resp, _) = collect_spans(cli.get("/fail")).await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    resp.assert_header_exist("traceresponse");
+}
+
+#[tokio::test]
+async fn no_headers_by_default() {
+    let cli = client(FastraceMiddleware::builder().with_trace_all_requests(true));
+
+    let (resp, _) = collect_spans(cli.get("/ping")).await;
+    resp.assert_header_is_not_exist("traceresponse");
+    resp.assert_header_is_not_exist("traceparent");
+    resp.assert_header_is_not_exist("server-timing");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn request_id() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_request_id_header("x-request-id")
+            .with_generate_request_id(true),
+    );
+
+    let request = cli
+        .get("/ping")
+        .header("traceparent", TRACEPARENT)
+        .header("x-request-id", "req-42");
+    let (resp, spans) = collect_spans(request).await;
+    resp.assert_header("x-request-id", "req-42");
+    assert_property(
+        assert_span_named(&spans, "GET"),
+        "http.request.id",
+        "req-42",
+    );
+
+    let (resp, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    let id = resp.0.headers()["x-request-id"].to_str().unwrap();
+    assert_property(assert_span_named(&spans, "GET"), "http.request.id", id);
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn trace_id_header() {
+    let cli = client(FastraceMiddleware::builder().with_trace_id_header("x-trace-id"));
+
+    for path in ["/ping", "/fail"] {
+        let (resp, _) = collect_spans(cli.get(path).header("traceparent", TRACEPARENT)).await;
+        resp.assert_header("x-trace-id", "0af7651916cd43dd8448eb211c80319c");
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn errors_with_response_headers_reach_outer_error_handlers() {
+    let cli = TestClient::new(
+        Route::new()
+            .at("/fail", fail)
+            .with(
+                FastraceMiddleware::builder()
+                    .with_trace_id_header("x-trace-id")
+                    .build(),
+            )
+            .catch_all_error(|err: Error| async move {
+                let mut resp = err.into_response();
+                resp.headers_mut()
+                    .insert("x-caught", HeaderValue::from_static("true"));
+                resp
+            }),
+    );
+
+    let (resp, _) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    resp.assert_header("x-caught", "true");
+    resp.assert_header("x-trace-id", "0af7651916cd43dd8448eb211c80319c");
+    resp.assert_text("database unavailable").await;
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn server_timing() {
+    let cli = client(FastraceMiddleware::builder().with_server_timing(true));
+
+    for path in ["/ping", "/fail"] {
+        let (resp, _) = collect_spans(cli.get(path).header("traceparent", TRACEPARENT)).await;
+        let timing = resp.0.headers()["server-timing"].to_str().unwrap();
+        assert!(timing.starts_with("total;dur="), "{timing}");
+        assert!(
+            timing.contains(", traceparent;desc=\"00-0af7651916cd43dd8448eb211c80319c-"),
+            "{timing}"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! The sampling decisions of requests with and without a parent context.
+
+mod common;
+
+use common::TRACEPARENT;
+use common::client;
+use fastrace::collector::SpanContext;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::SamplingDecision;
+use fastrace_poem::UnsampledParent;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::EndpointExt;
+use poem::Request;
+use poem::handler;
+use poem::test::TestClient;
+
+const UNSAMPLED_TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00";
+
+/// Returns whether the request carries a span context, and whether it is sampled.
+#[handler]
+fn context(req: &Request) -> String {
+    match req.extensions().get::<SpanContext>() {
+        Some(context) => format!("sampled={}", context.sampled),
+        None => "none".to_string(),
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn sampled_flag_of_the_parent() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET");
+
+    let (resp, spans) = collect_spans(
+        cli.get("/ping")
+            .header("traceparent", UNSAMPLED_TRACEPARENT),
+    )
+    .await;
+    resp.assert_text("pong").await;
+    assert!(spans.is_empty());
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no context is propagated with the `disabled` feature"
+)]
+async fn unsampled_parents_keep_propagating_the_context() {
+    let cli = TestClient::new(context.with(FastraceMiddleware::new()));
+
+    let (resp, _) = collect_spans(cli.get("/").header("traceparent", UNSAMPLED_TRACEPARENT)).await;
+    resp.assert_text("sampled=false").await;
+
+    let middleware = FastraceMiddleware::builder().with_unsampled_parent(UnsampledParent::Noop);
+    let cli = TestClient::new(context.with(middleware.build()));
+
+    let (resp, _) = collect_spans(cli.get("/").header("traceparent", UNSAMPLED_TRACEPARENT)).await;
+    resp.assert_text("none").await;
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn force_header_traces_unsampled_requests() {
+    let cli = client(FastraceMiddleware::builder().with_force_trace_header("x-force-trace"));
+
+    for value in ["1", "true"] {
+        let request = cli
+            .get("/ping")
+            .header("traceparent", UNSAMPLED_TRACEPARENT)
+            .header("x-force-trace", value);
+        let (_, spans) = collect_spans(request).await;
+        assert_property(assert_span_named(&spans, "GET"), "trace.forced", "true");
+    }
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("x-force-trace", "0")).await;
+    assert!(spans.is_empty());
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn force_header_bypasses_skipped_paths() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_force_trace_header("x-force-trace")
+            .with_skip_paths(["/ping"]),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    assert!(spans.is_empty());
+
+    let (_, spans) = collect_spans(cli.get("/ping").header("x-force-trace", "1")).await;
+    assert_property(assert_span_named(&spans, "GET"), "trace.forced", "true");
+}
+
+#[tokio::test]
+async fn requests_without_parent_are_not_traced_by_default() {
+    let cli = client(FastraceMiddleware::builder());
+
+    let (resp, spans) = collect_spans(cli.get("/ping")).await;
+
+    resp.assert_text("pong").await;
+    assert!(spans.is_empty());
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn trace_all_requests_starts_new_traces() {
+    let cli = client(FastraceMiddleware::builder().with_trace_all_requests(true));
+
+    let (_, first) = collect_spans(cli.get("/ping")).await;
+    let (_, second) = collect_spans(cli.get("/ping")).await;
+
+    let first = assert_span_named(&first, "GET");
+    let second = assert_span_named(&second, "GET");
+    assert_ne!(first.trace_id, second.trace_id);
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn sampler_decides_per_request() {
+    let sampler = |req: &Request| match req.headers().get("x-sample").map(|v| v.as_bytes()) {
+        Some(b"record") => SamplingDecision::Record,
+        Some(b"propagate") => SamplingDecision::PropagateOnly,
+        _ => SamplingDecision::Drop,
+    };
+    let middleware = FastraceMiddleware::builder().with_sampler(sampler);
+    let cli = TestClient::new(context.with(middleware.build()));
+
+    let (resp, spans) = collect_spans(cli.get("/").header("x-sample", "record")).await;
+    resp.assert_text("sampled=true").await;
+    assert_span_named(&spans, "GET");
+
+    // The decision replaces the sampled flag of the parent.
+    let request = cli
+        .get("/")
+        .header("traceparent", TRACEPARENT)
+        .header("x-sample", "propagate");
+    let (resp, spans) = collect_spans(request).await;
+    resp.assert_text("sampled=false").await;
+    assert!(spans.is_empty());
+
+    let (resp, spans) = collect_spans(cli.get("/").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("none").await;
+    assert!(spans.is_empty());
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn sample_ratio_drops_new_traces() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_trace_all_requests(true)
+            .with_sample_ratio(0.0),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/ping")).await;
+    assert!(spans.is_empty());
+
+    // Sampled parents are followed regardless of the ratio.
+    let (_, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn max_roots_per_second_limits_new_traces() {
+    let cli = client(
+        FastraceMiddleware::builder()
+            .with_trace_all_requests(true)
+            .with_max_roots_per_second(1),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/ping")).await;
+    assert_span_named(&spans, "GET");
+    let (_, spans) = collect_spans(cli.get("/ping")).await;
+    assert!(spans.is_empty());
+
+    // Parents are followed regardless of the limit.
+    let (_, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    assert_span_named(&spans, "GET");
+}
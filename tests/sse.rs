@@ -0,0 +1,49 @@
+//! The server-sent events recorded on the root span.
+
+#![cfg(feature = "sse")]
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace_poem::CurrentSpan;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::TracedEvents;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use futures_util::StreamExt;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+use poem::web::sse::Event;
+use poem::web::sse::SSE;
+
+#[handler]
+fn ticks(span: CurrentSpan) -> SSE {
+    let events = futures_util::stream::iter(0..5).map(|n| Event::message(n.to_string()));
+    SSE::new(TracedEvents::new(events, span).with_max_events(2))
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn events_are_capped() {
+    let cli = TestClient::new(
+        Route::new()
+            .at("/ticks", ticks)
+            .with(FastraceMiddleware::new()),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/ticks").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    let events = root
+        .events
+        .iter()
+        .filter(|event| event.name == "sse.event")
+        .count();
+    assert_eq!(events, 2);
+    assert_property(root, "sse.events", "5");
+}
@@ -0,0 +1,117 @@
+//! The properties of the files served by Poem's static file endpoints.
+
+mod common;
+
+use std::fs;
+
+use common::TRACEPARENT;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::TracedStaticFiles;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::Endpoint;
+use poem::EndpointExt;
+use poem::Route;
+use poem::endpoint::StaticFileEndpoint;
+use poem::endpoint::StaticFilesEndpoint;
+use poem::http::StatusCode;
+use poem::http::header;
+use poem::test::TestClient;
+
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests");
+
+const FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+fn client() -> TestClient<impl Endpoint> {
+    TestClient::new(
+        Route::new()
+            .nest(
+                "/files",
+                StaticFilesEndpoint::new(DIR).with(TracedStaticFiles::new()),
+            )
+            .at(
+                "/manifest",
+                StaticFileEndpoint::new(FILE).with(TracedStaticFiles::single_file()),
+            )
+            .with(FastraceMiddleware::new()),
+    )
+}
+
+fn file_size(path: &str) -> String {
+    fs::metadata(path).unwrap().len().to_string()
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn served_files() {
+    let cli = client();
+
+    let (resp, spans) = collect_spans(
+        cli.get("/files/common/mod.rs")
+            .header("traceparent", TRACEPARENT),
+    )
+    .await;
+    resp.assert_status_is_ok();
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "static_file.path", "common/mod.rs");
+    assert_property(
+        root,
+        "static_file.size",
+        &file_size(&format!("{DIR}/common/mod.rs")),
+    );
+    assert_property(root, "static_file.not_modified", "false");
+
+    let (resp, spans) =
+        collect_spans(cli.get("/manifest").header("traceparent", TRACEPARENT)).await;
+    resp.assert_status_is_ok();
+    let root = assert_span_named(&spans, "GET");
+    assert!(!root.properties.iter().any(|(k, _)| k == "static_file.path"));
+    assert_property(root, "static_file.size", &file_size(FILE));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn size_of_range_requests_is_the_file_size() {
+    let cli = client();
+
+    let (resp, spans) = collect_spans(
+        cli.get("/manifest")
+            .header("traceparent", TRACEPARENT)
+            .header(header::RANGE, "bytes=0-9"),
+    )
+    .await;
+    resp.assert_status(StatusCode::PARTIAL_CONTENT);
+    resp.assert_header(header::CONTENT_LENGTH, "10");
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "static_file.size", &file_size(FILE));
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn not_modified_files() {
+    let cli = client();
+
+    let resp = cli.get("/manifest").send().await;
+    let etag = resp.0.headers().get(header::ETAG).unwrap().clone();
+
+    let (resp, spans) = collect_spans(
+        cli.get("/manifest")
+            .header("traceparent", TRACEPARENT)
+            .header(header::IF_NONE_MATCH, etag),
+    )
+    .await;
+    resp.assert_status(StatusCode::NOT_MODIFIED);
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "static_file.not_modified", "true");
+    assert!(!root.properties.iter().any(|(k, _)| k == "static_file.size"));
+}
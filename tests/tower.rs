@@ -0,0 +1,66 @@
+//! The spans of requests traced by the Tower layer variant of the middleware.
+
+#![cfg(feature = "tower")]
+
+mod common;
+
+use common::SPAN_ID;
+use common::TRACE_ID;
+use common::TRACEPARENT;
+use common::fail;
+use common::ping;
+use fastrace::collector::SpanId;
+use fastrace::collector::TraceId;
+use fastrace_poem::FastraceLayer;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use poem::Endpoint;
+use poem::EndpointExt;
+use poem::Route;
+use poem::http::StatusCode;
+use poem::middleware::TowerLayerCompatExt;
+use poem::test::TestClient;
+
+fn client() -> TestClient<impl Endpoint> {
+    let layer = FastraceLayer::new(FastraceMiddleware::new());
+    TestClient::new(
+        Route::new()
+            .at("/ping", ping)
+            .at("/fail", fail)
+            .to_response()
+            .with(layer.compat()),
+    )
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn layer_records_the_root_span() {
+    let cli = client();
+
+    let (resp, spans) = collect_spans(cli.get("/ping").header("traceparent", TRACEPARENT)).await;
+    resp.assert_text("pong").await;
+    let root = assert_span_named(&spans, "GET");
+    assert_eq!(root.trace_id, TraceId(TRACE_ID));
+    assert_eq!(root.parent_id, SpanId(SPAN_ID));
+    assert_property(root, "http.response.status_code", "200");
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn layer_records_the_status_of_errors() {
+    let cli = client();
+
+    let (resp, spans) = collect_spans(cli.get("/fail").header("traceparent", TRACEPARENT)).await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    let root = assert_span_named(&spans, "GET");
+    assert_property(root, "http.response.status_code", "500");
+    assert_property(root, "error", "true");
+}
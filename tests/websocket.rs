@@ -0,0 +1,60 @@
+//! The WebSocket messages recorded on the root span.
+
+#![cfg(feature = "websocket")]
+
+mod common;
+
+use common::TRACEPARENT;
+use fastrace_poem::CurrentSpan;
+use fastrace_poem::FastraceMiddleware;
+use fastrace_poem::TracedWebSocket;
+use fastrace_poem::test::assert_property;
+use fastrace_poem::test::assert_span_named;
+use fastrace_poem::test::collect_spans;
+use futures_util::StreamExt;
+use futures_util::stream;
+use poem::EndpointExt;
+use poem::Route;
+use poem::handler;
+use poem::test::TestClient;
+use poem::web::websocket::Message;
+
+/// Receives five messages from a stream standing in for an upgraded connection, which the test
+/// client cannot open.
+#[handler]
+async fn receive(span: CurrentSpan) -> &'static str {
+    let messages = stream::iter((0..5).map(|n| Ok(Message::Text(n.to_string()))));
+    let mut socket = TracedWebSocket::new(messages, span).with_max_events(2);
+    while socket.next().await.is_some() {}
+    "done"
+}
+
+#[tokio::test]
+#[cfg_attr(
+    feature = "disabled",
+    ignore = "no spans are recorded with the `disabled` feature"
+)]
+async fn messages_are_capped() {
+    let cli = TestClient::new(
+        Route::new()
+            .at("/receive", receive)
+            .with(FastraceMiddleware::new()),
+    );
+
+    let (_, spans) = collect_spans(cli.get("/receive").header("traceparent", TRACEPARENT)).await;
+    let root = assert_span_named(&spans, "GET");
+    let messages = root
+        .events
+        .iter()
+        .filter(|event| event.name == "websocket.message")
+        .collect::<Vec<_>>();
+    assert_eq!(messages.len(), 2);
+    assert!(
+        messages[0]
+            .properties
+            .iter()
+            .any(|(k, v)| k == "websocket.message.direction" && v == "received")
+    );
+    assert_property(root, "websocket.messages.received", "5");
+    assert_property(root, "websocket.messages.sent", "0");
+}